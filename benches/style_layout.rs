@@ -0,0 +1,165 @@
+use browser_in_rust::css;
+use browser_in_rust::font::FontCollection;
+use browser_in_rust::layout::{self, Dimensions};
+use browser_in_rust::media::Device;
+use browser_in_rust::{dom, html, style};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+/// Style and lay out `examples/perf-rainbow.html`
+fn perf_rainbow(c: &mut Criterion) {
+    let root = html::parse(include_str!("../examples/perf-rainbow.html").to_string());
+    let author = css::parse(include_str!("../examples/perf-rainbow.css").to_string());
+    let user_agent = style::user_agent_stylesheet();
+    let stylesheets = [
+        (style::Origin::UserAgent, &user_agent),
+        (style::Origin::Author, &author),
+    ];
+    let device = Device::default();
+    let mut viewport: Dimensions = Default::default();
+    viewport.content.width = device.width;
+    viewport.content.height = device.height;
+
+    print_sharing_stats("perf-rainbow", &root, &stylesheets, &device);
+    c.bench_function("style perf-rainbow", |b| {
+        b.iter(|| style::style_tree(&root, &stylesheets, &device))
+    });
+    let style_root = style::style_tree(&root, &stylesheets, &device);
+    let fonts = FontCollection::default();
+    c.bench_function("layout perf-rainbow", |b| {
+        b.iter(|| layout::layout_tree(&style_root, viewport, &fonts))
+    });
+}
+
+/// Style a generated document with thousands of elements against thousands of rules, most of
+/// which don't match any given element
+fn many_rules(c: &mut Criterion) {
+    let mut html = String::from("<div>");
+    for i in 0..2000 {
+        html += &format!(
+            r#"<p class="c{} item" id="e{}"><span>x</span></p>"#,
+            i % 1000,
+            i
+        );
+    }
+    html += "</div>";
+    let root = html::parse(html);
+
+    let mut source = String::new();
+    for i in 0..1000 {
+        source += &format!(".c{} {{ color: #{:06x}; }}\n", i, i * 97);
+        source += &format!("#e{} {{ margin-left: {}px; }}\n", i, i % 10);
+        source += &format!("span.c{} {{ font-size: 12px; }}\n", i);
+    }
+    source += ".item { padding-top: 1px; } p { display: block; }";
+    let author = css::parse(source);
+    let stylesheets = [(style::Origin::Author, &author)];
+    let device = Device::default();
+
+    print_sharing_stats("many rules", &root, &stylesheets, &device);
+    c.bench_function("style many rules", |b| {
+        b.iter(|| style::style_tree(&root, &stylesheets, &device))
+    });
+    c.bench_function("style many rules in parallel", |b| {
+        b.iter(|| style::style_tree_parallel(&root, &stylesheets, &device))
+    });
+}
+
+/// Style a long list and a table, whose repeated siblings can share their styles
+fn repeated_siblings(c: &mut Criterion) {
+    let mut html = String::from("<div><ul>");
+    for i in 0..2000 {
+        let class = if i % 2 == 0 { "even" } else { "odd" };
+        html += &format!(
+            r#"<li class="{}"><span class="label">{}</span></li>"#,
+            class, i
+        );
+    }
+    html += "</ul><table>";
+    for i in 0..500 {
+        html += &format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            i,
+            i + 1,
+            i + 2
+        );
+    }
+    html += "</table></div>";
+    let root = html::parse(html);
+    let user_agent = style::user_agent_stylesheet();
+    let author = css::parse(
+        ".even { background-color: #eeeeee; } .odd { color: #333333; } \
+         .label { padding-left: 2px; } td { border-left-style: solid; }"
+            .to_string(),
+    );
+    let stylesheets = [
+        (style::Origin::UserAgent, &user_agent),
+        (style::Origin::Author, &author),
+    ];
+    let device = Device::default();
+
+    print_sharing_stats("repeated siblings", &root, &stylesheets, &device);
+    c.bench_function("style repeated siblings", |b| {
+        b.iter(|| style::style_tree(&root, &stylesheets, &device))
+    });
+    c.bench_function("style repeated siblings in parallel", |b| {
+        b.iter(|| style::style_tree_parallel(&root, &stylesheets, &device))
+    });
+}
+
+/// Style a deep document against rules with descendant selectors whose rightmost selectors
+/// match many elements, so that most of the work is checking their ancestors
+fn descendant_selectors(c: &mut Criterion) {
+    fn nested(depth: usize, index: usize) -> String {
+        if depth == 0 {
+            return format!(r#"<p class="leaf"><span>{}</span></p>"#, index);
+        }
+        let children: String = (0..3).map(|i| nested(depth - 1, index * 3 + i)).collect();
+        format!(
+            r#"<div class="d{} n{}">{}</div>"#,
+            depth,
+            index % 5,
+            children
+        )
+    }
+    let root = html::parse(nested(6, 0));
+
+    let mut source = String::new();
+    for i in 0..300 {
+        source += &format!(".x{} .y{} span {{ color: #{:06x}; }}\n", i, i, i * 97);
+        source += &format!("div.q{} > div .leaf {{ margin-left: 1px; }}\n", i);
+        source += &format!(".d{} .n{} p {{ padding-top: 1px; }}\n", i % 7, i % 5);
+    }
+    let author = css::parse(source);
+    let stylesheets = [(style::Origin::Author, &author)];
+    let device = Device::default();
+
+    c.bench_function("style descendant selectors", |b| {
+        b.iter(|| style::style_tree(&root, &stylesheets, &device))
+    });
+}
+
+/// Print the hit rate of the style sharing cache
+fn print_sharing_stats(
+    name: &str,
+    root: &dom::Node,
+    stylesheets: &[(style::Origin, &css::Stylesheet)],
+    device: &Device,
+) {
+    let (_, stats) = style::style_tree_with_stats(root, stylesheets, device, false);
+    println!(
+        "{}: style sharing hit rate {:.1}% ({} hits, {} misses)",
+        name,
+        stats.hit_rate() * 100.0,
+        stats.hits,
+        stats.misses
+    );
+}
+
+criterion_group!(
+    benches,
+    perf_rainbow,
+    many_rules,
+    repeated_siblings,
+    descendant_selectors
+);
+criterion_main!(benches);
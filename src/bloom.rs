@@ -0,0 +1,132 @@
+//! A Bloom filter of the tag names, ids and classes of the ancestors of an element, so that
+//! selectors with combinators can be rejected without walking up the tree.
+//!
+//! ref: https://en.wikipedia.org/wiki/Bloom_filter
+
+use crate::css::{Selector, SimpleSelector};
+use crate::dom::ElementData;
+
+/// The number of bits of the filter. Each name sets two of them.
+const BITS: usize = 1024;
+
+/// The names of the ancestors of an element. It is small enough to copy for each element, so
+/// that sibling subtrees can be styled in parallel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AncestorFilter {
+    bits: [u64; BITS / 64],
+}
+
+impl Default for AncestorFilter {
+    fn default() -> AncestorFilter {
+        AncestorFilter {
+            bits: [0; BITS / 64],
+        }
+    }
+}
+
+impl AncestorFilter {
+    /// Add the tag name, id and classes of `elem`
+    pub fn insert_element(&mut self, elem: &ElementData) {
+        self.insert(tag_hash(&elem.tag_name));
+        if let Some(id) = elem.id() {
+            self.insert(id_hash(id));
+        }
+        for class in elem.classes() {
+            self.insert(class_hash(class));
+        }
+    }
+
+    pub fn insert(&mut self, hash: u32) {
+        for bit in bits(hash) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Might one of the ancestors have the name with this hash? There can be false positives,
+    /// but no false negatives.
+    pub fn might_contain(&self, hash: u32) -> bool {
+        bits(hash)
+            .iter()
+            .all(|&bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+/// The two bits of the filter that a hash sets
+fn bits(hash: u32) -> [usize; 2] {
+    let mask = BITS as u32 - 1;
+    [(hash & mask) as usize, ((hash >> 16) & mask) as usize]
+}
+
+/// FNV-1a, with a prefix byte so that tag names, ids and classes hash differently
+fn hash(prefix: u8, name: &str) -> u32 {
+    std::iter::once(prefix)
+        .chain(name.bytes())
+        .fold(0x811c_9dc5, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        })
+}
+
+pub fn tag_hash(tag_name: &str) -> u32 {
+    hash(b't', tag_name)
+}
+
+pub fn id_hash(id: &str) -> u32 {
+    hash(b'#', id)
+}
+
+pub fn class_hash(class: &str) -> u32 {
+    hash(b'.', class)
+}
+
+/// The hashes of the names that the ancestors of an element must have for `selector` to match
+/// it
+pub fn ancestor_hashes(selector: &Selector) -> Vec<u32> {
+    match *selector {
+        Selector::Simple(_) => Vec::new(),
+        Selector::Complex(ref ancestors, _) => ancestors
+            .iter()
+            .flat_map(|(simple, _)| simple_selector_hashes(simple))
+            .collect(),
+    }
+}
+
+fn simple_selector_hashes(simple: &SimpleSelector) -> impl Iterator<Item = u32> + '_ {
+    simple
+        .tag_name
+        .iter()
+        .map(|tag_name| tag_hash(tag_name))
+        .chain(simple.id.iter().map(|id| id_hash(id)))
+        .chain(simple.class.iter().map(|class| class_hash(class)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ancestor_hashes, AncestorFilter};
+    use crate::css;
+    use crate::dom::{AttrMap, ElementData};
+
+    #[test]
+    fn test_ancestor_filter() {
+        let mut attributes = AttrMap::new();
+        attributes.insert("id".to_string(), "main".to_string());
+        attributes.insert("class".to_string(), "a b".to_string());
+        let mut filter = AncestorFilter::default();
+        filter.insert_element(&ElementData {
+            tag_name: "div".to_string(),
+            attributes,
+        });
+
+        let hashes = |selector: &str| {
+            let stylesheet = css::parse(format!("{} {{}}", selector));
+            ancestor_hashes(&stylesheet.rules[0].selectors[0])
+        };
+        let might_match = |selector| hashes(selector).iter().all(|&h| filter.might_contain(h));
+        assert!(hashes("div").is_empty());
+        assert!(might_match("div span"));
+        assert!(might_match("div#main.b > .x"));
+        assert!(might_match("* p"));
+        assert!(!might_match("p span"));
+        assert!(!might_match(".main span"));
+        assert!(!might_match("div.c span"));
+    }
+}
@@ -0,0 +1,379 @@
+//! Math expressions: `calc()`, `min()`, `max()` and `clamp()`.
+//!
+//! ref: https://www.w3.org/TR/css-values-4/#math
+
+use crate::css::{LengthContext, Unit};
+
+/// A node of a math expression tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcNode {
+    Number(f32),
+    /// A length or percentage
+    Length(f32, Unit),
+    Sum(Vec<CalcNode>),
+    Product(Vec<CalcNode>),
+    /// `-x`, for subtraction
+    Negate(Box<CalcNode>),
+    /// `1 / x`, for division
+    Invert(Box<CalcNode>),
+    Min(Vec<CalcNode>),
+    Max(Vec<CalcNode>),
+    /// `clamp(min, value, max)`
+    Clamp(Box<CalcNode>, Box<CalcNode>, Box<CalcNode>),
+}
+
+/// The type of a math expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CalcType {
+    Number,
+    Length,
+    Percentage,
+    /// A mix of lengths and percentages, which can only be resolved during layout
+    LengthPercentage,
+}
+
+impl CalcType {
+    /// The type of the sum of two expressions, or `None` if they can't be added
+    fn add(self, other: CalcType) -> Option<CalcType> {
+        match (self, other) {
+            (a, b) if a == b => Some(a),
+            (CalcType::Number, _) | (_, CalcType::Number) => None,
+            _ => Some(CalcType::LengthPercentage),
+        }
+    }
+
+    /// The type of the product of two expressions, or `None` if they can't be multiplied
+    fn multiply(self, other: CalcType) -> Option<CalcType> {
+        match (self, other) {
+            (CalcType::Number, t) | (t, CalcType::Number) => Some(t),
+            _ => None,
+        }
+    }
+}
+
+impl CalcNode {
+    /// Check that the expression is well-typed and return its type. Lengths can't be added to
+    /// numbers, at most one factor of a product can be a length, and divisors must be numbers.
+    pub fn calc_type(&self) -> Result<CalcType, String> {
+        match self {
+            CalcNode::Number(_) => Ok(CalcType::Number),
+            CalcNode::Length(_, Unit::Percent) => Ok(CalcType::Percentage),
+            CalcNode::Length(..) => Ok(CalcType::Length),
+            CalcNode::Negate(node) => node.calc_type(),
+            CalcNode::Invert(node) => match node.calc_type()? {
+                CalcType::Number => Ok(CalcType::Number),
+                _ => Err("can only divide by a number".to_string()),
+            },
+            CalcNode::Sum(nodes) | CalcNode::Min(nodes) | CalcNode::Max(nodes) => combine_types(
+                nodes.iter(),
+                CalcType::add,
+                "can't add a number to a length",
+            ),
+            CalcNode::Product(nodes) => combine_types(
+                nodes.iter(),
+                CalcType::multiply,
+                "can't multiply two lengths",
+            ),
+            CalcNode::Clamp(min, value, max) => combine_types(
+                vec![&**min, &**value, &**max].into_iter(),
+                CalcType::add,
+                "can't clamp a length with numbers",
+            ),
+        }
+    }
+
+    /// Simplify the expression as far as possible without knowing the length context:
+    /// numbers are folded, lengths with the same unit are added, absolute lengths are
+    /// converted to px, and products are distributed over sums.
+    pub fn simplify(self) -> CalcNode {
+        match self {
+            CalcNode::Number(_) => self,
+            // Absolute lengths are converted to px, so that they can be combined
+            CalcNode::Length(value, unit) => match unit.absolute_size() {
+                Some(px) => CalcNode::Length(value * px, Unit::Px),
+                None => self,
+            },
+            CalcNode::Negate(node) => node.simplify().scale(-1.0),
+            CalcNode::Invert(node) => match node.simplify() {
+                CalcNode::Number(n) => CalcNode::Number(1.0 / n),
+                node => CalcNode::Invert(Box::new(node)),
+            },
+            CalcNode::Sum(nodes) => simplify_sum(nodes),
+            CalcNode::Product(nodes) => simplify_product(nodes),
+            CalcNode::Min(nodes) => simplify_comparison(nodes, f32::min, CalcNode::Min),
+            CalcNode::Max(nodes) => simplify_comparison(nodes, f32::max, CalcNode::Max),
+            CalcNode::Clamp(min, value, max) => {
+                let (min, value, max) = (min.simplify(), value.simplify(), max.simplify());
+                match (min.as_number(), value.as_number(), max.as_number()) {
+                    (Some((min, unit)), Some((value, u1)), Some((max, u2)))
+                        if unit == u1 && unit == u2 =>
+                    {
+                        from_number(value.min(max).max(min), unit)
+                    }
+                    _ => CalcNode::Clamp(Box::new(min), Box::new(value), Box::new(max)),
+                }
+            }
+        }
+    }
+
+    /// Evaluate the expression, with lengths in px
+    pub fn to_px(&self, ctx: &LengthContext) -> f32 {
+        censor(self.evaluate(ctx))
+    }
+
+    fn evaluate(&self, ctx: &LengthContext) -> f32 {
+        match self {
+            CalcNode::Number(n) => *n,
+            CalcNode::Length(value, unit) => value * unit.to_px(ctx),
+            CalcNode::Sum(nodes) => nodes.iter().map(|node| node.evaluate(ctx)).sum(),
+            CalcNode::Product(nodes) => nodes.iter().map(|node| node.evaluate(ctx)).product(),
+            CalcNode::Negate(node) => -node.evaluate(ctx),
+            CalcNode::Invert(node) => 1.0 / node.evaluate(ctx),
+            CalcNode::Min(nodes) => nodes
+                .iter()
+                .map(|node| node.evaluate(ctx))
+                .fold(f32::INFINITY, f32::min),
+            CalcNode::Max(nodes) => nodes
+                .iter()
+                .map(|node| node.evaluate(ctx))
+                .fold(f32::NEG_INFINITY, f32::max),
+            // If min is greater than max, min wins
+            CalcNode::Clamp(min, value, max) => value
+                .evaluate(ctx)
+                .min(max.evaluate(ctx))
+                .max(min.evaluate(ctx)),
+        }
+    }
+
+    /// Convert all lengths except percentages to px, and simplify the result
+    pub fn resolve_lengths(&self, ctx: &LengthContext) -> CalcNode {
+        self.map_lengths(&|value, unit| match unit {
+            Unit::Percent => CalcNode::Length(value, unit),
+            unit => CalcNode::Length(value * unit.to_px(ctx), Unit::Px),
+        })
+        .simplify()
+        .finite()
+    }
+
+    /// Censor the result of a simplified expression if it's a number or a length: NaN becomes 0,
+    /// and infinities the largest finite values, e.g. for a division by zero
+    ///
+    /// ref: https://www.w3.org/TR/css-values-4/#calc-ieee
+    pub fn finite(self) -> CalcNode {
+        match self {
+            CalcNode::Number(n) => CalcNode::Number(censor(n)),
+            CalcNode::Length(value, unit) => CalcNode::Length(censor(value), unit),
+            node => node,
+        }
+    }
+
+    /// Replace every length in the expression with `f(value, unit)`
+    fn map_lengths(&self, f: &dyn Fn(f32, Unit) -> CalcNode) -> CalcNode {
+        let map_all = |nodes: &[CalcNode]| nodes.iter().map(|node| node.map_lengths(f)).collect();
+        match self {
+            CalcNode::Number(n) => CalcNode::Number(*n),
+            CalcNode::Length(value, unit) => f(*value, *unit),
+            CalcNode::Sum(nodes) => CalcNode::Sum(map_all(nodes)),
+            CalcNode::Product(nodes) => CalcNode::Product(map_all(nodes)),
+            CalcNode::Negate(node) => CalcNode::Negate(Box::new(node.map_lengths(f))),
+            CalcNode::Invert(node) => CalcNode::Invert(Box::new(node.map_lengths(f))),
+            CalcNode::Min(nodes) => CalcNode::Min(map_all(nodes)),
+            CalcNode::Max(nodes) => CalcNode::Max(map_all(nodes)),
+            CalcNode::Clamp(min, value, max) => CalcNode::Clamp(
+                Box::new(min.map_lengths(f)),
+                Box::new(value.map_lengths(f)),
+                Box::new(max.map_lengths(f)),
+            ),
+        }
+    }
+
+    /// Does the expression refer to a percentage?
+    pub fn has_percentage(&self) -> bool {
+        match self {
+            CalcNode::Number(_) => false,
+            CalcNode::Length(_, unit) => *unit == Unit::Percent,
+            CalcNode::Negate(node) | CalcNode::Invert(node) => node.has_percentage(),
+            CalcNode::Sum(nodes)
+            | CalcNode::Product(nodes)
+            | CalcNode::Min(nodes)
+            | CalcNode::Max(nodes) => nodes.iter().any(CalcNode::has_percentage),
+            CalcNode::Clamp(min, value, max) => {
+                min.has_percentage() || value.has_percentage() || max.has_percentage()
+            }
+        }
+    }
+
+    /// Return a number or a length as a value and its unit (`None` for numbers)
+    fn as_number(&self) -> Option<(f32, Option<Unit>)> {
+        match *self {
+            CalcNode::Number(n) => Some((n, None)),
+            CalcNode::Length(value, unit) => Some((value, Some(unit))),
+            _ => None,
+        }
+    }
+
+    /// Multiply a simplified expression by `factor`
+    fn scale(self, factor: f32) -> CalcNode {
+        match self {
+            CalcNode::Number(n) => CalcNode::Number(n * factor),
+            CalcNode::Length(value, unit) => CalcNode::Length(value * factor, unit),
+            CalcNode::Sum(nodes) => {
+                CalcNode::Sum(nodes.into_iter().map(|node| node.scale(factor)).collect())
+            }
+            node if factor == 1.0 => node,
+            node => CalcNode::Product(vec![CalcNode::Number(factor), node]),
+        }
+    }
+}
+
+/// Combine the types of `nodes` with `combine`, failing with `error` if they're incompatible
+fn combine_types<'a>(
+    mut nodes: impl Iterator<Item = &'a CalcNode>,
+    combine: fn(CalcType, CalcType) -> Option<CalcType>,
+    error: &str,
+) -> Result<CalcType, String> {
+    let first = nodes
+        .next()
+        .ok_or_else(|| "empty expression".to_string())?
+        .calc_type()?;
+    nodes.try_fold(first, |acc, node| {
+        combine(acc, node.calc_type()?).ok_or_else(|| error.to_string())
+    })
+}
+
+fn censor(value: f32) -> f32 {
+    if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(f32::MIN, f32::MAX)
+    }
+}
+
+fn from_number(value: f32, unit: Option<Unit>) -> CalcNode {
+    match unit {
+        Some(unit) => CalcNode::Length(value, unit),
+        None => CalcNode::Number(value),
+    }
+}
+
+/// Add up the terms of a sum that have the same unit
+fn simplify_sum(nodes: Vec<CalcNode>) -> CalcNode {
+    let mut totals: Vec<(Option<Unit>, f32)> = Vec::new();
+    let mut others = Vec::new();
+    let terms = nodes.into_iter().flat_map(|node| match node.simplify() {
+        CalcNode::Sum(terms) => terms,
+        term => vec![term],
+    });
+    for term in terms {
+        let (value, unit) = match term.as_number() {
+            Some(number) => number,
+            None => {
+                others.push(term);
+                continue;
+            }
+        };
+        match totals.iter_mut().find(|(u, _)| *u == unit) {
+            Some((_, total)) => *total += value,
+            None => totals.push((unit, value)),
+        }
+    }
+
+    let mut terms: Vec<CalcNode> = totals
+        .into_iter()
+        .map(|(unit, value)| from_number(value, unit))
+        .chain(others)
+        .collect();
+    match terms.len() {
+        1 => terms.pop().unwrap(),
+        _ => CalcNode::Sum(terms),
+    }
+}
+
+/// Multiply the numeric factors of a product, and distribute them over the other factor
+fn simplify_product(nodes: Vec<CalcNode>) -> CalcNode {
+    let mut factor = 1.0;
+    let mut others = Vec::new();
+    let factors = nodes.into_iter().flat_map(|node| match node.simplify() {
+        CalcNode::Product(factors) => factors,
+        node => vec![node],
+    });
+    for node in factors {
+        match node {
+            CalcNode::Number(n) => factor *= n,
+            node => others.push(node),
+        }
+    }
+    match others.len() {
+        0 => CalcNode::Number(factor),
+        1 => others.pop().unwrap().scale(factor),
+        _ => {
+            others.insert(0, CalcNode::Number(factor));
+            CalcNode::Product(others)
+        }
+    }
+}
+
+/// Evaluate `min()` or `max()` if all arguments have the same unit
+fn simplify_comparison(
+    nodes: Vec<CalcNode>,
+    compare: fn(f32, f32) -> f32,
+    rebuild: fn(Vec<CalcNode>) -> CalcNode,
+) -> CalcNode {
+    let nodes: Vec<CalcNode> = nodes.into_iter().map(CalcNode::simplify).collect();
+    let values: Option<Vec<_>> = nodes.iter().map(CalcNode::as_number).collect();
+    match values {
+        Some(values) if values.windows(2).all(|pair| pair[0].1 == pair[1].1) => {
+            let unit = values[0].1;
+            let value = values
+                .into_iter()
+                .map(|(value, _)| value)
+                .reduce(compare)
+                .unwrap();
+            from_number(value, unit)
+        }
+        _ if nodes.len() == 1 => nodes.into_iter().next().unwrap(),
+        _ => rebuild(nodes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CalcNode;
+    use crate::css::{LengthContext, Unit};
+
+    #[test]
+    fn test_division_by_zero() {
+        // `calc(10px / 0)` and `calc(-1 / 0)` are infinite, and `calc(0 / 0)` is NaN
+        let divide = |numerator, divisor| {
+            CalcNode::Product(vec![
+                numerator,
+                CalcNode::Invert(Box::new(CalcNode::Number(divisor))),
+            ])
+        };
+        assert_eq!(
+            CalcNode::Length(f32::MAX, Unit::Px),
+            divide(CalcNode::Length(10.0, Unit::Px), 0.0)
+                .simplify()
+                .finite()
+        );
+        assert_eq!(
+            CalcNode::Number(f32::MIN),
+            divide(CalcNode::Number(-1.0), 0.0).simplify().finite()
+        );
+        assert_eq!(
+            CalcNode::Number(0.0),
+            divide(CalcNode::Number(0.0), 0.0).simplify().finite()
+        );
+
+        // The same when evaluated during layout
+        let ctx = LengthContext {
+            font_size: 16.0,
+            root_font_size: 16.0,
+            viewport_width: 800.0,
+            viewport_height: 600.0,
+            percentage_basis: 100.0,
+        };
+        let node = divide(CalcNode::Length(10.0, Unit::Percent), 0.0);
+        assert_eq!(f32::MAX, node.to_px(&ctx));
+    }
+}
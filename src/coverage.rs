@@ -0,0 +1,246 @@
+//! Reports of which CSS rules and selectors matched elements, to find dead CSS.
+//!
+//! `style::style_tree_with_coverage` counts the matches; this module summarizes them.
+
+use crate::css::{Rule, Selector};
+use crate::serialize::{Format, ToCss};
+use crate::style::Origin;
+
+/// How many elements each rule of the stylesheets matched
+#[derive(Debug, PartialEq)]
+pub struct Coverage<'a> {
+    /// Every rule, in the order of the stylesheets
+    pub rules: Vec<RuleCoverage<'a>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RuleCoverage<'a> {
+    pub rule: &'a Rule,
+    pub origin: Origin,
+    /// Do the rule's media queries match the device? Rules that don't apply match nothing.
+    pub applies: bool,
+    /// The number of elements that matched one of the selectors
+    pub matches: usize,
+    /// Each selector of the rule, and the number of elements it matched
+    pub selectors: Vec<(&'a Selector, usize)>,
+}
+
+/// The number of most matched rules in the text report
+const MOST_MATCHED: usize = 10;
+
+impl<'a> Coverage<'a> {
+    /// Leave out the rules of the user-agent stylesheet, which are there for every document
+    pub fn without_user_agent(mut self) -> Coverage<'a> {
+        self.rules.retain(|rule| rule.origin != Origin::UserAgent);
+        self
+    }
+
+    /// The rules that matched no elements
+    pub fn unused_rules(&self) -> impl Iterator<Item = &RuleCoverage<'a>> {
+        self.rules.iter().filter(|rule| rule.matches == 0)
+    }
+
+    /// The rules that matched elements, from most to fewest matches. Ties are in source order.
+    pub fn most_matched(&self) -> Vec<&RuleCoverage<'a>> {
+        let mut rules: Vec<_> = self.rules.iter().filter(|rule| rule.matches > 0).collect();
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.matches));
+        rules
+    }
+
+    /// A summary for people: the unused rules, the unused selectors of used rules, and the most
+    /// matched rules, with their locations
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        let unused: Vec<_> = self.unused_rules().collect();
+        text += &format!("Unused rules: {} of {}\n", unused.len(), self.rules.len());
+        for rule in unused {
+            text += &format!("  {}  {}", rule.rule.location, selector_list(rule.rule));
+            if !rule.applies {
+                text += "  (media queries don't match)";
+            }
+            text += "\n";
+        }
+
+        let unused_selectors: Vec<_> = self
+            .rules
+            .iter()
+            .filter(|rule| rule.matches > 0)
+            .flat_map(|rule| {
+                rule.selectors
+                    .iter()
+                    .filter(|&&(_, matches)| matches == 0)
+                    .map(move |(selector, _)| (rule, selector))
+            })
+            .collect();
+        text += &format!(
+            "Unused selectors of used rules: {}\n",
+            unused_selectors.len()
+        );
+        for (rule, selector) in unused_selectors {
+            text += &format!(
+                "  {}  {}  (in {})\n",
+                rule.rule.location,
+                selector.to_css(Format::Minified),
+                selector_list(rule.rule)
+            );
+        }
+
+        text += "Most matched rules:\n";
+        for rule in self.most_matched().into_iter().take(MOST_MATCHED) {
+            text += &format!(
+                "  {:>6}  {}  {}\n",
+                rule.matches,
+                rule.rule.location,
+                selector_list(rule.rule)
+            );
+        }
+        text
+    }
+
+    /// Every rule with its location and match counts, as JSON:
+    ///
+    /// ```json
+    /// {"rules": [{"file": "main.css", "line": 1, "column": 1, "origin": "author",
+    ///   "applies": true, "matches": 2, "selectors": [{"selector": "p", "matches": 2}]}]}
+    /// ```
+    ///
+    /// `file` is `null` for stylesheets that weren't loaded from a file.
+    pub fn to_json(&self) -> String {
+        let rules: Vec<String> = self
+            .rules
+            .iter()
+            .map(|rule| {
+                let location = &rule.rule.location;
+                let file = match location.file {
+                    Some(ref file) => json_string(&file.display().to_string()),
+                    None => "null".to_string(),
+                };
+                let selectors: Vec<String> = rule
+                    .selectors
+                    .iter()
+                    .map(|(selector, matches)| {
+                        format!(
+                            r#"{{"selector": {}, "matches": {}}}"#,
+                            json_string(&selector.to_css(Format::Minified)),
+                            matches
+                        )
+                    })
+                    .collect();
+                format!(
+                    concat!(
+                        r#"{{"file": {}, "line": {}, "column": {}, "origin": "{}", "#,
+                        r#""applies": {}, "matches": {}, "selectors": [{}]}}"#
+                    ),
+                    file,
+                    location.line,
+                    location.column,
+                    origin_name(rule.origin),
+                    rule.applies,
+                    rule.matches,
+                    selectors.join(", ")
+                )
+            })
+            .collect();
+        format!("{{\"rules\": [\n  {}\n]}}\n", rules.join(",\n  "))
+    }
+}
+
+/// The selectors of a rule, as written in a stylesheet
+fn selector_list(rule: &Rule) -> String {
+    let selectors: Vec<String> = rule
+        .selectors
+        .iter()
+        .map(|selector| selector.to_css(Format::Minified))
+        .collect();
+    selectors.join(", ")
+}
+
+fn origin_name(origin: Origin) -> &'static str {
+    match origin {
+        Origin::UserAgent => "user-agent",
+        Origin::User => "user",
+        Origin::Author => "author",
+    }
+}
+
+/// A JSON string literal
+///
+/// ref: https://www.rfc-editor.org/rfc/rfc8259#section-7
+fn json_string(s: &str) -> String {
+    let mut result = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            '\n' => result += "\\n",
+            c if (c as u32) < 0x20 => result += &format!("\\u{:04x}", c as u32),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::json_string;
+    use crate::css;
+    use crate::html;
+    use crate::media::Device;
+    use crate::style::{style_tree_with_coverage, Origin};
+
+    #[test]
+    fn test_coverage() {
+        let root = html::parse(String::from(
+            r#"<div><p class="a"></p><p class="a"></p><span></span></div>"#,
+        ));
+        let user_agent = css::parse(String::from("div { display: block; }"));
+        let author = css::parse(String::from(
+            "p { color: #111111; }\n\
+             .a, p { color: #222222; }\n\
+             .b, span { color: #333333; }\n\
+             em { color: #444444; }\n\
+             @media print { div { color: #555555; } }",
+        ));
+        let stylesheets = [(Origin::UserAgent, &user_agent), (Origin::Author, &author)];
+        let (_, coverage) = style_tree_with_coverage(&root, &stylesheets, &Device::default());
+
+        let matches: Vec<_> = coverage.rules.iter().map(|rule| rule.matches).collect();
+        assert_eq!(vec![1, 2, 2, 1, 0, 0], matches);
+        // An element that matches two selectors of a rule counts for both
+        assert_eq!(vec![2, 2], selector_counts(&coverage.rules[2].selectors));
+        assert_eq!(vec![0, 1], selector_counts(&coverage.rules[3].selectors));
+        assert!(!coverage.rules[5].applies);
+
+        let coverage = coverage.without_user_agent();
+        let unused: Vec<_> = coverage
+            .unused_rules()
+            .map(|rule| rule.rule.location.line)
+            .collect();
+        assert_eq!(vec![4, 5], unused);
+        let most_matched: Vec<_> = coverage
+            .most_matched()
+            .iter()
+            .map(|rule| rule.rule.location.line)
+            .collect();
+        assert_eq!(vec![1, 2, 3], most_matched);
+
+        let text = coverage.to_text();
+        assert!(text.contains("Unused rules: 2 of 5\n  4:1  em\n  5:16  div"));
+        assert!(text.contains("Unused selectors of used rules: 1\n  3:1  .b  (in .b, span)"));
+        let json = coverage.to_json();
+        assert!(json.contains(
+            r#"{"file": null, "line": 3, "column": 1, "origin": "author", "applies": true, "#
+        ));
+        assert!(json.contains(r#"[{"selector": ".b", "matches": 0}, "#));
+    }
+
+    fn selector_counts(selectors: &[(&css::Selector, usize)]) -> Vec<usize> {
+        selectors.iter().map(|&(_, count)| count).collect()
+    }
+
+    #[test]
+    fn test_json_string() {
+        assert_eq!(r#""a\"b\\c\n\u0009""#, json_string("a\"b\\c\n\t"));
+    }
+}
@@ -1,17 +1,74 @@
+use crate::calc::{CalcNode, CalcType};
+use crate::color;
+use crate::media::{Comparison, MediaCondition, MediaFeature, MediaQuery, MediaQueryList};
+use crate::properties;
+use crate::serialize::{Format, ToCss};
+use std::fmt;
+use std::path::Path;
+use std::sync::Arc;
+
 #[derive(Debug, PartialEq)]
 pub struct Stylesheet {
     pub rules: Vec<Rule>,
+    /// The `@import` rules of the stylesheet. `import::load_stylesheet` resolves them and
+    /// leaves this empty; `parse` leaves them unresolved.
+    pub imports: Vec<Import>,
+}
+
+/// An `@import` rule, e.g. `@import url("print.css") print;`
+#[derive(Debug, PartialEq, Clone)]
+pub struct Import {
+    pub url: String,
+    /// The imported rules only apply if this matches
+    pub media: MediaQueryList,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Rule {
     pub selectors: Vec<Selector>,
     pub declarations: Vec<Declaration>,
+    /// The media query lists of the `@media` blocks the rule is nested in. The rule only
+    /// applies if all of them match.
+    pub media: Vec<MediaQueryList>,
+    /// Where the rule's selectors start
+    pub location: SourceLocation,
+}
+
+/// A position in the source of a stylesheet, for diagnostics
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SourceLocation {
+    /// The file the stylesheet was loaded from, if any
+    pub file: Option<Arc<Path>>,
+    /// The 1-based line number
+    pub line: usize,
+    /// The 1-based column number, in characters
+    pub column: usize,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref file) = self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        write!(f, "{}:{}", self.line, self.column)
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Selector {
     Simple(SimpleSelector),
+    /// Simple selectors joined by combinators, e.g. `ul > li .name`. Each simple selector on the
+    /// left is followed by the combinator that separates it from the next one; the last one
+    /// matches the element itself.
+    Complex(Vec<(SimpleSelector, Combinator)>, SimpleSelector),
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Combinator {
+    /// Whitespace: the left selector matches an ancestor
+    Descendant,
+    /// `>`: the left selector matches the parent
+    Child,
 }
 
 #[derive(Debug, PartialEq)]
@@ -25,23 +82,82 @@ pub struct SimpleSelector {
 pub struct Declaration {
     pub name: String,
     pub value: Value,
+    /// Was the declaration marked `!important`?
+    pub important: bool,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     Keyword(String),
+    /// A quoted string, without its quotes, e.g. `"Times New Roman"`
+    String(String),
+    /// A `url()`, e.g. `url(a.png)` or `url("a.png")`
+    Url(String),
     Length(f32, Unit),
+    Number(f32),
     ColorValue(Color),
+    /// A whitespace-separated list of component values, e.g. `1px solid #000000`
+    List(Vec<Value>),
+    /// A delimiter between component values, e.g. the `/` in `12px/1.5`
+    Delim(char),
+    /// A math expression that can't be simplified before layout, e.g. `calc(100% - 20px)`
+    Calc(Box<CalcNode>),
+    /// The value of a custom property, or a value that contains `var()` and can only be parsed
+    /// after substitution
+    Unparsed(UnparsedValue),
     // insert more values here
 }
 
+/// A value that is kept as source text until `var()` functions are substituted
 #[derive(Debug, PartialEq, Clone)]
+pub struct UnparsedValue {
+    /// The source text, with surrounding whitespace removed
+    pub css: String,
+    /// For a longhand set by a shorthand that contains `var()`, the name of the shorthand
+    pub shorthand: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Unit {
+    // Absolute lengths
     Px,
+    Pt,
+    Pc,
+    Cm,
+    Mm,
+    In,
+    Q,
+    // Font-relative lengths
+    Em,
+    Rem,
+    Ex,
+    Ch,
+    // Viewport-percentage lengths
+    Vw,
+    Vh,
+    Vmin,
+    Vmax,
+    Percent,
     //insert more units here
 }
 
-#[derive(Debug, Clone, PartialEq,Copy)]
+/// The sizes that relative lengths are resolved against
+#[derive(Debug, Clone, Copy)]
+pub struct LengthContext {
+    /// The computed `font-size` of the element, for `em`, `ex` and `ch`
+    pub font_size: f32,
+    /// The computed `font-size` of the root element, for `rem`
+    pub root_font_size: f32,
+    pub viewport_width: f32,
+    pub viewport_height: f32,
+    /// The length that `100%` refers to, usually the width of the containing block
+    pub percentage_basis: f32,
+}
+
+/// The initial value of `font-size` (`medium`), in px
+pub const DEFAULT_FONT_SIZE: f32 = 16.0;
+
+#[derive(Debug, Clone, PartialEq, Copy)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -54,67 +170,578 @@ pub type Specificity = (usize, usize, usize);
 impl Selector {
     pub fn specificity(&self) -> Specificity {
         // ref: http://www.w3.org/TR/selectors/#specificity
-        let Selector::Simple(ref simple) = *self;
-        let a = simple.id.iter().count();
-        let b = simple.class.len();
-        let c = simple.tag_name.iter().count();
-        (a, b, c)
+        let simple_specificity = |simple: &SimpleSelector| {
+            let a = simple.id.iter().count();
+            let b = simple.class.len();
+            let c = simple.tag_name.iter().count();
+            (a, b, c)
+        };
+        match *self {
+            Selector::Simple(ref simple) => simple_specificity(simple),
+            Selector::Complex(ref ancestors, ref subject) => ancestors
+                .iter()
+                .map(|(simple, _)| simple_specificity(simple))
+                .fold(simple_specificity(subject), |(a, b, c), (x, y, z)| {
+                    (a + x, b + y, c + z)
+                }),
+        }
+    }
+
+    /// The rightmost simple selector, which matches the element itself
+    pub fn subject(&self) -> &SimpleSelector {
+        match *self {
+            Selector::Simple(ref simple) | Selector::Complex(_, ref simple) => simple,
+        }
+    }
+}
+
+impl Unit {
+    /// Return the size of one unit in px
+    pub fn to_px(self, ctx: &LengthContext) -> f32 {
+        if let Some(px) = self.absolute_size() {
+            return px;
+        }
+        match self {
+            Unit::Em => ctx.font_size,
+            Unit::Rem => ctx.root_font_size,
+            // Without font metrics, assume the x-height and the advance of "0" are half an em
+            Unit::Ex | Unit::Ch => ctx.font_size / 2.0,
+            Unit::Vw => ctx.viewport_width / 100.0,
+            Unit::Vh => ctx.viewport_height / 100.0,
+            Unit::Vmin => ctx.viewport_width.min(ctx.viewport_height) / 100.0,
+            Unit::Vmax => ctx.viewport_width.max(ctx.viewport_height) / 100.0,
+            Unit::Percent => ctx.percentage_basis / 100.0,
+            _ => unreachable!("absolute units are handled above"),
+        }
+    }
+
+    /// Return the size of one unit in px if it is an absolute unit, otherwise `None`
+    pub fn absolute_size(self) -> Option<f32> {
+        match self {
+            Unit::Px => Some(1.0),
+            Unit::Pt => Some(96.0 / 72.0),
+            Unit::Pc => Some(16.0),
+            Unit::In => Some(96.0),
+            Unit::Cm => Some(96.0 / 2.54),
+            Unit::Mm => Some(96.0 / 25.4),
+            Unit::Q => Some(96.0 / 101.6),
+            _ => None,
+        }
     }
 }
 
 impl Value {
     /// Return the size of a length in px, or zero for no-lengths
-    pub fn to_px(&self) -> f32 {
+    pub fn to_px(&self, ctx: &LengthContext) -> f32 {
         match *self {
-            Value::Length(f, Unit::Px) => f,
+            Value::Length(f, unit) => f * unit.to_px(ctx),
+            Value::Calc(ref node) => node.to_px(ctx),
             _ => 0.0,
         }
     }
+
+    /// Return the component values of a list, or the value itself if it is a single component
+    pub fn components(&self) -> &[Value] {
+        match self {
+            Value::List(values) => values,
+            value => std::slice::from_ref(value),
+        }
+    }
 }
+
 /// Parse a whole CSS stylesheet
 pub fn parse(source: String) -> Stylesheet {
-    let mut parser = Parser {
-        pos: 0,
-        input: source,
-    };
+    parse_stylesheet(Parser::new(source))
+}
+
+/// Like `parse`, but record `file` in the locations of the rules
+pub fn parse_file(source: String, file: &Path) -> Stylesheet {
+    let mut parser = Parser::new(source);
+    parser.file = Some(Arc::from(file));
+    parse_stylesheet(parser)
+}
+
+fn parse_stylesheet(mut parser: Parser) -> Stylesheet {
     Stylesheet {
         rules: parser.parse_rules(),
+        imports: parser.imports,
+    }
+}
+
+/// Parse a comma-separated list of selectors, e.g. `ul > li, .name`
+pub fn parse_selector_list(source: String) -> Vec<Selector> {
+    // Parse it as the prelude of a rule, which ends with `{`
+    let mut parser = Parser::new(source.trim().to_string() + "{");
+    parser.parse_selectors()
+}
+
+/// Parse a list of declarations without the surrounding braces, e.g. `margin: 0; color: red`.
+/// The last `;` is optional.
+pub fn parse_declaration_list(source: String) -> Vec<Declaration> {
+    let mut parser = Parser::new(source);
+    let mut declarations = Vec::new();
+    loop {
+        parser.consume_whitespace();
+        if parser.eof() {
+            break;
+        }
+        if let Some(declaration) = parser.parse_declaration() {
+            declarations.extend(expand_shorthand(declaration).into_iter().filter(is_valid));
+        }
     }
+    declarations
 }
 
 struct Parser {
     pos: usize,
     input: String,
+    imports: Vec<Import>,
+    /// Is an `@import` still allowed here, i.e. have no other rules come before it?
+    imports_allowed: bool,
+    file: Option<Arc<Path>>,
+    /// A position whose line and column are known, so that locations are found without
+    /// rescanning the input from the start: `(pos, line, column)`
+    last_location: (usize, usize, usize),
 }
 
 impl Parser {
+    fn new(input: String) -> Parser {
+        Parser {
+            pos: 0,
+            input,
+            imports: Vec::new(),
+            imports_allowed: true,
+            file: None,
+            last_location: (0, 1, 1),
+        }
+    }
+
+    /// The location of the current position. Locations are requested in increasing order.
+    fn location(&mut self) -> SourceLocation {
+        let (start, mut line, mut column) = self.last_location;
+        for c in self.input[start..self.pos].chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        self.last_location = (self.pos, line, column);
+        SourceLocation {
+            file: self.file.clone(),
+            line,
+            column,
+        }
+    }
+
     /// Parse a list of rule sets, separated by optional whitespace
     fn parse_rules(&mut self) -> Vec<Rule> {
         let mut rules = Vec::new();
+        self.parse_rule_list(&[], &mut rules);
+        rules
+    }
+
+    /// Parse rule sets and at-rules up to the end of the input or a closing `}`, and append
+    /// them to `rules`. Rules inside `@media` blocks are flattened into the list.
+    fn parse_rule_list(&mut self, media: &[MediaQueryList], rules: &mut Vec<Rule>) {
         loop {
             self.consume_whitespace();
-            if self.eof() {
+            if self.eof() || self.next_char() == '}' {
                 break;
             }
-            rules.push(self.parse_rule());
+            if self.next_char() == '@' {
+                self.parse_at_rule(media, rules);
+            } else {
+                self.imports_allowed = false;
+                rules.push(self.parse_rule(media));
+            }
         }
-        rules
     }
 
     /// Parse a rule set: `<selectors> { <declarations> }`
-    fn parse_rule(&mut self) -> Rule {
+    fn parse_rule(&mut self, media: &[MediaQueryList]) -> Rule {
+        let location = self.location();
         Rule {
             selectors: self.parse_selectors(),
             declarations: self.parse_declarations(),
+            media: media.to_vec(),
+            location,
+        }
+    }
+
+    /// Parse an at-rule. Unsupported at-rules are skipped.
+    fn parse_at_rule(&mut self, media: &[MediaQueryList], rules: &mut Vec<Rule>) {
+        assert_eq!(self.consume_char(), '@');
+        let name = self.parse_identifier().to_ascii_lowercase();
+        // `@import` is only valid before all other rules, except `@charset`
+        if !matches!(&*name, "import" | "charset") {
+            self.imports_allowed = false;
+        }
+        match &*name {
+            "media" => {
+                let mut media = media.to_vec();
+                media.push(self.parse_media_query_list());
+                if self.eof() || self.consume_char() != '{' {
+                    eprintln!("Warning: ignoring @media rule without a block");
+                    return;
+                }
+                self.parse_rule_list(&media, rules);
+                // The end of the input closes the block
+                if !self.eof() {
+                    self.consume_char();
+                }
+            }
+            "import" if self.imports_allowed => {
+                let start = self.pos;
+                match self.parse_import() {
+                    Ok(import) => self.imports.push(import),
+                    Err(err) => {
+                        eprintln!(
+                            "Warning: ignoring invalid @import{} ({})",
+                            &self.input[start..self.pos],
+                            err
+                        );
+                        self.pos = start;
+                        self.skip_at_rule();
+                    }
+                }
+            }
+            _ => self.skip_at_rule(),
+        }
+    }
+
+    /// Parse the rest of an `@import` rule: `<url> <media-query-list>;`
+    fn parse_import(&mut self) -> Result<Import, String> {
+        self.consume_whitespace();
+        let url = self.parse_url()?;
+        let media = self.parse_media_query_list();
+        match self.peek_char(0) {
+            Some(';') => {
+                self.consume_char();
+            }
+            // The end of the input closes the rule
+            None => {}
+            Some(c) => return Err(format!("unexpected character {}", c)),
+        }
+        Ok(Import { url, media })
+    }
+
+    /// Parse a URL written as a string or as `url(...)`, with or without quotes
+    fn parse_url(&mut self) -> Result<String, String> {
+        if matches!(self.peek_char(0), Some('"' | '\'')) {
+            return Ok(self.parse_string());
+        }
+        let function = self.parse_identifier();
+        if !function.eq_ignore_ascii_case("url") || self.peek_char(0) != Some('(') {
+            return Err("expected a URL".to_string());
+        }
+        self.consume_char();
+        self.parse_url_arguments()
+    }
+
+    /// Parse the URL inside `url(...)`, after the `(`, and the closing `)`
+    fn parse_url_arguments(&mut self) -> Result<String, String> {
+        self.consume_whitespace();
+        let url = if matches!(self.peek_char(0), Some('"' | '\'')) {
+            self.parse_string()
+        } else {
+            self.consume_while(|c| c != ')' && !c.is_whitespace())
+        };
+        self.consume_whitespace();
+        self.expect_char(')')?;
+        Ok(url)
+    }
+
+    /// Parse a quoted string, e.g. `"foo"` or `'foo'`. A backslash escapes the next character,
+    /// e.g. `"say \"hi\""`.
+    fn parse_string(&mut self) -> String {
+        let open_quote = self.consume_char();
+        let mut value = String::new();
+        // The end of the input closes the string
+        while !self.eof() {
+            match self.consume_char() {
+                c if c == open_quote => break,
+                '\\' if !self.eof() => value.push(self.consume_char()),
+                c => value.push(c),
+            }
+        }
+        value
+    }
+
+    /// Skip the rest of an at-rule: everything up to a `;` or the end of a `{ ... }` block
+    fn skip_at_rule(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.consume_char() {
+                ';' if depth == 0 => break,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Methods for parsing media queries
+
+    /// Parse a comma-separated media query list, up to a `{` or `;`. A query that fails to
+    /// parse becomes `not all`, so it never matches but the rest of the list still applies.
+    ///
+    /// ref: https://www.w3.org/TR/mediaqueries-4/#error-handling
+    fn parse_media_query_list(&mut self) -> MediaQueryList {
+        let mut queries = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() || matches!(self.next_char(), '{' | ';') {
+                break;
+            }
+            let start = self.pos;
+            let query = self.parse_media_query().and_then(|query| {
+                self.consume_whitespace();
+                match self.peek_char(0) {
+                    None | Some(',' | '{' | ';') => Ok(query),
+                    Some(c) => Err(format!("unexpected character {}", c)),
+                }
+            });
+            queries.push(query.unwrap_or_else(|err| {
+                self.pos = start;
+                self.skip_media_query();
+                eprintln!(
+                    "Warning: ignoring invalid media query {} ({})",
+                    self.input[start..self.pos].trim(),
+                    err
+                );
+                MediaQuery {
+                    negated: true,
+                    media_type: Some("all".to_string()),
+                    condition: None,
+                }
+            }));
+            if !self.eof() && self.next_char() == ',' {
+                self.consume_char();
+            }
+        }
+        MediaQueryList(queries)
+    }
+
+    /// Skip the rest of a media query: everything up to a `,` outside of parentheses, or the
+    /// `{` or `;` that ends the list
+    fn skip_media_query(&mut self) {
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char() {
+                ',' if depth == 0 => break,
+                '{' | ';' => break,
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                _ => {}
+            }
+            self.consume_char();
+        }
+    }
+
+    /// Parse one media query: `[not | only]? <media-type> [and <condition>]?` or `<condition>`
+    fn parse_media_query(&mut self) -> Result<MediaQuery, String> {
+        if self.next_char() == '(' || self.starts_with_keyword("not") && self.followed_by_paren() {
+            return Ok(MediaQuery {
+                negated: false,
+                media_type: None,
+                condition: Some(self.parse_media_condition(true)?),
+            });
+        }
+        let negated = self.consume_keyword("not");
+        if !negated {
+            self.consume_keyword("only");
+        }
+        self.consume_whitespace();
+        let media_type = self.parse_identifier().to_ascii_lowercase();
+        if media_type.is_empty() {
+            return Err("expected a media type".to_string());
+        }
+        self.consume_whitespace();
+        let condition = if self.consume_keyword("and") {
+            // `or` isn't allowed at the top level after a media type
+            Some(self.parse_media_condition(false)?)
+        } else {
+            None
+        };
+        Ok(MediaQuery {
+            negated,
+            media_type: Some(media_type),
+            condition,
+        })
+    }
+
+    /// Is the keyword at the current position followed by a `(`, as in `not (color)`?
+    fn followed_by_paren(&self) -> bool {
+        let rest = self.input[self.pos..].trim_start_matches(valid_identifier_char);
+        rest.trim_start().starts_with('(')
+    }
+
+    /// Parse a media condition: `not <in-parens>`, or `<in-parens>` joined by `and` or `or`
+    fn parse_media_condition(&mut self, allow_or: bool) -> Result<MediaCondition, String> {
+        self.consume_whitespace();
+        if self.consume_keyword("not") {
+            return Ok(MediaCondition::Not(Box::new(self.parse_media_in_parens()?)));
+        }
+        let mut conditions = vec![self.parse_media_in_parens()?];
+        let mut is_and = None;
+        loop {
+            self.consume_whitespace();
+            let and = if self.consume_keyword("and") {
+                true
+            } else if allow_or && self.consume_keyword("or") {
+                false
+            } else {
+                break;
+            };
+            if is_and.is_some_and(|is_and| is_and != and) {
+                return Err("`and` and `or` can't be mixed without parentheses".to_string());
+            }
+            is_and = Some(and);
+            conditions.push(self.parse_media_in_parens()?);
+        }
+        Ok(match is_and {
+            None => conditions.pop().unwrap(),
+            Some(true) => MediaCondition::And(conditions),
+            Some(false) => MediaCondition::Or(conditions),
+        })
+    }
+
+    /// Parse a parenthesized media condition or media feature
+    fn parse_media_in_parens(&mut self) -> Result<MediaCondition, String> {
+        self.consume_whitespace();
+        self.expect_char('(')?;
+        self.consume_whitespace();
+        let condition = if self.peek_char(0) == Some('(') || self.starts_with_keyword("not") {
+            self.parse_media_condition(true)?
+        } else {
+            self.parse_media_feature()?
+        };
+        self.consume_whitespace();
+        self.expect_char(')')?;
+        Ok(condition)
+    }
+
+    /// Consume `c`, or return an error if the input doesn't continue with it
+    fn expect_char(&mut self, c: char) -> Result<(), String> {
+        match self.peek_char(0) {
+            Some(next) if next == c => {
+                self.consume_char();
+                Ok(())
+            }
+            Some(next) => Err(format!("expected {} but found {}", c, next)),
+            None => Err(format!("expected {} but found the end of the input", c)),
+        }
+    }
+
+    /// Parse the inside of a media feature: `name`, `name: value`, `name < value`,
+    /// `value < name` or `value < name < value`
+    fn parse_media_feature(&mut self) -> Result<MediaCondition, String> {
+        if self.starts_number() {
+            let left = self.parse_media_value()?;
+            let first = self.parse_comparison()?.flip();
+            self.consume_whitespace();
+            let name = self.parse_media_feature_name()?;
+            self.consume_whitespace();
+            let range = MediaCondition::Feature(MediaFeature::Range(name.clone(), first, left));
+            if !matches!(self.peek_char(0), Some('<' | '>')) {
+                return Ok(range);
+            }
+            let second = self.parse_comparison()?;
+            let right = self.parse_media_value()?;
+            let second = MediaCondition::Feature(MediaFeature::Range(name, second, right));
+            return Ok(MediaCondition::And(vec![range, second]));
+        }
+
+        let name = self.parse_media_feature_name()?;
+        self.consume_whitespace();
+        let feature = match self.peek_char(0) {
+            Some(':') => {
+                self.consume_char();
+                let value = self.parse_media_value()?;
+                if let Some(name) = name.strip_prefix("min-") {
+                    MediaFeature::Range(name.to_string(), Comparison::GreaterThanOrEqual, value)
+                } else if let Some(name) = name.strip_prefix("max-") {
+                    MediaFeature::Range(name.to_string(), Comparison::LessThanOrEqual, value)
+                } else {
+                    MediaFeature::Plain(name, value)
+                }
+            }
+            Some('<' | '>' | '=') => {
+                let comparison = self.parse_comparison()?;
+                MediaFeature::Range(name, comparison, self.parse_media_value()?)
+            }
+            _ => MediaFeature::Boolean(name),
+        };
+        Ok(MediaCondition::Feature(feature))
+    }
+
+    /// Parse the lowercase name of a media feature
+    fn parse_media_feature_name(&mut self) -> Result<String, String> {
+        match self.parse_identifier().to_ascii_lowercase() {
+            name if name.is_empty() => Err("expected a media feature".to_string()),
+            name => Ok(name),
+        }
+    }
+
+    /// Parse a media feature value: a number, length, keyword or ratio such as `16/9`
+    fn parse_media_value(&mut self) -> Result<Value, String> {
+        self.consume_whitespace();
+        if self.eof() {
+            return Err("expected a media feature value".to_string());
+        }
+        let value = self.parse_component_value()?;
+        self.consume_whitespace();
+        if self.peek_char(0) != Some('/') {
+            return Ok(value);
+        }
+        let delim = Value::Delim(self.consume_char());
+        self.consume_whitespace();
+        if self.eof() {
+            return Err("expected a media feature value".to_string());
+        }
+        Ok(Value::List(vec![
+            value,
+            delim,
+            self.parse_component_value()?,
+        ]))
+    }
+
+    /// Parse `=`, `<`, `<=`, `>` or `>=`
+    fn parse_comparison(&mut self) -> Result<Comparison, String> {
+        self.consume_whitespace();
+        let comparison = match self.peek_char(0) {
+            Some('=') => {
+                self.consume_char();
+                return Ok(Comparison::Equal);
+            }
+            Some('<') => Comparison::LessThan,
+            Some('>') => Comparison::GreaterThan,
+            Some(c) => return Err(format!("unexpected character {} in media feature", c)),
+            None => return Err("expected a comparison".to_string()),
+        };
+        self.consume_char();
+        if self.peek_char(0) != Some('=') {
+            return Ok(comparison);
         }
+        self.consume_char();
+        Ok(match comparison {
+            Comparison::LessThan => Comparison::LessThanOrEqual,
+            _ => Comparison::GreaterThanOrEqual,
+        })
     }
 
     /// Parse a commma-separated list of  selectors
     fn parse_selectors(&mut self) -> Vec<Selector> {
         let mut selectors = Vec::new();
         loop {
-            selectors.push(Selector::Simple(self.parse_simple_selector()));
-            self.consume_whitespace();
+            selectors.push(self.parse_selector());
             match self.next_char() {
                 ',' => {
                     self.consume_char();
@@ -126,10 +753,37 @@ impl Parser {
         }
 
         // Return selectors with highest specificity first, for use in matching
-        selectors.sort_by(|a, b| b.specificity().cmp(&a.specificity()));
+        selectors.sort_by_key(|s| std::cmp::Reverse(s.specificity()));
         selectors
     }
 
+    /// Parse one selector, e.g.: `ul > li .name`, and the whitespace after it
+    fn parse_selector(&mut self) -> Selector {
+        let mut ancestors = Vec::new();
+        let mut subject = self.parse_simple_selector();
+        loop {
+            self.consume_whitespace();
+            let combinator = match self.next_char() {
+                ',' | '{' => break,
+                '>' => {
+                    self.consume_char();
+                    self.consume_whitespace();
+                    Combinator::Child
+                }
+                '#' | '.' | '*' => Combinator::Descendant,
+                c if valid_identifier_char(c) => Combinator::Descendant,
+                c => panic!("Unexpected character {} in selector list", c),
+            };
+            ancestors.push((subject, combinator));
+            subject = self.parse_simple_selector();
+        }
+        if ancestors.is_empty() {
+            Selector::Simple(subject)
+        } else {
+            Selector::Complex(ancestors, subject)
+        }
+    }
+
     /// Parse one simple selector, e.g.: `type#id.class1.class2.class3`
     fn parse_simple_selector(&mut self) -> SimpleSelector {
         let mut selector = SimpleSelector {
@@ -147,91 +801,465 @@ impl Parser {
                     self.consume_char();
                     selector.class.push(self.parse_identifier());
                 }
-                '*' => {
-                    //universal selector
+                '*' => {
+                    //universal selector
+                    self.consume_char();
+                }
+                c if valid_identifier_char(c) => {
+                    selector.tag_name = Some(self.parse_identifier());
+                }
+                _ => break,
+            }
+        }
+        selector
+    }
+    /// Parse a list of declarations enclosed in `{ ... }`
+    fn parse_declarations(&mut self) -> Vec<Declaration> {
+        assert_eq!(self.consume_char(), '{'); //expect '{' when it is called
+        let mut declarations = Vec::new();
+        loop {
+            self.consume_whitespace();
+            // The end of the input closes the block, e.g. after an unclosed `(`
+            if self.eof() {
+                break;
+            }
+            if self.next_char() == '}' {
+                self.consume_char();
+                break;
+            }
+            if let Some(declaration) = self.parse_declaration() {
+                declarations.extend(expand_shorthand(declaration).into_iter().filter(is_valid));
+            }
+        }
+        declarations
+    }
+    /// Parse one `<property>: <value>;` declaration. The `;` is optional at the end of a block.
+    /// Return `None` with a warning if the value can't be parsed.
+    ///
+    /// Custom properties (`--*`) and values that contain `var()` are kept unparsed.
+    fn parse_declaration(&mut self) -> Option<Declaration> {
+        let property_name = self.parse_identifier();
+        self.consume_whitespace();
+        assert_eq!(self.consume_char(), ':');
+        self.consume_whitespace();
+        let start = self.pos;
+        let css = self.parse_raw_value();
+        let end = self.pos;
+        let value = if property_name.starts_with("--") || contains_var(&css) {
+            Ok(Value::Unparsed(UnparsedValue {
+                css: css.clone(),
+                shorthand: None,
+            }))
+        } else {
+            self.pos = start;
+            self.parse_value()
+        };
+        let declaration = value.and_then(|value| {
+            let important = self.parse_important()?;
+            self.consume_whitespace();
+            match self.peek_char(0) {
+                None | Some(';') | Some('}') => Ok(Declaration {
+                    name: property_name.clone(),
+                    value,
+                    important,
+                }),
+                Some(c) => Err(format!("unexpected character {}", c)),
+            }
+        });
+        let declaration = match declaration {
+            Ok(declaration) => Some(declaration),
+            Err(err) => {
+                eprintln!(
+                    "Warning: ignoring invalid value for {}: {} ({})",
+                    property_name, css, err
+                );
+                self.pos = end;
+                self.skip_declaration();
+                None
+            }
+        };
+        if !self.eof() && self.next_char() == ';' {
+            self.consume_char();
+        }
+        declaration
+    }
+
+    /// Skip to the `;` or `}` that ends a declaration, including any `!` annotations
+    fn skip_declaration(&mut self) {
+        self.parse_raw_value();
+        while !self.eof() && self.next_char() == '!' {
+            self.consume_char();
+            self.parse_raw_value();
+        }
+    }
+
+    /// Parse an optional `!important` annotation at the end of a declaration
+    fn parse_important(&mut self) -> Result<bool, String> {
+        self.consume_whitespace();
+        if self.eof() || self.next_char() != '!' {
+            return Ok(false);
+        }
+        self.consume_char();
+        self.consume_whitespace();
+        let keyword = self.parse_identifier();
+        if !keyword.eq_ignore_ascii_case("important") {
+            return Err(format!("unexpected !{}", keyword));
+        }
+        Ok(true)
+    }
+
+    // Methods for parsing values
+
+    /// Return the source text of a value, up to the `;`, `}` or `!` that ends the declaration
+    /// outside of any blocks or strings
+    fn parse_raw_value(&mut self) -> String {
+        let start = self.pos;
+        let mut depth = 0;
+        while !self.eof() {
+            match self.next_char() {
+                ';' | '}' | '!' if depth == 0 => break,
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                '"' | '\'' => {
+                    self.parse_string();
+                    continue;
+                }
+                _ => {}
+            }
+            self.consume_char();
+        }
+        self.input[start..self.pos].trim().to_string()
+    }
+
+    /// Parse the component values of a declaration, up to the closing `;` or `}`
+    fn parse_value(&mut self) -> Result<Value, String> {
+        let mut components = Vec::new();
+        loop {
+            self.consume_whitespace();
+            if self.eof() || matches!(self.next_char(), ';' | '}' | '!') {
+                break;
+            }
+            components.push(self.parse_component_value()?);
+        }
+        Ok(match components.len() {
+            1 => components.pop().unwrap(),
+            _ => Value::List(components),
+        })
+    }
+
+    /// Parse a single component value
+    fn parse_component_value(&mut self) -> Result<Value, String> {
+        Ok(match self.next_char() {
+            _ if self.starts_number() => self.parse_length()?, // e.g. 14px, -1.5em
+            '#' => self.parse_color()?,                        // e.g. #ff0000
+            '"' | '\'' => Value::String(self.parse_string()),  // e.g. "Times New Roman"
+            '/' | ',' => Value::Delim(self.consume_char()),
+            c if valid_identifier_char(c) => {
+                let name = self.parse_identifier();
+                if !self.eof() && self.next_char() == '(' {
+                    self.parse_function(&name)?
+                } else if let Some(color) = color::named_color(&name) {
+                    Value::ColorValue(color)
+                } else if name.eq_ignore_ascii_case("currentcolor") {
+                    Value::Keyword("currentcolor".to_string())
+                } else {
+                    Value::Keyword(name)
+                }
+            }
+            c => return Err(format!("unexpected character {}", c)),
+        })
+    }
+
+    /// Parse a functional notation such as `rgb(0 0 0 / 50%)`, starting at the `(`
+    fn parse_function(&mut self, name: &str) -> Result<Value, String> {
+        assert_eq!(self.consume_char(), '(');
+        match &*name.to_ascii_lowercase() {
+            "rgb" | "rgba" | "hsl" | "hsla" | "hwb" => self.parse_color_function(name),
+            "calc" | "min" | "max" | "clamp" => self.parse_math_function(name),
+            "url" => Ok(Value::Url(self.parse_url_arguments()?)),
+            _ => Err(format!("unknown function {}()", name)),
+        }
+    }
+
+    /// Parse and simplify a math function. The result stays a `Calc` value even if it's a
+    /// plain number or length, since it's only clamped to the range of the property once
+    /// computed, e.g. `width: calc(-10px)` is valid and computes to `0px`.
+    fn parse_math_function(&mut self, name: &str) -> Result<Value, String> {
+        let node = self.parse_math_arguments(name)?;
+        if let Err(err) = node.calc_type() {
+            return Err(format!("invalid {}(): {}", name, err));
+        }
+        Ok(Value::Calc(Box::new(node.simplify().finite())))
+    }
+
+    /// Parse the arguments of a math function, after the `(`, and the closing `)`
+    fn parse_math_arguments(&mut self, name: &str) -> Result<CalcNode, String> {
+        let mut args = Vec::new();
+        loop {
+            args.push(self.parse_calc_sum()?);
+            self.consume_whitespace();
+            match self.peek_char(0) {
+                Some(',') => {}
+                Some(')') => {
+                    self.consume_char();
+                    break;
+                }
+                Some(c) => return Err(format!("unexpected character {} in {}()", c, name)),
+                None => return Err(format!("unclosed {}()", name)),
+            }
+            self.consume_char();
+        }
+        Ok(match (&*name.to_ascii_lowercase(), args.len()) {
+            ("calc", 1) => args.pop().unwrap(),
+            ("min", _) => CalcNode::Min(args),
+            ("max", _) => CalcNode::Max(args),
+            ("clamp", 3) => {
+                let max = args.pop().unwrap();
+                let value = args.pop().unwrap();
+                let min = args.pop().unwrap();
+                CalcNode::Clamp(Box::new(min), Box::new(value), Box::new(max))
+            }
+            _ => return Err(format!("wrong number of arguments to {}()", name)),
+        })
+    }
+
+    /// Parse `<product> [ [ + | - ] <product> ]*`
+    fn parse_calc_sum(&mut self) -> Result<CalcNode, String> {
+        let mut terms = vec![self.parse_calc_product()?];
+        loop {
+            self.consume_whitespace();
+            let operator = match self.peek_char(0) {
+                Some(c @ ('+' | '-')) => c,
+                _ => break,
+            };
+            let spaced_before = self.input[..self.pos].ends_with(char::is_whitespace);
+            self.consume_char();
+            // Otherwise they'd be the signs of numbers, e.g. `1px -2px`
+            if !spaced_before || !self.peek_char(0).is_some_and(char::is_whitespace) {
+                return Err(format!("{} must be surrounded by whitespace", operator));
+            }
+            let term = self.parse_calc_product()?;
+            terms.push(match operator {
+                '-' => CalcNode::Negate(Box::new(term)),
+                _ => term,
+            });
+        }
+        Ok(match terms.len() {
+            1 => terms.pop().unwrap(),
+            _ => CalcNode::Sum(terms),
+        })
+    }
+
+    /// Parse `<value> [ [ * | / ] <value> ]*`
+    fn parse_calc_product(&mut self) -> Result<CalcNode, String> {
+        let mut factors = vec![self.parse_calc_value()?];
+        loop {
+            self.consume_whitespace();
+            match self.peek_char(0) {
+                Some('*') => {
+                    self.consume_char();
+                    factors.push(self.parse_calc_value()?);
+                }
+                Some('/') => {
+                    self.consume_char();
+                    factors.push(CalcNode::Invert(Box::new(self.parse_calc_value()?)));
+                }
+                _ => break,
+            }
+        }
+        Ok(match factors.len() {
+            1 => factors.pop().unwrap(),
+            _ => CalcNode::Product(factors),
+        })
+    }
+
+    /// Parse a number, length, percentage, parenthesized sum, nested math function or constant
+    fn parse_calc_value(&mut self) -> Result<CalcNode, String> {
+        self.consume_whitespace();
+        if self.starts_number() {
+            return match self.parse_length()? {
+                Value::Number(n) => Ok(CalcNode::Number(n)),
+                Value::Length(value, unit) => Ok(CalcNode::Length(value, unit)),
+                _ => unreachable!(),
+            };
+        }
+        if self.peek_char(0) == Some('(') {
+            self.consume_char();
+            return self.parse_math_arguments("calc");
+        }
+        let name = self.parse_identifier();
+        if self.peek_char(0) == Some('(') {
+            self.consume_char();
+            return match &*name.to_ascii_lowercase() {
+                "calc" | "min" | "max" | "clamp" => self.parse_math_arguments(&name),
+                _ => Err(format!("unknown function {}() in math function", name)),
+            };
+        }
+        match &*name.to_ascii_lowercase() {
+            "pi" => Ok(CalcNode::Number(std::f32::consts::PI)),
+            "e" => Ok(CalcNode::Number(std::f32::consts::E)),
+            "" => Err("expected a value in math function".to_string()),
+            _ => Err(format!("unexpected {} in math function", name)),
+        }
+    }
+
+    /// Parse the arguments of a color function, in either the legacy comma syntax,
+    /// `rgb(255, 0, 0, 0.5)`, or the modern space syntax, `rgb(255 0 0 / 0.5)`, and evaluate it.
+    /// The two can't be mixed.
+    ///
+    /// ref: https://www.w3.org/TR/css-color-4/#rgb-functions
+    fn parse_color_function(&mut self, name: &str) -> Result<Value, String> {
+        let mut args = vec![self.parse_color_argument(name)?];
+        self.consume_whitespace();
+        let legacy = self.peek_char(0) == Some(',') && !name.eq_ignore_ascii_case("hwb");
+        loop {
+            self.consume_whitespace();
+            match self.peek_char(0) {
+                Some(')') => {
+                    self.consume_char();
+                    break;
+                }
+                None => return Err(format!("unclosed {}()", name)),
+                Some(',') if legacy && args.len() < 4 => {
+                    self.consume_char();
+                }
+                // The alpha comes after a `/` in the space syntax
+                Some('/') if !legacy && args.len() == 3 => {
                     self.consume_char();
                 }
-                c if valid_identifier_char(c) => {
-                    selector.tag_name = Some(self.parse_identifier());
+                Some(c) if legacy || matches!(c, ',' | '/') || args.len() >= 3 => {
+                    return Err(format!("unexpected {} in {}()", c, name))
                 }
-                _ => break,
+                _ => {}
             }
-        }
-        selector
-    }
-    /// Parse a list of declarations enclosed in `{ ... }`
-    fn parse_declarations(&mut self) -> Vec<Declaration> {
-        assert_eq!(self.consume_char(), '{'); //expect '{' when it is called
-        let mut declarations = Vec::new();
-        loop {
-            self.consume_whitespace();
-            if self.next_char() == '}' {
-                self.consume_char();
-                break;
+            let arg = self.parse_color_argument(name)?;
+            // `none` is only allowed in the space syntax
+            if legacy && arg.1 == "none" {
+                return Err(format!("unexpected none in {}()", name));
             }
-            declarations.push(self.parse_declaration());
+            args.push(arg);
+        }
+        if args.len() < 3 {
+            return Err(format!("missing arguments in {}()", name));
+        }
+        let args: Vec<_> = args
+            .iter()
+            .map(|(value, unit)| (*value, if unit == "none" { "" } else { &**unit }))
+            .collect();
+        match color::from_function(name, &args) {
+            Some(color) => Ok(Value::ColorValue(color)),
+            None => Err(format!("invalid color {}()", name)),
         }
-        declarations
     }
-    /// Parse one `<property>: <value>;` declaration
-    fn parse_declaration(&mut self) -> Declaration {
-        let property_name = self.parse_identifier();
-        self.consume_whitespace();
-        assert_eq!(self.consume_char(), ':');
-        self.consume_whitespace();
-        let value = self.parse_value();
-        self.consume_whitespace();
-        assert_eq!(self.consume_char(), ';');
 
-        Declaration {
-            name: property_name,
-            value: value,
+    /// Parse a number with an optional unit or `%` in a color function, or `none`, which is a
+    /// missing component that behaves as zero
+    fn parse_color_argument(&mut self, name: &str) -> Result<(f32, String), String> {
+        self.consume_whitespace();
+        match self.peek_char(0) {
+            _ if self.starts_number() => {
+                let value = self.parse_float();
+                let unit = if self.peek_char(0) == Some('%') {
+                    self.consume_char();
+                    "%".to_string()
+                } else {
+                    self.parse_identifier()
+                };
+                Ok((value, unit))
+            }
+            Some(c) if valid_identifier_char(c) => match &*self.parse_identifier() {
+                "none" => Ok((0.0, "none".to_string())),
+                arg => Err(format!("unexpected argument {} in {}()", arg, name)),
+            },
+            Some(c) => Err(format!("unexpected character {} in {}()", c, name)),
+            None => Err(format!("unclosed {}()", name)),
         }
     }
 
-    // Methods for parsing values
-    fn parse_value(&mut self) -> Value {
-        match self.next_char() {
-            '0'..='9' => self.parse_length(), // e.g. 14px
-            '#' => self.parse_color(),        // e.g. #ff0000
-            _ => Value::Keyword(self.parse_identifier()),
-        }
+    /// Parse a number with an optional unit. A number without a unit is a `Number`.
+    fn parse_length(&mut self) -> Result<Value, String> {
+        let value = self.parse_float();
+        Ok(match self.parse_unit()? {
+            Some(unit) => Value::Length(value, unit),
+            None => Value::Number(value),
+        })
     }
 
-    fn parse_length(&mut self) -> Value {
-        Value::Length(self.parse_float(), self.parse_unit())
+    /// Does the input start with a number, e.g. `1`, `.5`, `-2` or `+0.5`?
+    fn starts_number(&self) -> bool {
+        match self.peek_char(0) {
+            Some('+') | Some('-') => {
+                is_digit(self.peek_char(1))
+                    || self.peek_char(1) == Some('.') && is_digit(self.peek_char(2))
+            }
+            Some('.') => is_digit(self.peek_char(1)),
+            c => is_digit(c),
+        }
     }
 
+    /// Parse a number with an optional sign and exponent, e.g. `-1.5e3`
     fn parse_float(&mut self) -> f32 {
-        let s = self.consume_while(|c| match c {
-            '0'..='9' | '.' => true,
-            _ => false,
-        });
+        let mut s = String::new();
+        if let Some('+') | Some('-') = self.peek_char(0) {
+            s.push(self.consume_char());
+        }
+        s.push_str(&self.consume_while(|c| c.is_ascii_digit()));
+        // A `.` is only part of the number if digits follow, so `1.2.3` is `1.2` then `.3`
+        if self.peek_char(0) == Some('.') && is_digit(self.peek_char(1)) {
+            s.push(self.consume_char());
+            s.push_str(&self.consume_while(|c| c.is_ascii_digit()));
+        }
+
+        // Only treat `e` as an exponent if digits follow, so that `1em` stays a length in em.
+        if let Some('e') | Some('E') = self.peek_char(0) {
+            let signed = matches!(self.peek_char(1), Some('+') | Some('-'));
+            if is_digit(self.peek_char(1)) || signed && is_digit(self.peek_char(2)) {
+                s.push(self.consume_char());
+                if signed {
+                    s.push(self.consume_char());
+                }
+                s.push_str(&self.consume_while(|c| c.is_ascii_digit()));
+            }
+        }
         s.parse().unwrap()
     }
 
-    fn parse_unit(&mut self) -> Unit {
-        match &*self.parse_identifier().to_ascii_lowercase() {
-            "px" => Unit::Px,
-            _ => panic!("unrecognized unit"),
+    /// Parse the unit after a number, if there is one
+    fn parse_unit(&mut self) -> Result<Option<Unit>, String> {
+        if !self.eof() && self.next_char() == '%' {
+            self.consume_char();
+            return Ok(Some(Unit::Percent));
         }
+        let unit = match &*self.parse_identifier().to_ascii_lowercase() {
+            "" => return Ok(None),
+            "px" => Unit::Px,
+            "pt" => Unit::Pt,
+            "pc" => Unit::Pc,
+            "cm" => Unit::Cm,
+            "mm" => Unit::Mm,
+            "in" => Unit::In,
+            "q" => Unit::Q,
+            "em" => Unit::Em,
+            "rem" => Unit::Rem,
+            "ex" => Unit::Ex,
+            "ch" => Unit::Ch,
+            "vw" => Unit::Vw,
+            "vh" => Unit::Vh,
+            "vmin" => Unit::Vmin,
+            "vmax" => Unit::Vmax,
+            unit => return Err(format!("unrecognized unit {}", unit)),
+        };
+        Ok(Some(unit))
     }
 
-    fn parse_color(&mut self) -> Value {
+    /// Parse a hex color: `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`
+    fn parse_color(&mut self) -> Result<Value, String> {
         assert_eq!(self.consume_char(), '#');
-        Value::ColorValue(Color {
-            r: self.parse_hex_pair(),
-            g: self.parse_hex_pair(),
-            b: self.parse_hex_pair(),
-            a: 255,
-        })
-    }
-
-    /// Parse two hexadecimal digits.   #ff0000 -> ff 00 00
-    fn parse_hex_pair(&mut self) -> u8 {
-        let s = &self.input[self.pos..self.pos + 2];
-        self.pos += 2;
-        u8::from_str_radix(s, 16).unwrap()
+        let digits = self.parse_identifier();
+        match color::from_hex(&digits) {
+            Some(color) => Ok(Value::ColorValue(color)),
+            None => Err(format!("invalid hex color #{}", digits)),
+        }
     }
 
     /// Parse a property name or keyword
@@ -239,6 +1267,28 @@ impl Parser {
         self.consume_while(valid_identifier_char)
     }
 
+    /// Does the input continue with `keyword` as a whole word, ignoring ASCII case?
+    fn starts_with_keyword(&self, keyword: &str) -> bool {
+        let rest = &self.input[self.pos..];
+        rest.get(..keyword.len())
+            .is_some_and(|word| word.eq_ignore_ascii_case(keyword))
+            && !rest[keyword.len()..]
+                .chars()
+                .next()
+                .is_some_and(valid_identifier_char)
+    }
+
+    /// Consume `keyword` and any whitespace after it if the input continues with it, and
+    /// return whether it did
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        if !self.starts_with_keyword(keyword) {
+            return false;
+        }
+        self.pos += keyword.len();
+        self.consume_whitespace();
+        true
+    }
+
     /// Consume and discard zero or more whitespace characters.
     fn consume_whitespace(&mut self) {
         self.consume_while(char::is_whitespace);
@@ -270,11 +1320,335 @@ impl Parser {
         self.input[self.pos..].chars().next().unwrap()
     }
 
+    /// Read the character `n` characters ahead without consuming anything, if there is one.
+    fn peek_char(&self, n: usize) -> Option<char> {
+        self.input[self.pos..].chars().nth(n)
+    }
+
     /// Return true if all input is consumed.
     fn eof(&self) -> bool {
         self.pos >= self.input.len()
     }
 }
+
+/// Expand a shorthand declaration into its longhand declarations. Other declarations are
+/// returned unchanged, and invalid shorthands are dropped.
+fn expand_shorthand(declaration: Declaration) -> Vec<Declaration> {
+    // Values with `var()` can't be expanded yet, so every longhand gets the whole shorthand
+    if let Value::Unparsed(ref unparsed) = declaration.value {
+        return match shorthand_longhands(&declaration.name) {
+            Some(longhands) => longhands
+                .into_iter()
+                .map(|name| Declaration {
+                    name,
+                    value: Value::Unparsed(UnparsedValue {
+                        css: unparsed.css.clone(),
+                        shorthand: Some(declaration.name.clone()),
+                    }),
+                    important: declaration.important,
+                })
+                .collect(),
+            None => vec![declaration],
+        };
+    }
+    let longhands = match &*declaration.name {
+        "margin" | "padding" => box_edges(&declaration.value).map(|edges| {
+            SIDES
+                .iter()
+                .zip(edges.iter().cloned())
+                .map(|(side, value)| (format!("{}-{}", declaration.name, side), value))
+                .collect()
+        }),
+        "border-width" | "border-style" | "border-color" => {
+            let suffix = &declaration.name["border-".len()..];
+            box_edges(&declaration.value).map(|edges| {
+                SIDES
+                    .iter()
+                    .zip(edges.iter().cloned())
+                    .map(|(side, value)| (format!("border-{}-{}", side, suffix), value))
+                    .collect()
+            })
+        }
+        "border" => border_components(&declaration.value).map(|components| {
+            SIDES
+                .iter()
+                .flat_map(|side| border_side_longhands(side, &components))
+                .collect()
+        }),
+        "border-top" | "border-right" | "border-bottom" | "border-left" => {
+            let side = &declaration.name["border-".len()..];
+            border_components(&declaration.value)
+                .map(|components| border_side_longhands(side, &components))
+        }
+        "background" => background_color(&declaration.value)
+            .map(|color| vec![("background-color".to_string(), color)]),
+        "font" => font_components(&declaration.value).map(|components| {
+            FONT_LONGHANDS
+                .iter()
+                .map(|name| name.to_string())
+                .zip(components)
+                .collect()
+        }),
+        _ => return vec![declaration],
+    };
+    let longhands = match longhands {
+        Some(longhands) => longhands,
+        None => {
+            warn_invalid(&declaration);
+            return Vec::new();
+        }
+    };
+    longhands
+        .into_iter()
+        .map(|(name, value)| Declaration {
+            name,
+            value,
+            important: declaration.important,
+        })
+        .collect()
+}
+
+/// Is `declaration` of a custom property, or of a known property with a valid value? Warn about
+/// it if not, since it will be ignored.
+fn is_valid(declaration: &Declaration) -> bool {
+    if declaration.name.starts_with("--") {
+        return true;
+    }
+    match properties::lookup(&declaration.name) {
+        Some(property) if property.accepts(&declaration.value) => true,
+        Some(_) => {
+            warn_invalid(declaration);
+            false
+        }
+        None => {
+            eprintln!("Warning: ignoring unknown property {}", declaration.name);
+            false
+        }
+    }
+}
+
+fn warn_invalid(declaration: &Declaration) {
+    eprintln!(
+        "Warning: ignoring invalid value for {}: {}",
+        declaration.name,
+        declaration.value.to_css(Format::Minified)
+    );
+}
+
+/// The longhands set by a shorthand property, or `None` if `name` isn't a shorthand
+fn shorthand_longhands(name: &str) -> Option<Vec<String>> {
+    let longhands = match name {
+        "margin" | "padding" => SIDES
+            .iter()
+            .map(|side| format!("{}-{}", name, side))
+            .collect(),
+        "border-width" | "border-style" | "border-color" => {
+            let suffix = &name["border-".len()..];
+            SIDES
+                .iter()
+                .map(|side| format!("border-{}-{}", side, suffix))
+                .collect()
+        }
+        "border" => SIDES
+            .iter()
+            .flat_map(|side| shorthand_longhands(&format!("border-{}", side)).unwrap())
+            .collect(),
+        "border-top" | "border-right" | "border-bottom" | "border-left" => {
+            ["width", "style", "color"]
+                .iter()
+                .map(|suffix| format!("{}-{}", name, suffix))
+                .collect()
+        }
+        "background" => vec!["background-color".to_string()],
+        "font" => FONT_LONGHANDS.iter().map(|name| name.to_string()).collect(),
+        _ => return None,
+    };
+    Some(longhands)
+}
+
+/// Does the source text of a value contain a `var()` function?
+fn contains_var(css: &str) -> bool {
+    css.to_ascii_lowercase().contains("var(")
+}
+
+/// The order in which box edges are listed by the 1-to-4 value syntax
+const SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
+
+/// Apply the 1-to-4 value rule for box edges: return the values for top, right, bottom, left
+fn box_edges(value: &Value) -> Option<[Value; 4]> {
+    match value.components() {
+        [all] => Some([all.clone(), all.clone(), all.clone(), all.clone()]),
+        [vertical, horizontal] => Some([
+            vertical.clone(),
+            horizontal.clone(),
+            vertical.clone(),
+            horizontal.clone(),
+        ]),
+        [top, horizontal, bottom] => Some([
+            top.clone(),
+            horizontal.clone(),
+            bottom.clone(),
+            horizontal.clone(),
+        ]),
+        [top, right, bottom, left] => {
+            Some([top.clone(), right.clone(), bottom.clone(), left.clone()])
+        }
+        _ => None,
+    }
+}
+
+const BORDER_STYLES: [&str; 10] = [
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+];
+
+/// Split a `border` value into its width, style and color, in any order. Missing components
+/// get their initial values.
+fn border_components(value: &Value) -> Option<[Value; 3]> {
+    let (mut width, mut style, mut color) = (None, None, None);
+    for component in value.components() {
+        let slot = match component {
+            Value::Length(..) | Value::Number(..) | Value::Calc(_) => &mut width,
+            Value::Keyword(k) if matches!(&**k, "thin" | "medium" | "thick") => &mut width,
+            Value::Keyword(k) if BORDER_STYLES.contains(&&**k) => &mut style,
+            Value::Keyword(_) | Value::ColorValue(_) => &mut color,
+            _ => return None,
+        };
+        if slot.replace(component.clone()).is_some() {
+            return None;
+        }
+    }
+    Some([
+        width.unwrap_or_else(|| Value::Keyword("medium".to_string())),
+        style.unwrap_or_else(|| Value::Keyword("none".to_string())),
+        color.unwrap_or_else(|| Value::Keyword("currentcolor".to_string())),
+    ])
+}
+
+fn border_side_longhands(side: &str, components: &[Value; 3]) -> Vec<(String, Value)> {
+    ["width", "style", "color"]
+        .iter()
+        .zip(components)
+        .map(|(suffix, value)| (format!("border-{}-{}", side, suffix), value.clone()))
+        .collect()
+}
+
+/// Keywords of the `background` longhands other than `background-color`
+const BACKGROUND_KEYWORDS: [&str; 21] = [
+    "none",
+    "repeat",
+    "repeat-x",
+    "repeat-y",
+    "no-repeat",
+    "space",
+    "round",
+    "scroll",
+    "fixed",
+    "local",
+    "top",
+    "bottom",
+    "left",
+    "right",
+    "center",
+    "border-box",
+    "padding-box",
+    "content-box",
+    "cover",
+    "contain",
+    "auto",
+];
+
+/// Find the color in a `background` value. Only `background-color` is supported, so the other
+/// components, e.g. images, repeats and positions, are ignored.
+fn background_color(value: &Value) -> Option<Value> {
+    let mut color = Value::ColorValue(color::named_color("transparent").unwrap());
+    for component in value.components() {
+        match component {
+            Value::Keyword(k) if BACKGROUND_KEYWORDS.contains(&&**k) => {}
+            Value::Keyword(_) | Value::ColorValue(_) => color = component.clone(),
+            Value::Length(..) | Value::Number(_) | Value::Calc(_) | Value::Delim('/') => {}
+            Value::Url(_) | Value::Delim(',') => {}
+            _ => return None,
+        }
+    }
+    Some(color)
+}
+
+/// The longhands of `font`, in the order `font_components` returns their values
+const FONT_LONGHANDS: [&str; 5] = [
+    "font-style",
+    "font-weight",
+    "font-size",
+    "line-height",
+    "font-family",
+];
+
+/// Split a `font` value, e.g. `italic bold 12px/1.5 serif`, into its style, weight, size,
+/// line height and family. The style and weight may come in either order before the size, and
+/// missing components get their initial values. `font-variant` and `font-stretch` keywords are
+/// accepted but ignored.
+///
+/// ref: https://www.w3.org/TR/css-fonts-4/#font-prop
+fn font_components(value: &Value) -> Option<[Value; 5]> {
+    let components = value.components();
+    let (mut style, mut weight) = (None, None);
+    let mut i = 0;
+    while let Some(component) = components.get(i) {
+        let slot = match component {
+            Value::Keyword(k) if k.eq_ignore_ascii_case("normal") => {
+                i += 1;
+                continue;
+            }
+            Value::Keyword(k) if matches!(&*k.to_ascii_lowercase(), "italic" | "oblique") => {
+                &mut style
+            }
+            Value::Keyword(k)
+                if matches!(&*k.to_ascii_lowercase(), "bold" | "bolder" | "lighter") =>
+            {
+                &mut weight
+            }
+            Value::Number(_) => &mut weight,
+            Value::Calc(node) if node.calc_type() == Ok(CalcType::Number) => &mut weight,
+            Value::Keyword(k)
+                if matches!(
+                    &*k.to_ascii_lowercase(),
+                    "small-caps" | "condensed" | "semi-condensed" | "expanded" | "semi-expanded"
+                ) =>
+            {
+                i += 1;
+                continue;
+            }
+            _ => break,
+        };
+        if slot.replace(component.clone()).is_some() {
+            return None;
+        }
+        i += 1;
+    }
+    let size = components.get(i)?.clone();
+    let mut rest = &components[i + 1..];
+    let mut line_height = Value::Keyword("normal".to_string());
+    if let [Value::Delim('/'), value, tail @ ..] = rest {
+        line_height = value.clone();
+        rest = tail;
+    }
+    let family = match rest {
+        [] => return None,
+        [family] => family.clone(),
+        families => Value::List(families.to_vec()),
+    };
+    Some([
+        style.unwrap_or_else(|| Value::Keyword("normal".to_string())),
+        weight.unwrap_or_else(|| Value::Keyword("normal".to_string())),
+        size,
+        line_height,
+        family,
+    ])
+}
+
+fn is_digit(c: Option<char>) -> bool {
+    c.is_some_and(|c| c.is_ascii_digit())
+}
+
 fn valid_identifier_char(c: char) -> bool {
     match c {
         'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => true, // TODO: Include U+00A0 and higher.
@@ -286,6 +1660,42 @@ fn valid_identifier_char(c: char) -> bool {
 mod tests {
     use super::*;
 
+    /// The longhands `<prefix>top<suffix>` ... `<prefix>left<suffix>`, all set to `value`
+    fn edges(prefix: &str, suffix: &str, value: Value) -> Vec<Declaration> {
+        SIDES
+            .iter()
+            .map(|side| Declaration {
+                name: format!("{}{}{}", prefix, side, suffix),
+                value: value.clone(),
+                important: false,
+            })
+            .collect()
+    }
+
+    /// Parse the declarations of a single `x { ... }` rule
+    fn declarations(source: &str) -> Vec<Declaration> {
+        parse(format!("x {{ {} }}", source))
+            .rules
+            .remove(0)
+            .declarations
+    }
+
+    fn px(value: f32) -> Value {
+        Value::Length(value, Unit::Px)
+    }
+
+    fn keyword(name: &str) -> Value {
+        Value::Keyword(String::from(name))
+    }
+
+    fn location(line: usize, column: usize) -> SourceLocation {
+        SourceLocation {
+            file: None,
+            line,
+            column,
+        }
+    }
+
     #[test]
     fn test_parse_id() {
         let source = String::from(
@@ -306,8 +1716,12 @@ mod tests {
                 declarations: vec![Declaration {
                     name: String::from("display"),
                     value: Value::Keyword(String::from("inline")),
+                    important: false,
                 }],
+                media: vec![],
+                location: location(2, 9),
             }],
+            imports: vec![],
         };
         assert_eq!(expected, parse(source));
     }
@@ -338,12 +1752,61 @@ mod tests {
                 declarations: vec![Declaration {
                     name: String::from("display"),
                     value: Value::Keyword(String::from("inline")),
+                    important: false,
                 }],
+                media: vec![],
+                location: location(2, 9),
             }],
+            imports: vec![],
         };
         assert_eq!(expected, parse(source));
     }
 
+    #[test]
+    fn test_parse_combinators() {
+        let stylesheet = parse(String::from("ul>li .a , div p#x>*.b { display: block; }"));
+        let simple = |tag_name: Option<&str>, id: Option<&str>, class: &[&str]| SimpleSelector {
+            tag_name: tag_name.map(String::from),
+            id: id.map(String::from),
+            class: class.iter().map(|c| c.to_string()).collect(),
+        };
+        assert_eq!(
+            vec![
+                Selector::Complex(
+                    vec![
+                        (simple(Some("div"), None, &[]), Combinator::Descendant),
+                        (simple(Some("p"), Some("x"), &[]), Combinator::Child),
+                    ],
+                    simple(None, None, &["b"]),
+                ),
+                Selector::Complex(
+                    vec![
+                        (simple(Some("ul"), None, &[]), Combinator::Child),
+                        (simple(Some("li"), None, &[]), Combinator::Descendant),
+                    ],
+                    simple(None, None, &["a"]),
+                ),
+            ],
+            stylesheet.rules[0].selectors
+        );
+        assert_eq!((1, 1, 2), stylesheet.rules[0].selectors[0].specificity());
+        assert_eq!((0, 1, 2), stylesheet.rules[0].selectors[1].specificity());
+    }
+
+    #[test]
+    fn test_drop_invalid_declarations() {
+        let stylesheet = parse(String::from(
+            "p { widht: 10px; display: box; color: 10px; display: block; --x: 10px; \
+             width: var(--x); border-top-style: DASHED; }",
+        ));
+        let names: Vec<&str> = stylesheet.rules[0]
+            .declarations
+            .iter()
+            .map(|d| &d.name[..])
+            .collect();
+        assert_eq!(vec!["display", "--x", "width", "border-top-style"], names);
+    }
+
     #[test]
     fn test_parse_multiple_declarations() {
         let source = String::from(
@@ -365,34 +1828,30 @@ mod tests {
                     id: None,
                     tag_name: Some(String::from("html")),
                 })],
-                declarations: vec![
-                    Declaration {
-                        name: String::from("width"),
-                        value: Value::Length(600.0, Unit::Px),
-                    },
-                    Declaration {
-                        name: String::from("padding"),
-                        value: Value::Length(10.0, Unit::Px),
-                    },
-                    Declaration {
-                        name: String::from("border-width"),
-                        value: Value::Length(1.0, Unit::Px),
-                    },
-                    Declaration {
-                        name: String::from("margin"),
-                        value: Value::Keyword(String::from("auto")),
-                    },
-                    Declaration {
-                        name: String::from("background"),
-                        value: Value::ColorValue(Color {
-                            r: 170,
-                            g: 187,
-                            b: 204,
-                            a: 255,
-                        }),
-                    },
-                ],
+                declarations: vec![Declaration {
+                    name: String::from("width"),
+                    value: Value::Length(600.0, Unit::Px),
+                    important: false,
+                }]
+                .into_iter()
+                .chain(edges("padding-", "", Value::Length(10.0, Unit::Px)))
+                .chain(edges("border-", "-width", Value::Length(1.0, Unit::Px)))
+                .chain(edges("margin-", "", Value::Keyword(String::from("auto"))))
+                .chain(vec![Declaration {
+                    name: String::from("background-color"),
+                    value: Value::ColorValue(Color {
+                        r: 170,
+                        g: 187,
+                        b: 204,
+                        a: 255,
+                    }),
+                    important: false,
+                }])
+                .collect(),
+                media: vec![],
+                location: location(2, 9),
             }],
+            imports: vec![],
         };
         assert_eq!(expected, parse(source));
     }
@@ -432,12 +1891,9 @@ mod tests {
                             tag_name: Some(String::from("h3")),
                         }),
                     ],
-                    declarations: vec![
-                        Declaration {
-                            name: String::from("margin"),
-                            value: Value::Keyword(String::from("auto")),
-                        },
-                        Declaration {
+                    declarations: edges("margin-", "", Value::Keyword(String::from("auto")))
+                        .into_iter()
+                        .chain(vec![Declaration {
                             name: String::from("color"),
                             value: Value::ColorValue(Color {
                                 r: 204,
@@ -445,8 +1901,11 @@ mod tests {
                                 b: 0,
                                 a: 255,
                             }),
-                        },
-                    ],
+                            important: false,
+                        }])
+                        .collect(),
+                    media: vec![],
+                    location: location(2, 9),
                 },
                 Rule {
                     selectors: vec![Selector::Simple(SimpleSelector {
@@ -454,19 +1913,468 @@ mod tests {
                         id: None,
                         tag_name: Some(String::from("div")),
                     })],
-                    declarations: vec![
-                        Declaration {
-                            name: String::from("margin-bottom"),
-                            value: Value::Length(20.0, Unit::Px),
-                        },
-                        Declaration {
-                            name: String::from("padding"),
-                            value: Value::Length(10.0, Unit::Px),
-                        },
-                    ],
+                    declarations: vec![Declaration {
+                        name: String::from("margin-bottom"),
+                        value: Value::Length(20.0, Unit::Px),
+                        important: false,
+                    }]
+                    .into_iter()
+                    .chain(edges("padding-", "", Value::Length(10.0, Unit::Px)))
+                    .collect(),
+                    media: vec![],
+                    location: location(6, 9),
                 },
             ],
+            imports: vec![],
         };
         assert_eq!(expected, parse(source));
     }
+
+    #[test]
+    fn test_parse_multiple_component_values() {
+        assert_eq!(
+            vec![Declaration {
+                name: String::from("font-family"),
+                value: Value::List(vec![
+                    px(12.0),
+                    Value::Delim('/'),
+                    Value::Number(1.5),
+                    keyword("serif"),
+                ]),
+                important: false,
+            }],
+            declarations("font-family: 12px/1.5 serif;")
+        );
+    }
+
+    #[test]
+    fn test_expand_box_edges() {
+        let values = |decls: Vec<Declaration>| -> Vec<Value> {
+            decls.into_iter().map(|decl| decl.value).collect()
+        };
+        assert_eq!(
+            vec![px(1.0), px(2.0), px(1.0), px(2.0)],
+            values(declarations("margin: 1px 2px;"))
+        );
+        assert_eq!(
+            vec![px(1.0), px(2.0), px(3.0), px(2.0)],
+            values(declarations("padding: 1px 2px 3px;"))
+        );
+        assert_eq!(
+            vec![px(1.0), px(2.0), px(3.0), px(4.0)],
+            values(declarations("border-width: 1px 2px 3px 4px;"))
+        );
+        assert_eq!(
+            edges("margin-", "", px(0.0))
+                .into_iter()
+                .map(|decl| decl.name)
+                .collect::<Vec<_>>(),
+            declarations("margin: 0px 0px 0px 0px;")
+                .into_iter()
+                .map(|decl| decl.name)
+                .collect::<Vec<_>>()
+        );
+        // Five values are invalid, so the declaration is dropped.
+        assert_eq!(
+            Vec::<Declaration>::new(),
+            declarations("margin: 1px 2px 3px 4px 5px;")
+        );
+    }
+
+    #[test]
+    fn test_expand_border() {
+        let black = Value::ColorValue(Color {
+            r: 0,
+            g: 0,
+            b: 0,
+            a: 255,
+        });
+        let expected: Vec<Declaration> = SIDES
+            .iter()
+            .flat_map(|side| {
+                vec![
+                    Declaration {
+                        name: format!("border-{}-width", side),
+                        value: px(1.0),
+                        important: false,
+                    },
+                    Declaration {
+                        name: format!("border-{}-style", side),
+                        value: keyword("solid"),
+                        important: false,
+                    },
+                    Declaration {
+                        name: format!("border-{}-color", side),
+                        value: black.clone(),
+                        important: false,
+                    },
+                ]
+            })
+            .collect();
+        assert_eq!(expected, declarations("border: 1px solid #000000;"));
+        assert_eq!(expected, declarations("border: #000000 solid 1px;"));
+
+        assert_eq!(
+            vec![
+                Declaration {
+                    name: String::from("border-left-width"),
+                    value: keyword("medium"),
+                    important: false,
+                },
+                Declaration {
+                    name: String::from("border-left-style"),
+                    value: keyword("dashed"),
+                    important: false,
+                },
+                Declaration {
+                    name: String::from("border-left-color"),
+                    value: keyword("currentcolor"),
+                    important: false,
+                },
+            ],
+            declarations("border-left: dashed;")
+        );
+    }
+
+    #[test]
+    fn test_expand_background() {
+        assert_eq!(
+            vec![Declaration {
+                name: String::from("background-color"),
+                value: rgba(0, 0, 0, 0),
+                important: false,
+            }],
+            declarations("background: none;")
+        );
+        assert_eq!(
+            vec![Declaration {
+                name: String::from("background-color"),
+                value: rgba(255, 0, 0, 255),
+                important: false,
+            }],
+            declarations("background: no-repeat red;")
+        );
+        // Images are ignored
+        for source in [
+            "background: url(x.png) no-repeat red",
+            "background: url( 'x.png' ) center / cover red",
+        ] {
+            assert_eq!(
+                vec![Declaration {
+                    name: String::from("background-color"),
+                    value: rgba(255, 0, 0, 255),
+                    important: false,
+                }],
+                declarations(source),
+                "{}",
+                source
+            );
+        }
+    }
+
+    #[test]
+    fn test_expand_font() {
+        let values = |source: &str| -> Vec<(String, Value)> {
+            declarations(source)
+                .into_iter()
+                .map(|declaration| (declaration.name, declaration.value))
+                .collect()
+        };
+        let longhands = |values: Vec<Value>| -> Vec<(String, Value)> {
+            FONT_LONGHANDS
+                .iter()
+                .map(|name| name.to_string())
+                .zip(values)
+                .collect()
+        };
+        assert_eq!(
+            longhands(vec![
+                keyword("normal"),
+                keyword("normal"),
+                px(12.0),
+                Value::Number(1.5),
+                keyword("serif"),
+            ]),
+            values("font: 12px/1.5 serif")
+        );
+        assert_eq!(
+            longhands(vec![
+                keyword("italic"),
+                Value::Number(700.0),
+                Value::Length(2.0, Unit::Em),
+                keyword("normal"),
+                Value::List(vec![
+                    Value::String("Times New Roman".to_string()),
+                    Value::Delim(','),
+                    keyword("serif"),
+                ]),
+            ]),
+            values("font: 700 italic 2em \"Times New Roman\", serif")
+        );
+        // The size and family are required
+        for source in [
+            "font: bold serif",
+            "font: 12px",
+            "font: italic italic 12px serif",
+        ] {
+            assert!(declarations(source).is_empty(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_parse_units_and_numbers() {
+        assert_eq!(
+            vec![Declaration {
+                name: String::from("font-family"),
+                value: Value::List(vec![
+                    Value::Length(-1.5, Unit::Em),
+                    Value::Length(2.0, Unit::Rem),
+                    Value::Length(50.0, Unit::Percent),
+                    Value::Length(1000.0, Unit::Vw),
+                    Value::Length(0.025, Unit::Q),
+                    Value::Length(0.5, Unit::Vmin),
+                    Value::Number(-3.0),
+                    keyword("-webkit-box"),
+                ]),
+                important: false,
+            }],
+            declarations("font-family: -1.5em +2REM 50% 1e3vw 2.5e-2Q .5vmin -3 -webkit-box;")
+        );
+    }
+
+    #[test]
+    fn test_drop_unparseable_values() {
+        // Each is dropped with a warning, and parsing goes on with the next declaration
+        for declaration in [
+            "transform: rotate(45deg)",
+            "background: image(a.png) red",
+            "width: 10deg",
+            "color: #12",
+            "color: rgb(1, 2, foo)",
+            "width: 1.2.3px",
+            "width: 1px ?",
+            "color: red !imporant",
+            "color: red !important 1px",
+        ] {
+            let source = format!("p {{ {}; height: 1px }} a {{ width: 2px }}", declaration);
+            let stylesheet = parse(source);
+            let names: Vec<Vec<&str>> = stylesheet
+                .rules
+                .iter()
+                .map(|rule| rule.declarations.iter().map(|d| &d.name[..]).collect())
+                .collect();
+            assert_eq!(
+                vec![vec!["height"], vec!["width"]],
+                names,
+                "{}",
+                declaration
+            );
+        }
+    }
+
+    #[test]
+    fn test_drop_unknown_units() {
+        // The rest of the stylesheet is still parsed
+        let stylesheet = parse(String::from(
+            "p { width: 10deg; margin-top: 1dpi 2px; height: 5px } a { width: 3pz; }",
+        ));
+        assert_eq!(2, stylesheet.rules.len());
+        let names: Vec<&str> = stylesheet.rules[0]
+            .declarations
+            .iter()
+            .map(|d| &d.name[..])
+            .collect();
+        assert_eq!(vec!["height"], names);
+        assert!(stylesheet.rules[1].declarations.is_empty());
+    }
+
+    #[test]
+    fn test_length_to_px() {
+        let ctx = LengthContext {
+            font_size: 20.0,
+            root_font_size: 16.0,
+            viewport_width: 800.0,
+            viewport_height: 600.0,
+            percentage_basis: 300.0,
+        };
+        let to_px = |value: f32, unit: Unit| Value::Length(value, unit).to_px(&ctx);
+        assert_eq!(12.0, to_px(12.0, Unit::Px));
+        assert_eq!(16.0, to_px(12.0, Unit::Pt));
+        assert_eq!(16.0, to_px(1.0, Unit::Pc));
+        assert_eq!(96.0, to_px(1.0, Unit::In));
+        assert!((to_px(2.54, Unit::Cm) - 96.0).abs() < 1e-3);
+        assert!((to_px(25.4, Unit::Mm) - 96.0).abs() < 1e-3);
+        assert!((to_px(101.6, Unit::Q) - 96.0).abs() < 1e-3);
+        assert_eq!(30.0, to_px(1.5, Unit::Em));
+        assert_eq!(32.0, to_px(2.0, Unit::Rem));
+        assert_eq!(10.0, to_px(1.0, Unit::Ex));
+        assert_eq!(80.0, to_px(10.0, Unit::Vw));
+        assert_eq!(60.0, to_px(10.0, Unit::Vh));
+        assert_eq!(60.0, to_px(10.0, Unit::Vmin));
+        assert_eq!(80.0, to_px(10.0, Unit::Vmax));
+        assert_eq!(150.0, to_px(50.0, Unit::Percent));
+        assert_eq!(0.0, keyword("auto").to_px(&ctx));
+    }
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> Value {
+        Value::ColorValue(Color { r, g, b, a })
+    }
+
+    /// Parse a single color value
+    fn color(source: &str) -> Value {
+        declarations(&format!("color: {};", source)).remove(0).value
+    }
+
+    #[test]
+    fn test_parse_hex_colors() {
+        assert_eq!(rgba(0xaa, 0xbb, 0xcc, 255), color("#abc"));
+        assert_eq!(rgba(0xaa, 0xbb, 0xcc, 0xdd), color("#abcd"));
+        assert_eq!(rgba(0xaa, 0xbb, 0xcc, 255), color("#AABBCC"));
+        assert_eq!(rgba(0x12, 0x34, 0x56, 0x78), color("#12345678"));
+    }
+
+    #[test]
+    fn test_parse_named_colors() {
+        assert_eq!(rgba(255, 0, 0, 255), color("red"));
+        assert_eq!(rgba(255, 255, 255, 255), color("White"));
+        assert_eq!(rgba(0x66, 0x33, 0x99, 255), color("rebeccapurple"));
+        assert_eq!(rgba(0, 0, 0, 0), color("transparent"));
+        assert_eq!(keyword("currentcolor"), color("currentColor"));
+        assert_eq!(keyword("inherit"), color("inherit"));
+    }
+
+    #[test]
+    fn test_parse_color_functions() {
+        assert_eq!(rgba(255, 0, 0, 255), color("rgb(255, 0, 0)"));
+        assert_eq!(rgba(255, 0, 0, 128), color("rgba(255, 0, 0, 0.5)"));
+        assert_eq!(rgba(255, 0, 0, 128), color("rgb(100% 0% 0% / 50%)"));
+        assert_eq!(rgba(0, 0, 0, 255), color("rgb(none none none)"));
+        assert_eq!(rgba(0, 255, 0, 255), color("hsl(120, 100%, 50%)"));
+        assert_eq!(rgba(0, 255, 0, 255), color("hsl(120deg 100% 50%)"));
+        assert_eq!(
+            rgba(0, 0, 255, 64),
+            color("hsla(0.6667turn 100% 50% / 0.25)")
+        );
+        assert_eq!(rgba(0x66, 0x33, 0x99, 255), color("hsl(270 50% 40%)"));
+        assert_eq!(rgba(128, 128, 128, 255), color("hwb(0 50% 50%)"));
+        assert_eq!(rgba(255, 0, 0, 255), color("hwb(0 0% 0%)"));
+        // Out-of-range channels are clamped
+        assert_eq!(rgba(255, 0, 0, 255), color("rgb(300 -10 0 / 2)"));
+
+        // The comma and space syntaxes can't be mixed
+        for source in [
+            "rgb(255, 0 0)",
+            "rgb(255 0, 0)",
+            "rgb(1 2 3 0.5)",
+            "rgb(1, 2, 3 / 0.5)",
+            "rgb(1 2 / 3 / 0.5)",
+            "rgb(1, 2, 3, 0.5, 1)",
+            "rgb(1, none, 3)",
+            "hwb(0, 0%, 0%)",
+            "rgb(1 2)",
+            "rgb(1, 2,)",
+        ] {
+            let source = format!("color: {}; width: 1px", source);
+            let names: Vec<String> = declarations(&source).into_iter().map(|d| d.name).collect();
+            assert_eq!(vec!["width"], names, "{}", source);
+        }
+    }
+
+    /// Parse the value of a single `width` declaration
+    fn width(source: &str) -> Value {
+        declarations(&format!("width: {};", source)).remove(0).value
+    }
+
+    #[test]
+    fn test_parse_unparsed_values() {
+        let unparsed = |css: &str, shorthand: Option<&str>| {
+            Value::Unparsed(UnparsedValue {
+                css: css.to_string(),
+                shorthand: shorthand.map(str::to_string),
+            })
+        };
+        let declarations = declarations(
+            "--x: { a: b; } [;] fn(;) ';' ; --empty:; padding: var(--p) !important; width: VAR(--w);",
+        );
+        assert_eq!(
+            unparsed("{ a: b; } [;] fn(;) ';'", None),
+            declarations[0].value
+        );
+        assert_eq!(unparsed("", None), declarations[1].value);
+        for (declaration, side) in declarations[2..6].iter().zip(SIDES) {
+            assert_eq!(format!("padding-{}", side), declaration.name);
+            assert_eq!(unparsed("var(--p)", Some("padding")), declaration.value);
+        }
+        assert!(declarations[2].important);
+        assert_eq!(unparsed("VAR(--w)", None), declarations[6].value);
+    }
+
+    #[test]
+    fn test_parse_calc() {
+        // Results stay math functions, so that they can be clamped once computed
+        let calc = |node| Value::Calc(Box::new(node));
+        let px = |value| calc(CalcNode::Length(value, Unit::Px));
+        assert_eq!(px(40.0), width("calc(2 * 20px)"));
+        assert_eq!(px(106.0), width("calc(1in + 10px)"));
+        assert_eq!(px(5.0), width("calc((10px + 20px) / 6)"));
+        assert_eq!(
+            calc(CalcNode::Number(3.0)),
+            declarations("line-height: calc(1 + 2)").remove(0).value
+        );
+        assert_eq!(
+            calc(CalcNode::Length(3.0, Unit::Em)),
+            width("calc(1em + 2em)")
+        );
+        assert_eq!(px(10.0), width("min(10px, 20px, 1in)"));
+        assert_eq!(px(20.0), width("max(10px, calc(4px * 5))"));
+        assert_eq!(px(15.0), width("clamp(10px, 15px, 20px)"));
+        assert_eq!(
+            calc(CalcNode::Sum(vec![
+                CalcNode::Length(100.0, Unit::Percent),
+                CalcNode::Length(-40.0, Unit::Px),
+            ])),
+            width("calc(100% - 2 * 20px)")
+        );
+        // Out of range results are valid
+        assert_eq!(px(-10.0), width("calc(-10px)"));
+        // Division by zero gives the largest finite value
+        assert_eq!(px(f32::MAX), width("calc(10px / 0)"));
+    }
+
+    #[test]
+    fn test_calc_type_errors() {
+        // Declarations with invalid math functions are dropped, and the next one is kept
+        for value in [
+            "calc(1px + 2)",
+            "calc(10px * 2px)",
+            "calc(1px / 1px)",
+            "clamp(1px, 2px)",
+            "calc(1px +)",
+            "calc(100%-20px)",
+            "calc(100% -20px)",
+            "calc(100%- 20px)",
+            "calc(1px +2px)",
+        ] {
+            let source = format!("width: {}; height: 1px", value);
+            let names: Vec<String> = declarations(&source).into_iter().map(|d| d.name).collect();
+            assert_eq!(vec!["height"], names, "{}", value);
+        }
+        // An unclosed function takes up the rest of the input
+        assert!(declarations("width: calc(1px; height: 1px").is_empty());
+    }
+
+    #[test]
+    fn test_calc_to_px() {
+        let ctx = LengthContext {
+            font_size: 20.0,
+            root_font_size: 16.0,
+            viewport_width: 800.0,
+            viewport_height: 600.0,
+            percentage_basis: 300.0,
+        };
+        assert_eq!(260.0, width("calc(100% - 2 * 20px)").to_px(&ctx));
+        assert_eq!(70.0, width("calc(50% / 3 + 1em)").to_px(&ctx));
+        assert_eq!(150.0, width("min(50%, 10vw + 1in)").to_px(&ctx));
+        assert_eq!(200.0, width("clamp(200px, 10%, 100%)").to_px(&ctx));
+    }
 }
@@ -0,0 +1,324 @@
+//! The `display` property: which boxes an element generates, and how they are laid out.
+//!
+//! ref: https://www.w3.org/TR/css-display-3/#the-display-properties
+
+use crate::css::Value;
+use crate::properties::ComputedValue;
+
+/// The computed value of `display`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Display {
+    /// A box with an outer display type, for the layout of its parent, and an inner display
+    /// type, for the layout of its children. List items also get a marker.
+    Box {
+        outside: DisplayOutside,
+        inside: DisplayInside,
+        list_item: bool,
+    },
+    /// A part of a table or ruby, which only makes sense inside one
+    Internal(DisplayInternal),
+    /// No box, but the children generate boxes as if they were children of the parent
+    Contents,
+    /// No boxes at all, for the element and its descendants
+    None,
+}
+
+/// How a box takes part in the layout of its parent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayOutside {
+    Block,
+    Inline,
+    RunIn,
+}
+
+/// How a box lays out its children
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayInside {
+    Flow,
+    /// Like `Flow`, but in a new block formatting context
+    FlowRoot,
+    Table,
+    Flex,
+    Grid,
+    Ruby,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayInternal {
+    TableRowGroup,
+    TableHeaderGroup,
+    TableFooterGroup,
+    TableRow,
+    TableCell,
+    TableColumnGroup,
+    TableColumn,
+    TableCaption,
+    RubyBase,
+    RubyText,
+    RubyBaseContainer,
+    RubyTextContainer,
+}
+
+const OUTSIDE: &[(&str, DisplayOutside)] = &[
+    ("block", DisplayOutside::Block),
+    ("inline", DisplayOutside::Inline),
+    ("run-in", DisplayOutside::RunIn),
+];
+
+const INSIDE: &[(&str, DisplayInside)] = &[
+    ("flow", DisplayInside::Flow),
+    ("flow-root", DisplayInside::FlowRoot),
+    ("table", DisplayInside::Table),
+    ("flex", DisplayInside::Flex),
+    ("grid", DisplayInside::Grid),
+    ("ruby", DisplayInside::Ruby),
+];
+
+const INTERNAL: &[(&str, DisplayInternal)] = &[
+    ("table-row-group", DisplayInternal::TableRowGroup),
+    ("table-header-group", DisplayInternal::TableHeaderGroup),
+    ("table-footer-group", DisplayInternal::TableFooterGroup),
+    ("table-row", DisplayInternal::TableRow),
+    ("table-cell", DisplayInternal::TableCell),
+    ("table-column-group", DisplayInternal::TableColumnGroup),
+    ("table-column", DisplayInternal::TableColumn),
+    ("table-caption", DisplayInternal::TableCaption),
+    ("ruby-base", DisplayInternal::RubyBase),
+    ("ruby-text", DisplayInternal::RubyText),
+    ("ruby-base-container", DisplayInternal::RubyBaseContainer),
+    ("ruby-text-container", DisplayInternal::RubyTextContainer),
+];
+
+/// The single keywords for inline-level boxes that aren't inline flow, written `inline <inside>`
+/// in the two-keyword syntax
+const LEGACY: &[(&str, DisplayInside)] = &[
+    ("inline-block", DisplayInside::FlowRoot),
+    ("inline-table", DisplayInside::Table),
+    ("inline-flex", DisplayInside::Flex),
+    ("inline-grid", DisplayInside::Grid),
+];
+
+fn find<T: Copy>(table: &[(&str, T)], keyword: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(k, _)| keyword.eq_ignore_ascii_case(k))
+        .map(|&(_, value)| value)
+}
+
+fn keyword_of<T: PartialEq>(table: &[(&'static str, T)], value: T) -> Option<&'static str> {
+    table.iter().find(|(_, v)| *v == value).map(|&(k, _)| k)
+}
+
+impl Display {
+    pub const INLINE: Display = Display::Box {
+        outside: DisplayOutside::Inline,
+        inside: DisplayInside::Flow,
+        list_item: false,
+    };
+    pub const BLOCK: Display = Display::Box {
+        outside: DisplayOutside::Block,
+        inside: DisplayInside::Flow,
+        list_item: false,
+    };
+    pub const LIST_ITEM: Display = Display::Box {
+        outside: DisplayOutside::Block,
+        inside: DisplayInside::Flow,
+        list_item: true,
+    };
+
+    /// Parse a `display` value: a single keyword, or the multi-keyword syntax, e.g.
+    /// `inline flow-root` or `inline list-item`. Return `None` if the value is invalid.
+    pub fn parse(value: &Value) -> Option<Display> {
+        let keywords: Vec<&str> = match value {
+            Value::Keyword(keyword) => vec![keyword],
+            Value::List(components) => components
+                .iter()
+                .map(|component| match component {
+                    Value::Keyword(keyword) => Some(&keyword[..]),
+                    _ => None,
+                })
+                .collect::<Option<_>>()?,
+            _ => return None,
+        };
+        if let [keyword] = keywords[..] {
+            if let Some(inside) = find(LEGACY, keyword) {
+                return Some(Display::Box {
+                    outside: DisplayOutside::Inline,
+                    inside,
+                    list_item: false,
+                });
+            }
+            if let Some(internal) = find(INTERNAL, keyword) {
+                return Some(Display::Internal(internal));
+            }
+            if keyword.eq_ignore_ascii_case("contents") {
+                return Some(Display::Contents);
+            }
+            if keyword.eq_ignore_ascii_case("none") {
+                return Some(Display::None);
+            }
+        }
+
+        // `<display-outside> || <display-inside>`, or
+        // `<display-outside>? && [ flow | flow-root ]? && list-item`
+        let (mut outside, mut inside, mut list_item) = (None, None, false);
+        for keyword in keywords {
+            if let Some(value) = find(OUTSIDE, keyword) {
+                if outside.replace(value).is_some() {
+                    return None;
+                }
+            } else if let Some(value) = find(INSIDE, keyword) {
+                if inside.replace(value).is_some() {
+                    return None;
+                }
+            } else if keyword.eq_ignore_ascii_case("list-item") && !list_item {
+                list_item = true;
+            } else {
+                return None;
+            }
+        }
+        let inside = inside.unwrap_or(DisplayInside::Flow);
+        if list_item && !matches!(inside, DisplayInside::Flow | DisplayInside::FlowRoot) {
+            return None;
+        }
+        // The outer display type defaults to block, except for ruby
+        let outside = outside.unwrap_or(match inside {
+            DisplayInside::Ruby => DisplayOutside::Inline,
+            _ => DisplayOutside::Block,
+        });
+        Some(Display::Box {
+            outside,
+            inside,
+            list_item,
+        })
+    }
+
+    /// The shortest keywords for the value, e.g. `inline-block` rather than `inline flow-root`
+    fn keywords(self) -> Vec<&'static str> {
+        let (outside, inside, list_item) = match self {
+            Display::Box {
+                outside,
+                inside,
+                list_item,
+            } => (outside, inside, list_item),
+            Display::Internal(internal) => return vec![keyword_of(INTERNAL, internal).unwrap()],
+            Display::Contents => return vec!["contents"],
+            Display::None => return vec!["none"],
+        };
+        let outside_keyword = keyword_of(OUTSIDE, outside).unwrap();
+        let inside_keyword = keyword_of(INSIDE, inside).unwrap();
+        if list_item {
+            let mut keywords = Vec::new();
+            if outside != DisplayOutside::Block {
+                keywords.push(outside_keyword);
+            }
+            if inside != DisplayInside::Flow {
+                keywords.push(inside_keyword);
+            }
+            keywords.push("list-item");
+            return keywords;
+        }
+        match (outside, inside) {
+            (_, DisplayInside::Flow) => vec![outside_keyword],
+            // `ruby` alone is inline
+            (DisplayOutside::Inline, DisplayInside::Ruby) => vec![inside_keyword],
+            (DisplayOutside::Block, DisplayInside::Ruby) => vec![outside_keyword, inside_keyword],
+            (DisplayOutside::Block, _) => vec![inside_keyword],
+            (DisplayOutside::Inline, _) => vec![keyword_of(LEGACY, inside).unwrap()],
+            (DisplayOutside::RunIn, _) => vec![outside_keyword, inside_keyword],
+        }
+    }
+}
+
+impl ComputedValue for Display {
+    fn from_value(value: &Value) -> Self {
+        Display::parse(value).unwrap_or(Display::INLINE)
+    }
+
+    fn to_value(&self) -> Value {
+        let keywords = self.keywords();
+        match keywords[..] {
+            [keyword] => Value::Keyword(keyword.to_string()),
+            _ => Value::List(
+                keywords
+                    .iter()
+                    .map(|keyword| Value::Keyword(keyword.to_string()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Display, DisplayInside, DisplayInternal, DisplayOutside};
+    use crate::css;
+    use crate::properties::ComputedValue;
+    use crate::serialize::{Format, ToCss};
+
+    fn parse(source: &str) -> Option<Display> {
+        let declarations = css::parse_declaration_list(format!("font-family: {}", source));
+        Display::parse(&declarations[0].value)
+    }
+
+    #[test]
+    fn test_parse_display() {
+        let display = |outside, inside, list_item| {
+            Some(Display::Box {
+                outside,
+                inside,
+                list_item,
+            })
+        };
+        use DisplayInside::*;
+        use DisplayOutside::*;
+        assert_eq!(Some(Display::BLOCK), parse("block"));
+        assert_eq!(Some(Display::BLOCK), parse("flow"));
+        assert_eq!(Some(Display::BLOCK), parse("Block Flow"));
+        assert_eq!(Some(Display::INLINE), parse("inline"));
+        assert_eq!(Some(Display::LIST_ITEM), parse("list-item"));
+        assert_eq!(display(Inline, FlowRoot, false), parse("inline-block"));
+        assert_eq!(display(Inline, FlowRoot, false), parse("flow-root inline"));
+        assert_eq!(display(Block, FlowRoot, false), parse("flow-root"));
+        assert_eq!(display(Block, Flex, false), parse("flex"));
+        assert_eq!(display(Inline, Flex, false), parse("inline-flex"));
+        assert_eq!(display(Inline, Grid, false), parse("inline grid"));
+        assert_eq!(display(Inline, Ruby, false), parse("ruby"));
+        assert_eq!(display(Inline, Flow, true), parse("list-item inline"));
+        assert_eq!(
+            display(RunIn, FlowRoot, true),
+            parse("run-in list-item flow-root")
+        );
+        assert_eq!(
+            Some(Display::Internal(DisplayInternal::TableCell)),
+            parse("table-cell")
+        );
+        assert_eq!(Some(Display::Contents), parse("contents"));
+        assert_eq!(Some(Display::None), parse("none"));
+
+        assert_eq!(None, parse("box"));
+        assert_eq!(None, parse("block inline"));
+        assert_eq!(None, parse("flex list-item"));
+        assert_eq!(None, parse("list-item list-item"));
+        assert_eq!(None, parse("inline table-cell"));
+        assert_eq!(None, parse("inline-block flow"));
+        assert_eq!(None, parse("1px"));
+    }
+
+    #[test]
+    fn test_serialize_display() {
+        let shortest = |source| parse(source).unwrap().to_value().to_css(Format::Minified);
+        assert_eq!("block", shortest("block flow"));
+        assert_eq!("inline-block", shortest("inline flow-root"));
+        assert_eq!("flex", shortest("block flex"));
+        assert_eq!("ruby", shortest("inline ruby"));
+        assert_eq!("block ruby", shortest("ruby block"));
+        assert_eq!("list-item", shortest("block flow list-item"));
+        assert_eq!(
+            "inline flow-root list-item",
+            shortest("list-item flow-root inline")
+        );
+        assert_eq!("run-in flex", shortest("flex run-in"));
+        assert_eq!("table-row", shortest("table-row"));
+    }
+}
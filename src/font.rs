@@ -0,0 +1,1723 @@
+//! Fonts: loading TrueType and OpenType files, choosing fonts for CSS font properties, and
+//! measuring text.
+//!
+//! ref: https://learn.microsoft.com/en-us/typography/opentype/spec/
+//! ref: https://www.w3.org/TR/css-fonts-4/#font-matching-algorithm
+
+use crate::properties::ComputedStyle;
+pub use crate::properties::FontStyle;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// The font that's always available, for characters that no other font has
+const FALLBACK_FONT: &[u8] = include_bytes!("../fonts/DejaVuSans.ttf");
+
+/// The installed families that generic families stand for, in order of preference. The
+/// fallback font is used if none of them are installed.
+const GENERIC_FAMILIES: &[(&str, &[&str])] = &[
+    (
+        "serif",
+        &[
+            "DejaVu Serif",
+            "Liberation Serif",
+            "Times New Roman",
+            "Times",
+            "Noto Serif",
+        ],
+    ),
+    (
+        "sans-serif",
+        &[
+            "DejaVu Sans",
+            "Liberation Sans",
+            "Arial",
+            "Helvetica",
+            "Noto Sans",
+        ],
+    ),
+    (
+        "monospace",
+        &[
+            "DejaVu Sans Mono",
+            "Liberation Mono",
+            "Courier New",
+            "Courier",
+            "Noto Sans Mono",
+        ],
+    ),
+    ("cursive", &["Comic Sans MS", "Apple Chancery"]),
+    ("fantasy", &["Impact", "Papyrus"]),
+    (
+        "system-ui",
+        &["DejaVu Sans", "Noto Sans", "Segoe UI", "Helvetica"],
+    ),
+];
+
+#[derive(Debug)]
+pub enum FontError {
+    /// The data ends before a table or value that should be there
+    Truncated,
+    MissingTable(&'static str),
+    /// A font collection, or a table in a format that isn't supported
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FontError::Truncated => write!(f, "unexpected end of font data"),
+            FontError::MissingTable(tag) => write!(f, "missing {} table", tag),
+            FontError::Unsupported(what) => write!(f, "unsupported {}", what),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, FontError>;
+
+/// A font face, with what we need to measure and draw text. Sizes are in font units.
+pub struct Font {
+    /// The family name, e.g. `DejaVu Sans`
+    pub family: String,
+    /// The weight, from 1 to 1000, where 400 is normal and 700 is bold
+    pub weight: u16,
+    pub style: FontStyle,
+    units_per_em: f32,
+    ascender: f32,
+    /// Negative, below the baseline
+    descender: f32,
+    line_gap: f32,
+    /// The glyph of each character the font has
+    glyphs: HashMap<char, u16>,
+    /// The advance width of each glyph
+    advances: Vec<u16>,
+    /// Adjustments to the advance of the first glyph of a pair, when the second follows it
+    kerning: HashMap<(u16, u16), i16>,
+    outlines: Outlines,
+}
+
+impl fmt::Debug for Font {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Font")
+            .field("family", &self.family)
+            .field("weight", &self.weight)
+            .field("style", &self.style)
+            .finish()
+    }
+}
+
+/// Where a font's glyph outlines come from
+enum Outlines {
+    /// A `glyf` table, and the offset of each glyph in it from the `loca` table, with one more
+    /// for the end of the last glyph
+    TrueType {
+        glyf: Vec<u8>,
+        loca: Vec<usize>,
+    },
+    Cff(Cff),
+    /// Fonts without outlines, e.g. bitmap-only fonts, draw nothing
+    None,
+}
+
+/// A point of an outline, in font units with y up, or in px with y down once it's placed
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Point {
+    pub x: f32,
+    pub y: f32,
+}
+
+/// An edge of a glyph outline. The edges of each contour join up, and contours are closed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Segment {
+    Line(Point, Point),
+    /// A quadratic Bézier curve, with one control point
+    Quad(Point, Point, Point),
+    /// A cubic Bézier curve, with two control points
+    Cubic(Point, Point, Point, Point),
+}
+
+impl Segment {
+    /// The segment with `f` applied to each point
+    pub fn map(self, f: impl Fn(Point) -> Point) -> Segment {
+        match self {
+            Segment::Line(p0, p1) => Segment::Line(f(p0), f(p1)),
+            Segment::Quad(p0, p1, p2) => Segment::Quad(f(p0), f(p1), f(p2)),
+            Segment::Cubic(p0, p1, p2, p3) => Segment::Cubic(f(p0), f(p1), f(p2), f(p3)),
+        }
+    }
+
+    /// The end points and control points. The curve stays within their convex hull.
+    pub fn points(&self) -> Vec<Point> {
+        match *self {
+            Segment::Line(p0, p1) => vec![p0, p1],
+            Segment::Quad(p0, p1, p2) => vec![p0, p1, p2],
+            Segment::Cubic(p0, p1, p2, p3) => vec![p0, p1, p2, p3],
+        }
+    }
+}
+
+/// Glyphs placed on a line, all in one font
+#[derive(Debug, Clone)]
+pub struct GlyphRun {
+    pub font: Arc<Font>,
+    /// Each glyph, and its x offset in px from the start of the text
+    pub glyphs: Vec<(u16, f32)>,
+}
+
+/// The vertical metrics of the first available font for a style, in px
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontMetrics {
+    /// The height above the baseline
+    pub ascent: f32,
+    /// The depth below the baseline, as a positive number
+    pub descent: f32,
+    /// The recommended extra space between lines
+    pub line_gap: f32,
+}
+
+/// The font properties that font matching depends on
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct FontDescription {
+    families: Vec<String>,
+    weight: u16,
+    style: FontStyle,
+}
+
+/// The fonts that text can be rendered with: the fonts loaded from font directories, and the
+/// bundled fallback font
+pub struct FontCollection {
+    fonts: Vec<Arc<Font>>,
+    /// The fonts to try for each description, in order, as indexes into `fonts`
+    matches: Mutex<HashMap<FontDescription, Arc<Vec<usize>>>>,
+}
+
+impl Default for FontCollection {
+    /// A collection of just the fallback font
+    fn default() -> FontCollection {
+        let fallback = Font::parse(FALLBACK_FONT).expect("The fallback font is invalid");
+        FontCollection {
+            fonts: vec![Arc::new(fallback)],
+            matches: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl FontCollection {
+    /// Add the `.ttf` and `.otf` files in `dir` and its subdirectories. Files that can't be
+    /// parsed are skipped with a warning.
+    pub fn load_directory(&mut self, dir: &Path) -> io::Result<()> {
+        let mut paths: Vec<_> = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<_>>()?;
+        paths.sort();
+        for path in paths {
+            if path.is_dir() {
+                self.load_directory(&path)?;
+                continue;
+            }
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            if !matches!(
+                extension.map(str::to_ascii_lowercase).as_deref(),
+                Some("ttf" | "otf")
+            ) {
+                continue;
+            }
+            match Font::parse(&fs::read(&path)?) {
+                Ok(font) => self.add(font),
+                Err(err) => eprintln!("Warning: skipping font {}: {}", path.display(), err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a font, which takes precedence over the fallback font for generic families
+    pub fn add(&mut self, font: Font) {
+        let fallback = self.fonts.len() - 1;
+        self.fonts.insert(fallback, Arc::new(font));
+        self.matches.lock().unwrap().clear();
+    }
+
+    /// The width of `text` in px, in the fonts for `style`, with kerning
+    pub fn text_width(&self, text: &str, style: &ComputedStyle) -> f32 {
+        self.place_glyphs(text, style, |_, _, _| {})
+    }
+
+    /// The glyphs for `text` in the fonts for `style`, placed one after another with kerning,
+    /// in runs of the same font
+    pub fn shape(&self, text: &str, style: &ComputedStyle) -> Vec<GlyphRun> {
+        let mut runs: Vec<(usize, GlyphRun)> = Vec::new();
+        self.place_glyphs(text, style, |index, glyph, x| match runs.last_mut() {
+            Some((run_index, run)) if *run_index == index => run.glyphs.push((glyph, x)),
+            _ => runs.push((
+                index,
+                GlyphRun {
+                    font: Arc::clone(&self.fonts[index]),
+                    glyphs: vec![(glyph, x)],
+                },
+            )),
+        });
+        runs.into_iter().map(|(_, run)| run).collect()
+    }
+
+    /// Call `f` with the font index, glyph and x offset in px of each character of `text`, and
+    /// return the width of the text
+    fn place_glyphs(
+        &self,
+        text: &str,
+        style: &ComputedStyle,
+        mut f: impl FnMut(usize, u16, f32),
+    ) -> f32 {
+        let fonts = self.fonts_for(style);
+        let mut width = 0.0;
+        let mut previous: Option<(usize, u16)> = None;
+        for c in text.chars() {
+            let (index, glyph) = self.glyph(&fonts, c);
+            let font = &self.fonts[index];
+            let scale = style.font_size / font.units_per_em;
+            // Kerning moves this glyph, by adjusting the advance of the one before it
+            if let Some((previous_index, previous_glyph)) = previous {
+                if previous_index == index {
+                    width += font.kerning(previous_glyph, glyph) * scale;
+                }
+            }
+            f(index, glyph, width);
+            width += font.advance(glyph) * scale;
+            previous = Some((index, glyph));
+        }
+        width
+    }
+
+    /// The metrics of the first available font for `style`
+    pub fn metrics(&self, style: &ComputedStyle) -> FontMetrics {
+        let font = &self.fonts[self.fonts_for(style)[0]];
+        let scale = style.font_size / font.units_per_em;
+        FontMetrics {
+            ascent: font.ascender * scale,
+            descent: -font.descender * scale,
+            line_gap: font.line_gap * scale,
+        }
+    }
+
+    /// The font to use for character `c`: the first of `fonts` that has it, or the first font
+    /// with its missing glyph
+    fn glyph(&self, fonts: &[usize], c: char) -> (usize, u16) {
+        fonts
+            .iter()
+            .find_map(|&index| Some((index, self.fonts[index].glyph(c)?)))
+            .unwrap_or((fonts[0], 0))
+    }
+
+    /// The fonts to try for text in `style`, in order: the best match in each family of
+    /// `font-family` that's available, then the best match in the fallback font's family, then
+    /// every other font
+    fn fonts_for(&self, style: &ComputedStyle) -> Arc<Vec<usize>> {
+        let description = FontDescription {
+            families: style.font_family.0.clone(),
+            weight: style.font_weight.0,
+            style: style.font_style,
+        };
+        let mut matches = self.matches.lock().unwrap();
+        if let Some(fonts) = matches.get(&description) {
+            return Arc::clone(fonts);
+        }
+
+        let mut fonts = Vec::new();
+        let fallback_family = &self.fonts.last().unwrap().family;
+        let families = description
+            .families
+            .iter()
+            .map(|family| self.resolve_generic(family))
+            .chain(std::iter::once(&fallback_family[..]));
+        for family in families {
+            if let Some(index) = self.match_face(family, description.weight, description.style) {
+                if !fonts.contains(&index) {
+                    fonts.push(index);
+                }
+            }
+        }
+        let others: Vec<usize> = (0..self.fonts.len())
+            .filter(|index| !fonts.contains(index))
+            .collect();
+        fonts.extend(others);
+        let fonts = Arc::new(fonts);
+        matches.insert(description, Arc::clone(&fonts));
+        fonts
+    }
+
+    /// The installed family that `family` stands for, if it's a generic family
+    fn resolve_generic<'f>(&self, family: &'f str) -> &'f str {
+        match GENERIC_FAMILIES
+            .iter()
+            .find(|(generic, _)| *generic == family)
+        {
+            Some((_, candidates)) => candidates
+                .iter()
+                .find(|candidate| self.fonts.iter().any(|font| font.is_family(candidate)))
+                .copied()
+                .unwrap_or(family),
+            None => family,
+        }
+    }
+
+    /// The face of `family` that best matches the weight and style, as an index into `fonts`
+    ///
+    /// ref: https://www.w3.org/TR/css-fonts-4/#font-style-matching
+    fn match_face(&self, family: &str, weight: u16, style: FontStyle) -> Option<usize> {
+        let faces: Vec<usize> = (0..self.fonts.len())
+            .filter(|&index| self.fonts[index].is_family(family))
+            .collect();
+        // Narrow the faces by style, then by weight
+        let style_order = match style {
+            FontStyle::Italic => [FontStyle::Italic, FontStyle::Oblique, FontStyle::Normal],
+            FontStyle::Oblique => [FontStyle::Oblique, FontStyle::Italic, FontStyle::Normal],
+            FontStyle::Normal => [FontStyle::Normal, FontStyle::Oblique, FontStyle::Italic],
+        };
+        let faces: Vec<usize> = style_order
+            .iter()
+            .map(|&style| {
+                faces
+                    .iter()
+                    .copied()
+                    .filter(|&index| self.fonts[index].style == style)
+                    .collect::<Vec<_>>()
+            })
+            .find(|faces| !faces.is_empty())?;
+        faces
+            .iter()
+            .copied()
+            .min_by_key(|&index| weight_preference(weight, self.fonts[index].weight))
+    }
+}
+
+/// How far `available` is from the desired `weight`, for choosing a face: lower is better.
+/// Weights between 400 and 500 first look up to 500, then down, then further up. Lower weights
+/// look down first, and higher weights look up first.
+fn weight_preference(weight: u16, available: u16) -> (u8, u16) {
+    let (weight, available) = (i32::from(weight), i32::from(available));
+    let distance = (weight - available).unsigned_abs() as u16;
+    let rank = if available == weight {
+        0
+    } else if (400..=500).contains(&weight) {
+        if available > weight && available <= 500 {
+            1
+        } else if available < weight {
+            2
+        } else {
+            3
+        }
+    } else if (available < weight) == (weight < 400) {
+        1
+    } else {
+        2
+    };
+    (rank, distance)
+}
+
+/// The computed value of `bolder` or `lighter`, relative to the parent's weight
+///
+/// ref: https://www.w3.org/TR/css-fonts-4/#relative-weights
+pub fn relative_weight(parent: u16, bolder: bool) -> u16 {
+    match (parent, bolder) {
+        (0..=349, true) => 400,
+        (350..=549, true) => 700,
+        (_, true) => 900.max(parent),
+        (0..=99, false) => parent,
+        (100..=549, false) => 100,
+        (550..=749, false) => 400,
+        (_, false) => 700,
+    }
+}
+
+impl Font {
+    /// Parse a TrueType or OpenType font file
+    pub fn parse(data: &[u8]) -> Result<Font> {
+        let tables = table_directory(data)?;
+        let table = |tag: &'static str| -> Result<&[u8]> {
+            tables
+                .get(tag.as_bytes())
+                .copied()
+                .ok_or(FontError::MissingTable(tag))
+        };
+
+        let head = table("head")?;
+        let units_per_em = f32::from(read_u16(head, 18)?);
+        let mac_style = read_u16(head, 44)?;
+        let long_offsets = read_i16(head, 50)? != 0;
+
+        let hhea = table("hhea")?;
+        let ascender = f32::from(read_i16(hhea, 4)?);
+        let descender = f32::from(read_i16(hhea, 6)?);
+        let line_gap = f32::from(read_i16(hhea, 8)?);
+        let metrics_count = usize::from(read_u16(hhea, 34)?);
+        let glyph_count = usize::from(read_u16(table("maxp")?, 4)?);
+        let advances = parse_hmtx(table("hmtx")?, metrics_count, glyph_count)?;
+        let outlines = match (
+            tables.get(b"glyf"),
+            tables.get(b"loca"),
+            tables.get(b"CFF "),
+        ) {
+            (Some(glyf), Some(loca), _) => Outlines::TrueType {
+                glyf: glyf.to_vec(),
+                loca: parse_loca(loca, long_offsets, glyph_count)?,
+            },
+            (_, _, Some(cff)) => Outlines::Cff(Cff::parse(cff)?),
+            _ => Outlines::None,
+        };
+
+        // Without an OS/2 table, the weight and style come from the `head` table
+        let (weight, style) = match tables.get(b"OS/2") {
+            Some(os2) => {
+                let selection = read_u16(os2, 62)?;
+                let style = if selection & 0x200 != 0 {
+                    FontStyle::Oblique
+                } else if selection & 1 != 0 {
+                    FontStyle::Italic
+                } else {
+                    FontStyle::Normal
+                };
+                (read_u16(os2, 4)?.clamp(1, 1000), style)
+            }
+            None => (
+                if mac_style & 1 != 0 { 700 } else { 400 },
+                if mac_style & 2 != 0 {
+                    FontStyle::Italic
+                } else {
+                    FontStyle::Normal
+                },
+            ),
+        };
+
+        Ok(Font {
+            family: parse_family_name(table("name")?)?,
+            weight,
+            style,
+            units_per_em,
+            ascender,
+            descender,
+            line_gap,
+            glyphs: parse_cmap(table("cmap")?)?,
+            advances,
+            kerning: match tables.get(b"kern") {
+                Some(kern) => parse_kern(kern)?,
+                None => HashMap::new(),
+            },
+            outlines,
+        })
+    }
+
+    /// The number of font units in an em, which is the font size
+    pub fn units_per_em(&self) -> f32 {
+        self.units_per_em
+    }
+
+    /// The outline of a glyph, in font units with y up. It's empty for glyphs without outlines
+    /// like spaces, and for glyphs whose data is invalid.
+    pub fn outline(&self, glyph: u16) -> Vec<Segment> {
+        let mut segments = Vec::new();
+        let result = match self.outlines {
+            Outlines::TrueType { ref glyf, ref loca } => {
+                glyf_outline(glyf, loca, glyph, IDENTITY, 0, &mut segments)
+            }
+            Outlines::Cff(ref cff) => cff.outline(glyph, &mut segments),
+            Outlines::None => Ok(()),
+        };
+        match result {
+            Ok(()) => segments,
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// The glyph for `c`, or `None` if the font doesn't have one
+    pub fn glyph(&self, c: char) -> Option<u16> {
+        self.glyphs.get(&c).copied()
+    }
+
+    /// The advance width of a glyph, in font units
+    pub fn advance(&self, glyph: u16) -> f32 {
+        let advance = self
+            .advances
+            .get(usize::from(glyph))
+            .or_else(|| self.advances.last());
+        f32::from(advance.copied().unwrap_or(0))
+    }
+
+    /// The kerning adjustment between two glyphs, in font units
+    pub fn kerning(&self, left: u16, right: u16) -> f32 {
+        f32::from(self.kerning.get(&(left, right)).copied().unwrap_or(0))
+    }
+
+    /// Is the font in `family`? Family names are ASCII case-insensitive.
+    fn is_family(&self, family: &str) -> bool {
+        self.family.eq_ignore_ascii_case(family)
+    }
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(FontError::Truncated),
+    }
+}
+
+fn read_i16(data: &[u8], offset: usize) -> Result<i16> {
+    read_u16(data, offset).map(|value| value as i16)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from(read_u16(data, offset)?) << 16 | u32::from(read_u16(data, offset + 2)?))
+}
+
+/// The part of `data` at `offset` with `length` bytes
+fn slice(data: &[u8], offset: usize, length: usize) -> Result<&[u8]> {
+    data.get(offset..offset + length)
+        .ok_or(FontError::Truncated)
+}
+
+/// Map the tags of the tables in a font file to their data
+fn table_directory(data: &[u8]) -> Result<HashMap<[u8; 4], &[u8]>> {
+    match read_u32(data, 0)? {
+        // TrueType outlines, or `OTTO` for CFF outlines
+        0x0001_0000 | 0x7472_7565 | 0x4F54_544F => {}
+        0x7474_6366 => return Err(FontError::Unsupported("font collection")),
+        _ => return Err(FontError::Unsupported("file format")),
+    }
+    let count = usize::from(read_u16(data, 4)?);
+    let mut tables = HashMap::new();
+    for i in 0..count {
+        let record = 12 + 16 * i;
+        let tag = slice(data, record, 4)?;
+        let offset = read_u32(data, record + 8)? as usize;
+        let length = read_u32(data, record + 12)? as usize;
+        tables.insert(
+            [tag[0], tag[1], tag[2], tag[3]],
+            slice(data, offset, length)?,
+        );
+    }
+    Ok(tables)
+}
+
+/// The advance widths of all glyphs. Glyphs after the last metric have its advance.
+fn parse_hmtx(hmtx: &[u8], metrics_count: usize, glyph_count: usize) -> Result<Vec<u16>> {
+    let mut advances = (0..metrics_count)
+        .map(|i| read_u16(hmtx, 4 * i))
+        .collect::<Result<Vec<_>>>()?;
+    let last = advances.last().copied().unwrap_or(0);
+    advances.resize(glyph_count.max(metrics_count), last);
+    Ok(advances)
+}
+
+/// The typographic family name, or the family name if there isn't one
+fn parse_family_name(name: &[u8]) -> Result<String> {
+    let count = usize::from(read_u16(name, 2)?);
+    let strings = usize::from(read_u16(name, 4)?);
+    let mut names = Vec::new();
+    for i in 0..count {
+        let record = 6 + 12 * i;
+        let platform = read_u16(name, record)?;
+        let name_id = read_u16(name, record + 6)?;
+        if !matches!(name_id, 1 | 16) {
+            continue;
+        }
+        let length = usize::from(read_u16(name, record + 8)?);
+        let offset = strings + usize::from(read_u16(name, record + 10)?);
+        let bytes = slice(name, offset, length)?;
+        let text = match platform {
+            // Unicode and Windows names are UTF-16BE
+            0 | 3 => {
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            // Macintosh names are Mac Roman, which is ASCII for most family names
+            _ => bytes.iter().map(|&byte| char::from(byte)).collect(),
+        };
+        names.push((name_id == 16, text));
+    }
+    names.sort_by_key(|&(typographic, _)| !typographic);
+    names
+        .into_iter()
+        .next()
+        .map(|(_, name)| name)
+        .ok_or(FontError::MissingTable("family name"))
+}
+
+/// The map from characters to glyphs, from a Unicode subtable in format 4 or 12
+fn parse_cmap(cmap: &[u8]) -> Result<HashMap<char, u16>> {
+    let count = usize::from(read_u16(cmap, 2)?);
+    let mut best = None;
+    for i in 0..count {
+        let record = 4 + 8 * i;
+        let encoding = (read_u16(cmap, record)?, read_u16(cmap, record + 2)?);
+        let offset = read_u32(cmap, record + 4)? as usize;
+        let format = read_u16(cmap, offset)?;
+        // Prefer full Unicode subtables to ones for the Basic Multilingual Plane
+        let rank = match (encoding, format) {
+            ((3, 10) | (0, 4) | (0, 6), 12) => 0,
+            ((3, 1) | (0, _), 4) => 1,
+            _ => continue,
+        };
+        if best.is_none_or(|(best_rank, _, _)| rank < best_rank) {
+            best = Some((rank, offset, format));
+        }
+    }
+    let (_, offset, format) = best.ok_or(FontError::Unsupported("character map"))?;
+    let subtable = &cmap[offset..];
+    let mut glyphs = HashMap::new();
+    let mut add = |code: u32, glyph: u16| {
+        if let (Some(c), true) = (char::from_u32(code), glyph != 0) {
+            glyphs.insert(c, glyph);
+        }
+    };
+    if format == 4 {
+        let segments = usize::from(read_u16(subtable, 6)? / 2);
+        let ends = 14;
+        let starts = ends + 2 * segments + 2;
+        let deltas = starts + 2 * segments;
+        let range_offsets = deltas + 2 * segments;
+        for i in 0..segments {
+            let end = read_u16(subtable, ends + 2 * i)?;
+            let start = read_u16(subtable, starts + 2 * i)?;
+            let delta = read_u16(subtable, deltas + 2 * i)?;
+            let range_offset = usize::from(read_u16(subtable, range_offsets + 2 * i)?);
+            for code in start..=end.min(0xFFFE) {
+                let glyph = if range_offset == 0 {
+                    code.wrapping_add(delta)
+                } else {
+                    // The offset is relative to where it's stored
+                    let index =
+                        range_offsets + 2 * i + range_offset + 2 * usize::from(code - start);
+                    match read_u16(subtable, index)? {
+                        0 => 0,
+                        glyph => glyph.wrapping_add(delta),
+                    }
+                };
+                add(u32::from(code), glyph);
+            }
+        }
+    } else {
+        let groups = read_u32(subtable, 12)? as usize;
+        for i in 0..groups {
+            let group = 16 + 12 * i;
+            let start = read_u32(subtable, group)?;
+            let end = read_u32(subtable, group + 4)?.min(0x10FFFF);
+            let start_glyph = read_u32(subtable, group + 8)?;
+            for code in start..=end {
+                add(code, (start_glyph + code - start) as u16);
+            }
+        }
+    }
+    Ok(glyphs)
+}
+
+/// The pairs of the horizontal format 0 subtables of a `kern` table
+fn parse_kern(kern: &[u8]) -> Result<HashMap<(u16, u16), i16>> {
+    let mut pairs = HashMap::new();
+    // Apple's version 1 tables have a different header
+    if read_u16(kern, 0)? != 0 {
+        return Ok(pairs);
+    }
+    let count = read_u16(kern, 2)?;
+    let mut offset = 4;
+    for _ in 0..count {
+        let length = usize::from(read_u16(kern, offset + 2)?);
+        let coverage = read_u16(kern, offset + 4)?;
+        // Horizontal, format 0, not minimum values or cross-stream
+        if coverage & 0xFF07 == 1 {
+            let pair_count = usize::from(read_u16(kern, offset + 6)?);
+            for i in 0..pair_count {
+                let pair = offset + 14 + 6 * i;
+                let glyphs = (read_u16(kern, pair)?, read_u16(kern, pair + 2)?);
+                pairs.insert(glyphs, read_i16(kern, pair + 4)?);
+            }
+        }
+        offset += length;
+    }
+    Ok(pairs)
+}
+
+/// The offset of each glyph in the `glyf` table, and of the end of the last glyph
+fn parse_loca(loca: &[u8], long_offsets: bool, glyph_count: usize) -> Result<Vec<usize>> {
+    (0..=glyph_count)
+        .map(|i| match long_offsets {
+            true => read_u32(loca, 4 * i).map(|offset| offset as usize),
+            // Short offsets are divided by 2
+            false => read_u16(loca, 2 * i).map(|offset| 2 * usize::from(offset)),
+        })
+        .collect()
+}
+
+/// An affine transform `[a, b, c, d, e, f]`, which maps (x, y) to
+/// (a * x + c * y + e, b * x + d * y + f)
+type Transform = [f32; 6];
+
+const IDENTITY: Transform = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+fn transform_point(t: Transform, x: f32, y: f32) -> Point {
+    Point {
+        x: t[0] * x + t[2] * y + t[4],
+        y: t[1] * x + t[3] * y + t[5],
+    }
+}
+
+/// The transform that applies `inner`, then `outer`
+fn compose(outer: Transform, inner: Transform) -> Transform {
+    let origin = transform_point(outer, inner[4], inner[5]);
+    [
+        outer[0] * inner[0] + outer[2] * inner[1],
+        outer[1] * inner[0] + outer[3] * inner[1],
+        outer[0] * inner[2] + outer[2] * inner[3],
+        outer[1] * inner[2] + outer[3] * inner[3],
+        origin.x,
+        origin.y,
+    ]
+}
+
+fn midpoint(a: Point, b: Point) -> Point {
+    Point {
+        x: 0.5 * (a.x + b.x),
+        y: 0.5 * (a.y + b.y),
+    }
+}
+
+/// Add the outline of a TrueType glyph, transformed by `transform`. Composite glyphs add the
+/// outlines of their components.
+///
+/// ref: https://learn.microsoft.com/en-us/typography/opentype/spec/glyf
+fn glyf_outline(
+    glyf: &[u8],
+    loca: &[usize],
+    glyph: u16,
+    transform: Transform,
+    depth: usize,
+    segments: &mut Vec<Segment>,
+) -> Result<()> {
+    let index = usize::from(glyph);
+    let (start, end) = match (loca.get(index), loca.get(index + 1)) {
+        (Some(&start), Some(&end)) if start < end => (start, end),
+        // Glyphs without data, like spaces, have no outline
+        _ => return Ok(()),
+    };
+    let data = slice(glyf, start, end - start)?;
+    let contour_count = read_i16(data, 0)?;
+    if contour_count < 0 {
+        return composite_outline(glyf, loca, data, transform, depth, segments);
+    }
+
+    let contour_count = contour_count as usize;
+    let end_points = (0..contour_count)
+        .map(|i| read_u16(data, 10 + 2 * i).map(usize::from))
+        .collect::<Result<Vec<_>>>()?;
+    let point_count = end_points.iter().max().map_or(0, |&last| last + 1);
+    let instructions = usize::from(read_u16(data, 10 + 2 * contour_count)?);
+    let mut offset = 12 + 2 * contour_count + instructions;
+    let byte = |offset: usize| data.get(offset).copied().ok_or(FontError::Truncated);
+
+    // Each flag may be repeated, to save space
+    let mut flags = Vec::with_capacity(point_count);
+    while flags.len() < point_count {
+        let flag = byte(offset)?;
+        offset += 1;
+        let repeat = if flag & 8 != 0 {
+            offset += 1;
+            byte(offset - 1)?
+        } else {
+            0
+        };
+        for _ in 0..=repeat {
+            flags.push(flag);
+        }
+    }
+    flags.truncate(point_count);
+
+    // Coordinates are deltas from the previous point: a byte with its sign in the flags, or the
+    // same as before, or a 16-bit value
+    let mut coordinates = |short: u8, same_or_positive: u8| -> Result<Vec<f32>> {
+        let mut value = 0;
+        flags
+            .iter()
+            .map(|&flag| {
+                if flag & short != 0 {
+                    let delta = i32::from(byte(offset)?);
+                    offset += 1;
+                    value += if flag & same_or_positive != 0 {
+                        delta
+                    } else {
+                        -delta
+                    };
+                } else if flag & same_or_positive == 0 {
+                    value += i32::from(read_i16(data, offset)?);
+                    offset += 2;
+                }
+                Ok(value as f32)
+            })
+            .collect()
+    };
+    let xs = coordinates(2, 16)?;
+    let ys = coordinates(4, 32)?;
+
+    let mut first = 0;
+    for last in end_points {
+        if first <= last {
+            let points: Vec<(Point, bool)> = (first..=last)
+                .map(|i| (transform_point(transform, xs[i], ys[i]), flags[i] & 1 != 0))
+                .collect();
+            contour_segments(&points, segments);
+        }
+        first = last + 1;
+    }
+    Ok(())
+}
+
+/// Add a contour of points that are on or off the curve. Two off-curve points in a row have an
+/// implied on-curve point halfway between them.
+fn contour_segments(points: &[(Point, bool)], segments: &mut Vec<Segment>) {
+    // Start at an on-curve point, or between the first two points if they're all off the curve
+    let (start, rest) = match points.iter().position(|&(_, on_curve)| on_curve) {
+        Some(i) => (points[i].0, [&points[i + 1..], &points[..=i]]),
+        None if points.is_empty() => return,
+        None => (
+            midpoint(points[0].0, points[1 % points.len()].0),
+            [&points[1..], &points[..1]],
+        ),
+    };
+    let mut current = start;
+    let mut control: Option<Point> = None;
+    for &(point, on_curve) in rest.iter().flat_map(|points| points.iter()) {
+        match (on_curve, control) {
+            (true, None) => segments.push(Segment::Line(current, point)),
+            (true, Some(control)) => segments.push(Segment::Quad(current, control, point)),
+            (false, None) => {
+                control = Some(point);
+                continue;
+            }
+            (false, Some(previous)) => {
+                let implied = midpoint(previous, point);
+                segments.push(Segment::Quad(current, previous, implied));
+                current = implied;
+                control = Some(point);
+                continue;
+            }
+        }
+        current = point;
+        control = None;
+    }
+    match control {
+        Some(control) => segments.push(Segment::Quad(current, control, start)),
+        None if current != start => segments.push(Segment::Line(current, start)),
+        None => {}
+    }
+}
+
+/// Add the components of a composite glyph, each with its offset and scale
+fn composite_outline(
+    glyf: &[u8],
+    loca: &[usize],
+    data: &[u8],
+    transform: Transform,
+    depth: usize,
+    segments: &mut Vec<Segment>,
+) -> Result<()> {
+    // Components can't refer back to their glyph, but a broken font could
+    if depth > 8 {
+        return Err(FontError::Unsupported("deeply nested composite glyph"));
+    }
+    let f2dot14 = |offset: usize| read_i16(data, offset).map(|value| f32::from(value) / 16384.0);
+    let mut offset = 10;
+    loop {
+        let flags = read_u16(data, offset)?;
+        let glyph = read_u16(data, offset + 2)?;
+        offset += 4;
+        let (dx, dy) = if flags & 1 != 0 {
+            offset += 4;
+            (read_i16(data, offset - 4)?, read_i16(data, offset - 2)?)
+        } else {
+            offset += 2;
+            let bytes = slice(data, offset - 2, 2)?;
+            (i16::from(bytes[0] as i8), i16::from(bytes[1] as i8))
+        };
+        // Without ARGS_ARE_XY_VALUES, the arguments are points to match up, which we don't
+        // support, so the component goes at the origin
+        let (dx, dy) = match flags & 2 {
+            0 => (0.0, 0.0),
+            _ => (f32::from(dx), f32::from(dy)),
+        };
+        let (a, b, c, d) = if flags & 8 != 0 {
+            let scale = f2dot14(offset)?;
+            offset += 2;
+            (scale, 0.0, 0.0, scale)
+        } else if flags & 0x40 != 0 {
+            offset += 4;
+            (f2dot14(offset - 4)?, 0.0, 0.0, f2dot14(offset - 2)?)
+        } else if flags & 0x80 != 0 {
+            offset += 8;
+            (
+                f2dot14(offset - 8)?,
+                f2dot14(offset - 6)?,
+                f2dot14(offset - 4)?,
+                f2dot14(offset - 2)?,
+            )
+        } else {
+            (1.0, 0.0, 0.0, 1.0)
+        };
+        let component = compose(transform, [a, b, c, d, dx, dy]);
+        glyf_outline(glyf, loca, glyph, component, depth + 1, segments)?;
+        // MORE_COMPONENTS
+        if flags & 0x20 == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// An INDEX of a CFF table: an array of variable-sized objects
+#[derive(Default)]
+struct Index {
+    data: Vec<u8>,
+    /// The offset of each object in `data`, and of the end of the last one
+    offsets: Vec<usize>,
+}
+
+impl Index {
+    /// Parse the INDEX at `offset`, and return it with the offset of its end
+    fn parse(cff: &[u8], offset: usize) -> Result<(Index, usize)> {
+        let count = usize::from(read_u16(cff, offset)?);
+        if count == 0 {
+            return Ok((Index::default(), offset + 2));
+        }
+        let size = usize::from(*cff.get(offset + 2).ok_or(FontError::Truncated)?);
+        if !(1..=4).contains(&size) {
+            return Err(FontError::Unsupported("CFF offset size"));
+        }
+        // Offsets start at 1, from the byte before the data
+        let offsets = (0..=count)
+            .map(|i| {
+                let bytes = slice(cff, offset + 3 + size * i, size)?;
+                let offset = bytes
+                    .iter()
+                    .fold(0, |offset, &byte| offset << 8 | usize::from(byte));
+                offset.checked_sub(1).ok_or(FontError::Truncated)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if offsets.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(FontError::Truncated);
+        }
+        let start = offset + 3 + size * (count + 1);
+        let end = start + offsets[count];
+        let data = slice(cff, start, offsets[count])?.to_vec();
+        Ok((Index { data, offsets }, end))
+    }
+
+    fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    fn get(&self, i: usize) -> Option<&[u8]> {
+        Some(&self.data[*self.offsets.get(i)?..*self.offsets.get(i + 1)?])
+    }
+
+    /// The subroutine for an operand of `callsubr` or `callgsubr`, which is biased to make
+    /// better use of the short number encodings
+    fn subroutine(&self, operand: f32) -> Result<&[u8]> {
+        let bias = match self.len() {
+            0..=1239 => 107,
+            1240..=33899 => 1131,
+            _ => 32768,
+        };
+        let index = operand as i32 + bias;
+        match index {
+            0.. => self.get(index as usize).ok_or(FontError::Truncated),
+            _ => Err(FontError::Truncated),
+        }
+    }
+}
+
+/// Parse a CFF DICT: operators and their operands. Two-byte operators are `1200 + second byte`.
+fn parse_dict(data: &[u8]) -> Result<HashMap<u16, Vec<f32>>> {
+    let mut dict = HashMap::new();
+    let mut operands = Vec::new();
+    let mut i = 0;
+    let byte = |i: usize| data.get(i).copied().ok_or(FontError::Truncated);
+    while i < data.len() {
+        let b0 = data[i];
+        i += 1;
+        match b0 {
+            0..=21 => {
+                let operator = match b0 {
+                    12 => {
+                        i += 1;
+                        1200 + u16::from(byte(i - 1)?)
+                    }
+                    _ => u16::from(b0),
+                };
+                dict.insert(operator, std::mem::take(&mut operands));
+            }
+            28 => {
+                operands.push(f32::from(read_i16(data, i)?));
+                i += 2;
+            }
+            29 => {
+                operands.push(read_u32(data, i)? as i32 as f32);
+                i += 4;
+            }
+            // A real number, as decimal nibbles
+            30 => {
+                let mut number = String::new();
+                'nibbles: loop {
+                    let b = byte(i)?;
+                    i += 1;
+                    for nibble in [b >> 4, b & 0xF] {
+                        match nibble {
+                            0..=9 => number.push(char::from(b'0' + nibble)),
+                            0xA => number.push('.'),
+                            0xB => number.push('E'),
+                            0xC => number.push_str("E-"),
+                            0xE => number.push('-'),
+                            0xF => break 'nibbles,
+                            _ => {}
+                        }
+                    }
+                }
+                operands.push(number.parse().unwrap_or(0.0));
+            }
+            32..=246 => operands.push(f32::from(b0) - 139.0),
+            247..=250 => {
+                let b1 = f32::from(byte(i)?);
+                i += 1;
+                operands.push((f32::from(b0) - 247.0) * 256.0 + b1 + 108.0);
+            }
+            251..=254 => {
+                let b1 = f32::from(byte(i)?);
+                i += 1;
+                operands.push(-(f32::from(b0) - 251.0) * 256.0 - b1 - 108.0);
+            }
+            _ => {}
+        }
+    }
+    Ok(dict)
+}
+
+/// The outlines of a CFF table, as Type 2 charstrings
+///
+/// ref: https://adobe-type-tools.github.io/font-tech-notes/pdfs/5176.CFF.pdf
+struct Cff {
+    charstrings: Index,
+    global_subrs: Index,
+    /// The local subroutines of each font DICT. Only CID-keyed fonts have more than one.
+    local_subrs: Vec<Index>,
+    /// The font DICT of each glyph, for CID-keyed fonts
+    fd_select: Vec<u8>,
+}
+
+impl Cff {
+    fn parse(cff: &[u8]) -> Result<Cff> {
+        let header_size = usize::from(*cff.get(2).ok_or(FontError::Truncated)?);
+        let (_names, offset) = Index::parse(cff, header_size)?;
+        let (top_dicts, offset) = Index::parse(cff, offset)?;
+        let (_strings, offset) = Index::parse(cff, offset)?;
+        let (global_subrs, _) = Index::parse(cff, offset)?;
+        let top = parse_dict(top_dicts.get(0).ok_or(FontError::Truncated)?)?;
+        let offset = |dict: &HashMap<u16, Vec<f32>>, operator| {
+            dict.get(&operator)
+                .and_then(|operands| operands.last())
+                .map(|&offset| offset as usize)
+        };
+
+        let charstrings = offset(&top, 17).ok_or(FontError::MissingTable("CFF CharStrings"))?;
+        let (charstrings, _) = Index::parse(cff, charstrings)?;
+        // CID-keyed fonts have an FDArray of font DICTs and an FDSelect, instead of one
+        // Private DICT
+        let (local_subrs, fd_select) = match (offset(&top, 1236), offset(&top, 1237)) {
+            (Some(fd_array), Some(fd_select)) => {
+                let (fd_array, _) = Index::parse(cff, fd_array)?;
+                let local_subrs = (0..fd_array.len())
+                    .map(|i| local_subrs(cff, &parse_dict(fd_array.get(i).unwrap())?))
+                    .collect::<Result<_>>()?;
+                (
+                    local_subrs,
+                    parse_fd_select(cff, fd_select, charstrings.len())?,
+                )
+            }
+            _ => (vec![local_subrs(cff, &top)?], Vec::new()),
+        };
+        Ok(Cff {
+            charstrings,
+            global_subrs,
+            local_subrs,
+            fd_select,
+        })
+    }
+
+    fn outline(&self, glyph: u16, segments: &mut Vec<Segment>) -> Result<()> {
+        let glyph = usize::from(glyph);
+        let charstring = self.charstrings.get(glyph).ok_or(FontError::Truncated)?;
+        let font_dict = self.fd_select.get(glyph).map_or(0, |&fd| usize::from(fd));
+        let mut builder = Charstring {
+            global_subrs: &self.global_subrs,
+            local_subrs: self
+                .local_subrs
+                .get(font_dict)
+                .ok_or(FontError::Truncated)?,
+            stack: Vec::new(),
+            stems: 0,
+            width_parsed: false,
+            current: Point::default(),
+            start: Point::default(),
+            segments,
+        };
+        builder.run(charstring, 0)?;
+        builder.close();
+        Ok(())
+    }
+}
+
+/// The local subroutines of the Private DICT of a Top DICT or font DICT, which are at an offset
+/// from the Private DICT
+fn local_subrs(cff: &[u8], dict: &HashMap<u16, Vec<f32>>) -> Result<Index> {
+    let (size, offset) = match dict.get(&18).map(|operands| &operands[..]) {
+        Some(&[size, offset]) => (size as usize, offset as usize),
+        _ => return Ok(Index::default()),
+    };
+    let private = parse_dict(slice(cff, offset, size)?)?;
+    match private.get(&19).and_then(|operands| operands.last()) {
+        Some(&subrs) => Ok(Index::parse(cff, offset + subrs as usize)?.0),
+        None => Ok(Index::default()),
+    }
+}
+
+/// The font DICT of each glyph, from an FDSelect in format 0 or 3
+fn parse_fd_select(cff: &[u8], offset: usize, glyph_count: usize) -> Result<Vec<u8>> {
+    match cff.get(offset) {
+        Some(0) => Ok(slice(cff, offset + 1, glyph_count)?.to_vec()),
+        Some(3) => {
+            let ranges = usize::from(read_u16(cff, offset + 1)?);
+            let mut fd_select = vec![0; glyph_count];
+            for i in 0..ranges {
+                let range = offset + 3 + 3 * i;
+                let first = usize::from(read_u16(cff, range)?);
+                let fd = *cff.get(range + 2).ok_or(FontError::Truncated)?;
+                // The next range's first glyph, or the sentinel after the last range
+                let end = usize::from(read_u16(cff, range + 3)?).min(glyph_count);
+                if let Some(glyphs) = fd_select.get_mut(first..end) {
+                    glyphs.fill(fd);
+                }
+            }
+            Ok(fd_select)
+        }
+        _ => Err(FontError::Unsupported("FDSelect format")),
+    }
+}
+
+/// The state of running a Type 2 charstring. Hints don't affect the outline, but they take up
+/// operands and bytes that have to be skipped.
+///
+/// ref: https://adobe-type-tools.github.io/font-tech-notes/pdfs/5177.Type2.pdf
+struct Charstring<'a> {
+    global_subrs: &'a Index,
+    local_subrs: &'a Index,
+    stack: Vec<f32>,
+    /// The number of stem hints so far, which is how many bits the hint masks have
+    stems: usize,
+    /// Whether the optional width before the first stack-clearing operator has been seen
+    width_parsed: bool,
+    current: Point,
+    /// The start of the current contour
+    start: Point,
+    segments: &'a mut Vec<Segment>,
+}
+
+impl Charstring<'_> {
+    /// Run a charstring or subroutine, and return whether it ended the glyph
+    fn run(&mut self, code: &[u8], depth: usize) -> Result<bool> {
+        if depth > 10 {
+            return Err(FontError::Unsupported("deeply nested subroutines"));
+        }
+        let byte = |i: usize| code.get(i).copied().ok_or(FontError::Truncated);
+        let mut i = 0;
+        while i < code.len() {
+            let b0 = code[i];
+            i += 1;
+            match b0 {
+                28 => {
+                    self.stack.push(f32::from(read_i16(code, i)?));
+                    i += 2;
+                }
+                32..=246 => self.stack.push(f32::from(b0) - 139.0),
+                247..=250 => {
+                    let b1 = f32::from(byte(i)?);
+                    i += 1;
+                    self.stack
+                        .push((f32::from(b0) - 247.0) * 256.0 + b1 + 108.0);
+                }
+                251..=254 => {
+                    let b1 = f32::from(byte(i)?);
+                    i += 1;
+                    self.stack
+                        .push(-(f32::from(b0) - 251.0) * 256.0 - b1 - 108.0);
+                }
+                // A 16.16 fixed-point number
+                255 => {
+                    self.stack.push(read_u32(code, i)? as i32 as f32 / 65536.0);
+                    i += 4;
+                }
+                // callsubr and callgsubr leave the stack to the subroutine
+                10 | 29 => {
+                    let operand = self.stack.pop().ok_or(FontError::Truncated)?;
+                    let subrs = if b0 == 10 {
+                        self.local_subrs
+                    } else {
+                        self.global_subrs
+                    };
+                    if self.run(subrs.subroutine(operand)?, depth + 1)? {
+                        return Ok(true);
+                    }
+                }
+                // return
+                11 => return Ok(false),
+                // endchar
+                14 => {
+                    self.take_width(|n| n == 1 || n == 5);
+                    return Ok(true);
+                }
+                // hintmask and cntrmask, with implied vstem hints before them
+                19 | 20 => {
+                    self.take_width(|n| n % 2 == 1);
+                    self.stems += self.stack.len() / 2;
+                    self.stack.clear();
+                    i += self.stems.div_ceil(8);
+                }
+                12 => {
+                    let b1 = byte(i)?;
+                    i += 1;
+                    self.flex(b1);
+                    self.stack.clear();
+                }
+                _ => {
+                    self.operator(b0);
+                    self.stack.clear();
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Run a one-byte operator on the operands on the stack
+    fn operator(&mut self, operator: u8) {
+        let args = self.stack.clone();
+        match operator {
+            // hstem, vstem, hstemhm, vstemhm
+            1 | 3 | 18 | 23 => {
+                self.take_width(|n| n % 2 == 1);
+                self.stems += self.stack.len() / 2;
+            }
+            // rmoveto, hmoveto, vmoveto
+            21 | 22 | 4 => {
+                let count = if operator == 21 { 2 } else { 1 };
+                self.take_width(|n| n > count);
+                let args = &self.stack[..];
+                let (dx, dy) = match (operator, args) {
+                    (21, &[.., dx, dy]) => (dx, dy),
+                    (22, &[.., dx]) => (dx, 0.0),
+                    (4, &[.., dy]) => (0.0, dy),
+                    _ => (0.0, 0.0),
+                };
+                self.close();
+                self.current = self.offset(dx, dy);
+                self.start = self.current;
+            }
+            // rlineto
+            5 => {
+                for pair in args.chunks_exact(2) {
+                    self.line_to(pair[0], pair[1]);
+                }
+            }
+            // hlineto and vlineto alternate between horizontal and vertical lines
+            6 | 7 => {
+                for (i, &d) in args.iter().enumerate() {
+                    if (i % 2 == 0) == (operator == 6) {
+                        self.line_to(d, 0.0);
+                    } else {
+                        self.line_to(0.0, d);
+                    }
+                }
+            }
+            // rrcurveto
+            8 => {
+                for curve in args.chunks_exact(6) {
+                    self.curve_to(curve);
+                }
+            }
+            // rcurveline
+            24 => {
+                let curves = args.len().saturating_sub(2);
+                for curve in args[..curves].chunks_exact(6) {
+                    self.curve_to(curve);
+                }
+                if let [dx, dy] = args[curves..] {
+                    self.line_to(dx, dy);
+                }
+            }
+            // rlinecurve
+            25 => {
+                let lines = args.len().saturating_sub(6);
+                for pair in args[..lines].chunks_exact(2) {
+                    self.line_to(pair[0], pair[1]);
+                }
+                if args.len() >= 6 {
+                    self.curve_to(&args[lines..]);
+                }
+            }
+            // vvcurveto and hhcurveto: curves that start and end vertically or horizontally,
+            // except that the first may start at an angle
+            26 | 27 => {
+                let (mut first, curves) = match args.len() % 4 {
+                    1 => (args[0], &args[1..]),
+                    _ => (0.0, &args[..]),
+                };
+                for curve in curves.chunks_exact(4) {
+                    if operator == 26 {
+                        self.curve_to(&[first, curve[0], curve[1], curve[2], 0.0, curve[3]]);
+                    } else {
+                        self.curve_to(&[curve[0], first, curve[1], curve[2], curve[3], 0.0]);
+                    }
+                    first = 0.0;
+                }
+            }
+            // vhcurveto and hvcurveto alternate between curves that start vertically and end
+            // horizontally, and the other way around. The last may end at an angle.
+            30 | 31 => {
+                let mut horizontal = operator == 31;
+                let mut i = 0;
+                while i + 4 <= args.len() {
+                    let c = &args[i..i + 4];
+                    let last = if args.len() - i == 5 {
+                        args[i + 4]
+                    } else {
+                        0.0
+                    };
+                    if horizontal {
+                        self.curve_to(&[c[0], 0.0, c[1], c[2], last, c[3]]);
+                    } else {
+                        self.curve_to(&[0.0, c[0], c[1], c[2], c[3], last]);
+                    }
+                    horizontal = !horizontal;
+                    i += 4;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Run a two-byte flex operator. Flex hints can be drawn as their two curves.
+    fn flex(&mut self, operator: u8) {
+        let a = self.stack.clone();
+        match (operator, &a[..]) {
+            // flex
+            (35, &[_, _, _, _, _, _, _, _, _, _, _, _, _]) => {
+                self.curve_to(&a[..6]);
+                self.curve_to(&a[6..12]);
+            }
+            // hflex
+            (34, &[dx1, dx2, dy2, dx3, dx4, dx5, dx6]) => {
+                self.curve_to(&[dx1, 0.0, dx2, dy2, dx3, 0.0]);
+                self.curve_to(&[dx4, 0.0, dx5, -dy2, dx6, 0.0]);
+            }
+            // hflex1
+            (36, &[dx1, dy1, dx2, dy2, dx3, dx4, dx5, dy5, dx6]) => {
+                self.curve_to(&[dx1, dy1, dx2, dy2, dx3, 0.0]);
+                self.curve_to(&[dx4, 0.0, dx5, dy5, dx6, -(dy1 + dy2 + dy5)]);
+            }
+            // flex1: the last point is level with the start in whichever direction it moved
+            // least
+            (37, &[_, _, _, _, _, _, _, _, _, _, d6]) => {
+                let dx: f32 = a[..10].iter().step_by(2).sum();
+                let dy: f32 = a[1..10].iter().step_by(2).sum();
+                let (dx6, dy6) = if dx.abs() > dy.abs() {
+                    (d6, -dy)
+                } else {
+                    (-dx, d6)
+                };
+                self.curve_to(&a[..6]);
+                self.curve_to(&[a[6], a[7], a[8], a[9], dx6, dy6]);
+            }
+            _ => {}
+        }
+    }
+
+    /// Drop the glyph's width, if it's the first operand before the first stack-clearing
+    /// operator and `extra(operand count)` says there's one more operand than the operator
+    /// takes
+    fn take_width(&mut self, extra: impl Fn(usize) -> bool) {
+        if !self.width_parsed && extra(self.stack.len()) {
+            self.stack.remove(0);
+        }
+        self.width_parsed = true;
+    }
+
+    fn offset(&self, dx: f32, dy: f32) -> Point {
+        Point {
+            x: self.current.x + dx,
+            y: self.current.y + dy,
+        }
+    }
+
+    fn line_to(&mut self, dx: f32, dy: f32) {
+        let end = self.offset(dx, dy);
+        self.segments.push(Segment::Line(self.current, end));
+        self.current = end;
+    }
+
+    /// Add a cubic curve from six relative coordinates, each relative to the point before
+    fn curve_to(&mut self, d: &[f32]) {
+        let p1 = self.offset(d[0], d[1]);
+        let p2 = Point {
+            x: p1.x + d[2],
+            y: p1.y + d[3],
+        };
+        let p3 = Point {
+            x: p2.x + d[4],
+            y: p2.y + d[5],
+        };
+        self.segments.push(Segment::Cubic(self.current, p1, p2, p3));
+        self.current = p3;
+    }
+
+    /// Close the current contour, which is implied by the next move or the end of the glyph
+    fn close(&mut self) {
+        if self.current != self.start {
+            self.segments.push(Segment::Line(self.current, self.start));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_dict, relative_weight, weight_preference, Charstring, Font, FontCollection,
+        FontStyle, Index, Point, Segment,
+    };
+    use crate::css;
+    use crate::properties::ComputedStyle;
+    use crate::style::{style_tree, Origin};
+    use crate::{html, media::Device};
+
+    /// The computed style of a `p` with the declarations `css`
+    fn style(css: &str) -> ComputedStyle {
+        let root = html::parse("<p></p>".to_string());
+        let stylesheet = css::parse(format!("p {{ {} }}", css));
+        let styled = style_tree(&root, &[(Origin::Author, &stylesheet)], &Device::default());
+        (*styled.style).clone()
+    }
+
+    /// A face of the fallback font, renamed to pretend it's in another family or has another
+    /// weight and style
+    fn face(family: &str, weight: u16, style: FontStyle) -> Font {
+        let mut font = Font::parse(super::FALLBACK_FONT).unwrap();
+        font.family = family.to_string();
+        font.weight = weight;
+        font.style = style;
+        font
+    }
+
+    #[test]
+    fn test_parse_font() {
+        let font = Font::parse(super::FALLBACK_FONT).unwrap();
+        assert_eq!("DejaVu Sans", font.family);
+        assert_eq!((400, FontStyle::Normal), (font.weight, font.style));
+        assert_eq!(2048.0, font.units_per_em);
+        let (a, v) = (font.glyph('A').unwrap(), font.glyph('V').unwrap());
+        assert_eq!(1401.0, font.advance(a));
+        assert!(font.kerning(a, v) < 0.0);
+        assert!(font.glyph('\u{10FFFF}').is_none());
+        assert!(Font::parse(b"ttcf\0\0").is_err());
+        assert!(Font::parse(&super::FALLBACK_FONT[..100]).is_err());
+    }
+
+    #[test]
+    fn test_text_width() {
+        let fonts = FontCollection::default();
+        let style = style("font-size: 20.48px");
+        // Advances are in 1/2048 em
+        assert_eq!(14.01, round(fonts.text_width("A", &style)));
+        assert!(fonts.text_width("AV", &style) < 2.0 * fonts.text_width("A", &style));
+        assert_eq!(0.0, fonts.text_width("", &style));
+        let metrics = fonts.metrics(&style);
+        assert_eq!(
+            (19.01, 4.83),
+            (round(metrics.ascent), round(metrics.descent))
+        );
+    }
+
+    #[test]
+    fn test_shape() {
+        let fonts = FontCollection::default();
+        let style = style("font-size: 20.48px");
+        let runs = fonts.shape("AV", &style);
+        assert_eq!(1, runs.len());
+        let font = &runs[0].font;
+        let (a, v) = (font.glyph('A').unwrap(), font.glyph('V').unwrap());
+        let glyphs: Vec<(u16, f32)> = runs[0].glyphs.iter().map(|&(g, x)| (g, round(x))).collect();
+        let kerned = (font.advance(a) + font.kerning(a, v)) / 100.0;
+        assert_eq!(vec![(a, 0.0), (v, round(kerned))], glyphs);
+        assert!(fonts.shape("", &style).is_empty());
+    }
+
+    #[test]
+    fn test_glyph_outlines() {
+        let font = Font::parse(super::FALLBACK_FONT).unwrap();
+        let outline = |c| font.outline(font.glyph(c).unwrap());
+        assert!(outline(' ').is_empty());
+
+        // Each contour of `O` is closed, and made of quadratic curves
+        let o = outline('O');
+        assert!(o.iter().all(|segment| matches!(segment, Segment::Quad(..))));
+        let starts: Vec<Point> = o.iter().map(|segment| segment.points()[0]).collect();
+        let ends: Vec<Point> = o
+            .iter()
+            .map(|segment| *segment.points().last().unwrap())
+            .collect();
+        let contour_starts = starts.iter().filter(|start| !ends.contains(start)).count();
+        assert_eq!(0, contour_starts);
+
+        let top = |outline: &[Segment]| {
+            outline
+                .iter()
+                .flat_map(|segment| segment.points())
+                .map(|p| p.y)
+                .fold(f32::NEG_INFINITY, f32::max)
+        };
+        // The cap height of DejaVu Sans is 1493 units
+        assert_eq!(1493.0, top(&outline('A')));
+        // `Ä` is a composite of `A` and a dieresis above it
+        let a_dieresis = outline('Ä');
+        assert!(a_dieresis.len() > outline('A').len());
+        assert!(top(&a_dieresis) > 1493.0);
+        assert!(font.outline(u16::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_cff() {
+        // Two objects, with 1-byte offsets
+        let (index, end) = Index::parse(&[0, 2, 1, 1, 3, 4, 7, 8, 9, 0], 0).unwrap();
+        assert_eq!(
+            (Some(&[7, 8][..]), Some(&[9][..]), 9),
+            (index.get(0), index.get(1), end)
+        );
+        assert!(Index::parse(&[0, 2, 1, 1, 3, 9, 7], 0).is_err());
+
+        // 256 for CharStrings, and 1.5 and -2 for the two-byte operator 12 7
+        let dict = parse_dict(&[28, 1, 0, 17, 30, 0x1A, 0x5F, 137, 12, 7]).unwrap();
+        assert_eq!(Some(&vec![256.0]), dict.get(&17));
+        assert_eq!(Some(&vec![1.5, -2.0]), dict.get(&1207));
+
+        // `50 30 hlineto return`
+        let subrs = Index {
+            data: vec![189, 169, 6, 11],
+            offsets: vec![0, 4],
+        };
+        let mut segments = Vec::new();
+        let mut charstring = Charstring {
+            global_subrs: &Index::default(),
+            local_subrs: &subrs,
+            stack: Vec::new(),
+            stems: 0,
+            width_parsed: false,
+            current: Point::default(),
+            start: Point::default(),
+            segments: &mut segments,
+        };
+        // `100 10 20 rmoveto -107 callsubr -10 -20 -10 -20 hvcurveto endchar`, where 100 is
+        // the width
+        let code = [239, 149, 159, 21, 32, 10, 129, 119, 129, 119, 31, 14];
+        assert!(charstring.run(&code, 0).unwrap());
+        charstring.close();
+        let p = |x, y| Point { x, y };
+        assert_eq!(
+            vec![
+                Segment::Line(p(10.0, 20.0), p(60.0, 20.0)),
+                Segment::Line(p(60.0, 20.0), p(60.0, 50.0)),
+                Segment::Cubic(p(60.0, 50.0), p(50.0, 50.0), p(30.0, 40.0), p(30.0, 20.0)),
+                Segment::Line(p(30.0, 20.0), p(10.0, 20.0)),
+            ],
+            segments
+        );
+    }
+
+    fn round(px: f32) -> f32 {
+        (px * 100.0).round() / 100.0
+    }
+
+    #[test]
+    fn test_font_matching() {
+        let mut fonts = FontCollection::default();
+        fonts.add(face("Test", 300, FontStyle::Normal));
+        fonts.add(face("Test", 700, FontStyle::Normal));
+        fonts.add(face("Test", 400, FontStyle::Oblique));
+        fonts.add(face("Test Serif", 400, FontStyle::Normal));
+        let first = |css: &str| {
+            let font = &fonts.fonts[fonts.fonts_for(&style(css))[0]];
+            (font.family.clone(), font.weight, font.style)
+        };
+        let test = |weight, style| ("Test".to_string(), weight, style);
+        assert_eq!(test(300, FontStyle::Normal), first("font-family: test"));
+        assert_eq!(
+            test(700, FontStyle::Normal),
+            first("font-family: Test; font-weight: 600")
+        );
+        assert_eq!(
+            test(300, FontStyle::Normal),
+            first("font-family: Test; font-weight: 200")
+        );
+        assert_eq!(
+            test(400, FontStyle::Oblique),
+            first("font-family: Test; font-style: italic")
+        );
+        assert_eq!(
+            ("Test Serif".to_string(), 400, FontStyle::Normal),
+            first("font-family: Missing, Test Serif, Test")
+        );
+        assert_eq!(
+            ("Test Serif".to_string(), 400, FontStyle::Normal),
+            first("font-family: \"Test Serif\", serif")
+        );
+        // Generic families without an installed family use the fallback font
+        assert_eq!("DejaVu Sans", first("font-family: monospace").0);
+        // Characters that the first font lacks come from the next one that has them
+        let style = style("font-family: Test");
+        assert_eq!(
+            fonts.text_width("a", &style),
+            fonts.text_width("a", &self::style("font-family: sans-serif"))
+        );
+    }
+
+    #[test]
+    fn test_weights() {
+        // 400 prefers 500, then lighter weights, then heavier ones
+        let mut available = vec![100, 300, 500, 600, 900];
+        available.sort_by_key(|&w| weight_preference(400, w));
+        assert_eq!(vec![500, 300, 100, 600, 900], available);
+        available.sort_by_key(|&w| weight_preference(700, w));
+        assert_eq!(vec![900, 600, 500, 300, 100], available);
+        available.sort_by_key(|&w| weight_preference(200, w));
+        assert_eq!(vec![100, 300, 500, 600, 900], available);
+        assert_eq!(
+            (400, 700, 900),
+            (
+                relative_weight(100, true),
+                relative_weight(400, true),
+                relative_weight(700, true)
+            )
+        );
+        assert_eq!(
+            (100, 400, 700),
+            (
+                relative_weight(400, false),
+                relative_weight(700, false),
+                relative_weight(900, false)
+            )
+        );
+    }
+}
@@ -33,7 +33,7 @@ impl Parser {
             }
             nodes.push(self.parse_node());
         }
-        return nodes;
+        nodes
     }
     ///Parse a single node.
     fn parse_node(&mut self) -> dom::Node {
@@ -65,7 +65,7 @@ impl Parser {
         assert!(self.parse_tag_name() == tag_name);
         assert!(self.consume_char() == '>');
 
-        return dom::elem(tag_name, attrs, children);
+        dom::elem(tag_name, attrs, children)
     }
 
     // Parse a single name="value" pair
@@ -73,7 +73,7 @@ impl Parser {
         let name = self.parse_tag_name();
         assert!(self.consume_char() == '=');
         let value = self.parse_attr_value();
-        return (name, value);
+        (name, value)
     }
 
     // Parse a quoted value
@@ -82,7 +82,7 @@ impl Parser {
         assert!(open_quote == '"' || open_quote == '\'');
         let value = self.consume_while(|c| c != open_quote);
         assert!(self.consume_char() == open_quote);
-        return value;
+        value
     }
 
     //Parse a list of name="value" pairs, separated by whitespace
@@ -96,7 +96,7 @@ impl Parser {
             let (name, value) = self.parse_attr();
             attributes.insert(name, value);
         }
-        return attributes;
+        attributes
     }
 
     /// Parse a tag or attribute name
@@ -127,7 +127,7 @@ impl Parser {
         let (_, cur_char) = iter.next().unwrap();
         let (next_pos, _) = iter.next().unwrap_or((1, ' '));
         self.pos += next_pos;
-        return cur_char;
+        cur_char
     }
 
     /// Read the current character without consuming it.
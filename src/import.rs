@@ -0,0 +1,272 @@
+//! Loading stylesheets and resolving their `@import` rules.
+
+use crate::css::{self, Rule, Stylesheet};
+use crate::media::MediaQueryList;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+/// Reads the source of a stylesheet. This lets tests provide files without a filesystem.
+pub trait StylesheetLoader {
+    fn load(&self, path: &Path) -> io::Result<String>;
+}
+
+/// Loads stylesheets from the local filesystem
+pub struct FileLoader;
+
+impl StylesheetLoader for FileLoader {
+    fn load(&self, path: &Path) -> io::Result<String> {
+        fs::read_to_string(path)
+    }
+}
+
+/// Loads stylesheets from a map of in-memory files
+#[derive(Default)]
+pub struct MemoryLoader {
+    pub files: HashMap<PathBuf, String>,
+}
+
+impl StylesheetLoader for MemoryLoader {
+    fn load(&self, path: &Path) -> io::Result<String> {
+        self.files
+            .get(path)
+            .cloned()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, path.display().to_string()))
+    }
+}
+
+/// Load and parse the stylesheet at `path`, and recursively resolve its `@import` rules.
+///
+/// Imported rules are inserted before the rules of the importing stylesheet, in the order of the
+/// `@import` rules, and only apply if the import's media queries match. Imports are resolved
+/// relative to the importing file. Imports that can't be loaded, or that would import a
+/// stylesheet into itself, are skipped with a warning.
+pub fn load_stylesheet(path: &Path, loader: &dyn StylesheetLoader) -> io::Result<Stylesheet> {
+    let source = loader.load(path)?;
+    let mut rules = Vec::new();
+    let mut importing = vec![normalize(path)];
+    resolve_imports(
+        css::parse_file(source, path),
+        &[],
+        loader,
+        &mut importing,
+        &mut rules,
+    );
+    Ok(Stylesheet {
+        rules,
+        imports: Vec::new(),
+    })
+}
+
+/// Append the rules of `stylesheet`, which is the last file in `importing`, to `rules`,
+/// preceded by the rules of the stylesheets it imports. `media` holds the media queries of the
+/// imports that led to this stylesheet.
+fn resolve_imports(
+    stylesheet: Stylesheet,
+    media: &[MediaQueryList],
+    loader: &dyn StylesheetLoader,
+    importing: &mut Vec<PathBuf>,
+    rules: &mut Vec<Rule>,
+) {
+    let base = importing.last().unwrap().parent().map(Path::to_path_buf);
+    for import in stylesheet.imports {
+        let path = normalize(&base.clone().unwrap_or_default().join(&import.url));
+        if importing.contains(&path) {
+            eprintln!("Warning: skipping cyclic @import of {}", path.display());
+            continue;
+        }
+        let source = match loader.load(&path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("Warning: can't @import {}: {}", path.display(), err);
+                continue;
+            }
+        };
+        let mut import_media = media.to_vec();
+        if !import.media.0.is_empty() {
+            import_media.push(import.media);
+        }
+        let stylesheet = css::parse_file(source, &path);
+        importing.push(path);
+        resolve_imports(stylesheet, &import_media, loader, importing, rules);
+        importing.pop();
+    }
+
+    rules.extend(stylesheet.rules.into_iter().map(|mut rule| {
+        rule.media = media.iter().cloned().chain(rule.media).collect();
+        rule
+    }));
+}
+
+/// Remove `.` components and resolve `..` components without touching the filesystem, so that
+/// different spellings of the same path are recognized when detecting cycles
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::media::Device;
+
+    fn loader(files: &[(&str, &str)]) -> MemoryLoader {
+        MemoryLoader {
+            files: files
+                .iter()
+                .map(|&(path, source)| (PathBuf::from(path), source.to_string()))
+                .collect(),
+        }
+    }
+
+    /// The tag names of the selectors of each rule, in order
+    fn tags(stylesheet: &Stylesheet) -> Vec<String> {
+        stylesheet
+            .rules
+            .iter()
+            .map(|rule| rule.selectors[0].subject().tag_name.clone().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_imports() {
+        let stylesheet = css::parse(String::from(
+            r#"
+            @import "a.css";
+            @import url(b.css) print;
+            @import url( 'c.css' );
+            p { color: red; }
+            @import "ignored.css";
+            "#,
+        ));
+        let urls: Vec<_> = stylesheet.imports.iter().map(|i| i.url.clone()).collect();
+        assert_eq!(vec!["a.css", "b.css", "c.css"], urls);
+        assert_eq!(
+            Some("print"),
+            stylesheet.imports[1].media.0[0].media_type.as_deref()
+        );
+        assert_eq!(1, stylesheet.rules.len());
+    }
+
+    #[test]
+    fn test_invalid_imports() {
+        // Malformed imports are skipped, and the rules after them still load
+        let stylesheet = css::parse(String::from(
+            r#"
+            @import foo.css;
+            @import url(a.css;
+            @import "b.css" print {}
+            @import;
+            @import "c.css";
+            p { color: red; }
+            "#,
+        ));
+        let urls: Vec<_> = stylesheet.imports.iter().map(|i| i.url.clone()).collect();
+        assert_eq!(vec!["c.css"], urls);
+        assert_eq!(1, stylesheet.rules.len());
+
+        // Rules that are skipped or dropped still end the imports
+        for source in [
+            "p { color: red; }",
+            "p { widht: 1px; }",
+            "@media print { }",
+            "@font-face { }",
+        ] {
+            let stylesheet = css::parse(format!("{} @import \"a.css\";", source));
+            assert!(stylesheet.imports.is_empty(), "{}", source);
+        }
+        assert_eq!(
+            1,
+            css::parse("@charset \"utf-8\"; @import \"a.css\";".to_string())
+                .imports
+                .len()
+        );
+    }
+
+    #[test]
+    fn test_import_order_and_relative_paths() {
+        let loader = loader(&[
+            (
+                "css/main.css",
+                "@import 'a.css'; @import 'sub/b.css'; main {}",
+            ),
+            ("css/a.css", "a {}"),
+            ("css/sub/b.css", "@import '../c.css'; b {}"),
+            ("css/c.css", "c {}"),
+        ]);
+        let stylesheet = load_stylesheet(Path::new("css/main.css"), &loader).unwrap();
+        assert_eq!(vec!["a", "c", "b", "main"], tags(&stylesheet));
+        assert!(stylesheet.imports.is_empty());
+        let locations: Vec<_> = stylesheet
+            .rules
+            .iter()
+            .map(|rule| rule.location.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "css/a.css:1:1",
+                "css/c.css:1:1",
+                "css/sub/b.css:1:21",
+                "css/main.css:1:39"
+            ],
+            locations
+        );
+    }
+
+    #[test]
+    fn test_import_cycles() {
+        let loader = loader(&[
+            ("a.css", "@import 'b.css'; a {}"),
+            ("b.css", "@import './a.css'; @import 'b.css'; b {}"),
+        ]);
+        let stylesheet = load_stylesheet(Path::new("a.css"), &loader).unwrap();
+        assert_eq!(vec!["b", "a"], tags(&stylesheet));
+    }
+
+    #[test]
+    fn test_missing_imports() {
+        let loader = loader(&[("a.css", "@import 'missing.css'; a {}")]);
+        let stylesheet = load_stylesheet(Path::new("a.css"), &loader).unwrap();
+        assert_eq!(vec!["a"], tags(&stylesheet));
+        assert!(load_stylesheet(Path::new("missing.css"), &loader).is_err());
+    }
+
+    #[test]
+    fn test_media_conditional_imports() {
+        let loader = loader(&[
+            (
+                "main.css",
+                "@import 'print.css' print; @import 'wide.css' (min-width: 600px);",
+            ),
+            (
+                "print.css",
+                "@import 'nested.css' (min-width: 1px); print {}",
+            ),
+            ("nested.css", "nested {}"),
+            ("wide.css", "@media screen { wide {} }"),
+        ]);
+        let stylesheet = load_stylesheet(Path::new("main.css"), &loader).unwrap();
+        assert_eq!(vec!["nested", "print", "wide"], tags(&stylesheet));
+        assert_eq!(2, stylesheet.rules[0].media.len());
+        assert_eq!(1, stylesheet.rules[1].media.len());
+        assert_eq!(2, stylesheet.rules[2].media.len());
+
+        let screen = Device::default();
+        let applies: Vec<_> = stylesheet
+            .rules
+            .iter()
+            .map(|rule| screen.matches_all(&rule.media))
+            .collect();
+        assert_eq!(vec![false, false, true], applies);
+    }
+}
@@ -1,9 +1,14 @@
-use crate::css::Unit::Px;
-use crate::css::Value::{Keyword, Length};
+use crate::display::{DisplayInside, DisplayInternal, DisplayOutside};
+use crate::dom::NodeType;
+use crate::font::FontCollection;
+use crate::properties::{ComputedStyle, LineHeight, TextAlign};
 use crate::style::{Display, StyledNode};
 use std::default::Default;
+use std::{mem, ptr};
 
-pub use self::BoxType::{AnonymousBlock, BlockNode, InlineNode};
+pub use self::BoxType::{
+    AnonymousBlock, BlockNode, InlineBlockNode, InlineNode, LineBox, TextFragment,
+};
 
 // CSS box model. All sizes are in px
 
@@ -40,69 +45,135 @@ pub struct LayoutBox<'a> {
     pub children: Vec<LayoutBox<'a>>,
 }
 
+/// The kind of a box, from the outer display type of its node. Flex, grid and table layout
+/// aren't implemented, so their containers and table parts lay out their children as blocks.
 pub enum BoxType<'a> {
+    /// A block-level box: `block`, `flow-root`, `list-item`, `flex`, `grid`, `table` and the
+    /// parts of tables
     BlockNode(&'a StyledNode<'a>),
+    /// An inline box, whose contents flow into lines. Once laid out, an inline box that spans
+    /// several lines has a fragment in each of them.
     InlineNode(&'a StyledNode<'a>),
-    AnonymousBlock,
+    /// An inline-level box that lays out its children like a block and is placed in a line as
+    /// a whole: `inline-block`, `inline-flex`, `inline-grid`, `inline-table`
+    InlineBlockNode(&'a StyledNode<'a>),
+    /// A block that holds a run of inline-level children of the node it belongs to. Once laid
+    /// out, its children are line boxes.
+    AnonymousBlock(&'a StyledNode<'a>),
+    /// A line of inline content
+    LineBox,
+    /// The part of a text node that's on one line, with its whitespace collapsed
+    TextFragment(&'a StyledNode<'a>, String),
 }
 
 /// Transform a style tree into a layout tree
-pub fn layout_tree<'a>(node: &'a StyledNode<'a>, mut containing_block: Dimensions)-> LayoutBox<'a>{
+pub fn layout_tree<'a>(
+    node: &'a StyledNode<'a>,
+    mut containing_block: Dimensions,
+    fonts: &FontCollection,
+) -> LayoutBox<'a> {
     //The layout algorithm expects the container height to start at 0
     // TODO Save the initial containing block height, for calculating precent height
     containing_block.content.height = 0.0;
 
     let mut root_box = build_layout_tree(node);
-    root_box.layout(containing_block);
+    root_box.layout(containing_block, fonts);
     root_box
 }
+
 /// Build the tree of LayoutBoxes, but don't perform any layout calculations yet
 fn build_layout_tree<'a>(style_node: &'a StyledNode<'a>) -> LayoutBox<'a> {
-    let mut root = LayoutBox::new(match style_node.display() {
-        Display::Block => BlockNode(style_node),
-        Display::Inline => InlineNode(style_node),
-        Display::None => panic!("Root node has display: none."),
-    });
-
-    //Create the descendant boxes.
-    for child in &style_node.children {
-        match child.display() {
-            Display::Block => root.children.push(build_layout_tree(child)),
-            Display::Inline => root
-                .get_inline_container()
-                .children
-                .push(build_layout_tree(child)),
-            Display::None => {} // Don't lay out nodes with `display: none;`
-        }
-    }
+    let box_type = match (box_type(style_node), style_node.display()) {
+        (Some(box_type), _) => box_type,
+        // The root element can't have `display: contents`; it computes to `block`
+        (None, Display::Contents) => BlockNode(style_node),
+        (None, _) => panic!("Root node has display: none."),
+    };
+    let mut root = LayoutBox::new(box_type);
+    root.add_children(style_node);
     root
 }
 
+/// The box that a node generates, or `None` if it doesn't generate one
+fn box_type<'a>(style_node: &'a StyledNode<'a>) -> Option<BoxType<'a>> {
+    match style_node.display() {
+        Display::Box {
+            outside: DisplayOutside::Inline,
+            inside: DisplayInside::Flow | DisplayInside::Ruby,
+            ..
+        } => Some(InlineNode(style_node)),
+        Display::Box {
+            outside: DisplayOutside::Inline,
+            ..
+        } => Some(InlineBlockNode(style_node)),
+        // We don't support run-in boxes, which become block-level unless they're merged into
+        // the next block
+        Display::Box { .. } => Some(BlockNode(style_node)),
+        // Columns only hold styles for the cells of the column
+        Display::Internal(DisplayInternal::TableColumn | DisplayInternal::TableColumnGroup) => None,
+        Display::Internal(
+            DisplayInternal::RubyBase
+            | DisplayInternal::RubyText
+            | DisplayInternal::RubyBaseContainer
+            | DisplayInternal::RubyTextContainer,
+        ) => Some(InlineNode(style_node)),
+        Display::Internal(_) => Some(BlockNode(style_node)),
+        Display::Contents | Display::None => None,
+    }
+}
+
 impl<'a> LayoutBox<'a> {
     fn new(box_type: BoxType) -> LayoutBox {
         LayoutBox {
-            box_type: box_type,
+            box_type,
             dimensions: Default::default(),
             children: Vec::new(),
         }
     }
 
+    /// Create the boxes of the children of `style_node`. Nodes with `display: contents` don't get
+    /// a box, so their children's boxes go in their place.
+    fn add_children(&mut self, style_node: &'a StyledNode<'a>) {
+        for child in &style_node.children {
+            match box_type(child) {
+                Some(box_type) => {
+                    let block_level = matches!(box_type, BlockNode(_));
+                    let mut child_box = LayoutBox::new(box_type);
+                    child_box.add_children(child);
+                    if block_level {
+                        self.children.push(child_box);
+                    } else {
+                        self.get_inline_container().children.push(child_box);
+                    }
+                }
+                None if child.display() == Display::Contents => self.add_children(child),
+                None => {} // Don't lay out nodes with `display: none;`
+            }
+        }
+    }
+
     fn get_style_node(&self) -> &'a StyledNode<'a> {
         match self.box_type {
-            BlockNode(node) | InlineNode(node) => node,
-            AnonymousBlock => panic!("Anonymous block box has no style node"),
+            BlockNode(node) | InlineNode(node) | InlineBlockNode(node) | TextFragment(node, _) => {
+                node
+            }
+            AnonymousBlock(_) => panic!("Anonymous block box has no style node"),
+            LineBox => panic!("Line box has no style node"),
         }
     }
 
-    fn layout(&mut self, containing_block: Dimensions) {
+    /// Lay out this box and its descendants
+    fn layout(&mut self, containing_block: Dimensions, fonts: &FontCollection) {
         match self.box_type {
-            BlockNode(_) => self.layout_block(containing_block),
-            InlineNode(_) | AnonymousBlock => {} // TODO
+            BlockNode(_) => self.layout_block(containing_block, fonts),
+            AnonymousBlock(_) => self.layout_inline_content(containing_block, true, fonts),
+            // Inline-level boxes are laid out by the anonymous block that holds them
+            InlineNode(_) | InlineBlockNode(_) | LineBox | TextFragment(..) => {}
         }
     }
 
     /// Lay out a block-level element and its descendants
-    fn layout_block(&mut self, containing_block: Dimensions) {
+    fn layout_block(&mut self, containing_block: Dimensions, fonts: &FontCollection) {
         // Child width can depend on parent width, so we need to calculate this box's width before
         // laying  out its children.
         self.calculate_block_width(containing_block);
@@ -111,7 +182,7 @@ impl<'a> LayoutBox<'a> {
         self.calculate_block_position(containing_block);
 
         //Recursively lay out the children of this box.
-        self.layout_block_children();
+        self.layout_block_children(fonts);
 
         // Parent height can depend on child height, so `calculate_height` must be called after
         // children are laid out.
@@ -124,101 +195,77 @@ impl<'a> LayoutBox<'a> {
     ///
     /// Sets the horizontal margin/padding/border dimensions, and the `width`.
     fn calculate_block_width(&mut self, containing_block: Dimensions) {
-        let style = self.get_style_node();
-
-        // `width` has initial value `auto`.
-        let auto = Keyword("auto".to_string());
-        let mut width = style.value("width").unwrap_or(auto.clone());
+        let style = &self.get_style_node().style;
+        let cb_width = containing_block.content.width;
 
-        // margin, border, and padding have initial value 0.
-        let zero = Length(0.0, Px);
+        // `None` stands for `auto`. Percentages refer to the width of the containing block.
+        let width = style.width.resolve(cb_width);
+        let mut margin_left = style.margin_left.resolve(cb_width);
+        let mut margin_right = style.margin_right.resolve(cb_width);
 
-        let mut margin_left = style.lookup("margin-left", "margin", &zero);
-        let mut margin_right = style.lookup("margin-right", "margin", &zero);
+        let border_left = style.border_left_width;
+        let border_right = style.border_right_width;
 
-        let border_left = style.lookup("border-left-width", "border-width", &zero);
-        let border_right = style.lookup("border-right-width", "border-width", &zero);
-
-        let padding_left = style.lookup("padding-left", "padding", &zero);
-        let padding_right = style.lookup("padding-right", "padding", &zero);
+        let padding_left = style.padding_left.resolve(cb_width).unwrap_or(0.0);
+        let padding_right = style.padding_right.resolve(cb_width).unwrap_or(0.0);
 
         let total = sum([
-            &margin_left,
-            &margin_right,
-            &border_left,
-            &border_right,
-            &padding_left,
-            &padding_right,
-            &width,
+            margin_left.unwrap_or(0.0),
+            margin_right.unwrap_or(0.0),
+            border_left,
+            border_right,
+            padding_left,
+            padding_right,
+            width.unwrap_or(0.0),
         ]
         .iter()
-        .map(|v| v.to_px()));
+        .copied());
 
         // If width is not auto and the total is wider than the container, treat auto margins as 0.
-        if width != auto && total > containing_block.content.width {
-            if margin_left == auto {
-                margin_left = Length(0.0, Px);
-            }
-            if margin_right == auto {
-                margin_right = Length(0.0, Px);
-            }
+        if width.is_some() && total > cb_width {
+            margin_left.get_or_insert(0.0);
+            margin_right.get_or_insert(0.0);
         }
 
         // Adjust used values so that the above sum equals `containing_block.width`.
         // Each arm of the `match` should increase the total width by exactly `underflow`,
         // and afterward all values should be absolute lengths in px.
-        let underflow = containing_block.content.width - total;
+        let underflow = cb_width - total;
 
-        match (width == auto, margin_left == auto, margin_right == auto) {
+        let (width, margin_left, margin_right) = match (width, margin_left, margin_right) {
             // If the values are overconstrained, calculate margin_right.
-            (false, false, false) => {
-                margin_right = Length(margin_right.to_px() + underflow, Px);
-            }
+            (Some(width), Some(left), Some(right)) => (width, left, right + underflow),
 
             // If exactly one size is auto, its  used value follows from the equality
-            (false, false, true) => {
-                margin_right = Length(underflow, Px);
-            }
-            (false, true, false) => {
-                margin_left = Length(underflow, Px);
-            }
+            (Some(width), Some(left), None) => (width, left, underflow),
+            (Some(width), None, Some(right)) => (width, underflow, right),
 
             // If width is set to auto, any other auto values become 0
-            (true, _, _) => {
-                if margin_left == auto {
-                    margin_left = Length(0.0, Px);
-                }
-                if margin_right == auto {
-                    margin_right = Length(0.0, Px);
-                }
-
+            (None, left, right) => {
+                let (left, right) = (left.unwrap_or(0.0), right.unwrap_or(0.0));
                 if underflow >= 0.0 {
                     //Expand width to fill the underflow
-                    width = Length(underflow, Px);
+                    (underflow, left, right)
                 } else {
                     //width can't be negative. Adjust the right margin instead
-                    width = Length(0.0, Px);
-                    margin_right = Length(margin_right.to_px() + underflow, Px);
+                    (0.0, left, right + underflow)
                 }
             }
             // If margin-left and margin-right are both auto, their used values are equal.
-            (false, true, true) => {
-                margin_left = Length(underflow / 2.0, Px);
-                margin_right = Length(underflow / 2.0, Px);
-            }
-        }
+            (Some(width), None, None) => (width, underflow / 2.0, underflow / 2.0),
+        };
 
         let d = &mut self.dimensions;
-        d.content.width = width.to_px();
+        d.content.width = width;
 
-        d.padding.left = padding_left.to_px();
-        d.padding.right = padding_right.to_px();
+        d.padding.left = padding_left;
+        d.padding.right = padding_right;
 
-        d.border.left = border_left.to_px();
-        d.border.right = border_right.to_px();
+        d.border.left = border_left;
+        d.border.right = border_right;
 
-        d.margin.left = margin_left.to_px();
-        d.margin.right = margin_right.to_px();
+        d.margin.left = margin_left;
+        d.margin.right = margin_right;
     }
 
     /// Finish calculating the block's edge sizes, and position it within its containing block.
@@ -227,25 +274,19 @@ impl<'a> LayoutBox<'a> {
     ///
     /// Sets the vertical margin/padding/border dimensions, and the `x`, `y` values.
     fn calculate_block_position(&mut self, containing_block: Dimensions) {
-        let style = self.get_style_node();
+        let style = &self.get_style_node().style;
+        let cb_width = containing_block.content.width;
         let d = &mut self.dimensions;
 
-        // margin, border, and padding have initial value 0
-        let zero = Length(0.0, Px);
-
         // If margin-top or margin-bottom is `auto, the used value is zero
-        d.margin.top = style.lookup("margin-top", "margin", &zero).to_px();
-        d.margin.bottom = style.lookup("margin-bottom", "margin", &zero).to_px();
+        d.margin.top = style.margin_top.resolve(cb_width).unwrap_or(0.0);
+        d.margin.bottom = style.margin_bottom.resolve(cb_width).unwrap_or(0.0);
 
-        d.border.top = style
-            .lookup("border-top-width", "border-width", &zero)
-            .to_px();
-        d.border.bottom = style
-            .lookup("border-bottom-width", "border-width", &zero)
-            .to_px();
+        d.border.top = style.border_top_width;
+        d.border.bottom = style.border_bottom_width;
 
-        d.padding.top = style.lookup("padding-top", "padding", &zero).to_px();
-        d.padding.bottom = style.lookup("padding-bottom", "padding", &zero).to_px();
+        d.padding.top = style.padding_top.resolve(cb_width).unwrap_or(0.0);
+        d.padding.bottom = style.padding_bottom.resolve(cb_width).unwrap_or(0.0);
 
         d.content.x = containing_block.content.x + d.margin.left + d.border.left + d.padding.left;
 
@@ -260,43 +301,698 @@ impl<'a> LayoutBox<'a> {
     /// Lay out the block's children within its content area
     ///
     /// Sets `self.dimensions.height` to the total content height
-    fn layout_block_children(&mut self) {
+    fn layout_block_children(&mut self, fonts: &FontCollection) {
         let d = &mut self.dimensions;
-        for child in &mut self.children {
-            child.layout(*d);
+        for (index, child) in self.children.iter_mut().enumerate() {
+            match child.box_type {
+                // Only the first line of a block is indented
+                AnonymousBlock(_) => child.layout_inline_content(*d, index == 0, fonts),
+                _ => child.layout(*d, fonts),
+            }
             // Increment the height so each child is laid out below the previous one.
-            d.content.height = d.content.height + child.dimensions.margin_box().height;
+            d.content.height += child.dimensions.margin_box().height;
         }
     }
     /// Height of a block-level non-replaced element in normal flow with overflow visible.
     fn calculate_block_height(&mut self) {
         // If the height is set to an explicit length, use that exact length.
         // Otherwise, just keep the value set by `layout_block_children`.
-        if let Some(Length(h, Px)) = self.get_style_node().value("height") {
-            self.dimensions.content.height = h;
+        // The containing block's height isn't known yet, so percentages behave as `auto`.
+        let height = &self.get_style_node().style.height;
+        if !height.has_percentage() {
+            if let Some(height) = height.resolve(0.0) {
+                self.dimensions.content.height = height;
+            }
         }
     }
 
     /// Where a new inline child should go
     fn get_inline_container(&mut self) -> &mut LayoutBox<'a> {
         match self.box_type {
-            InlineNode(_) | AnonymousBlock => self,
-            BlockNode(_) => {
+            InlineNode(_) | AnonymousBlock(_) => self,
+            BlockNode(node) | InlineBlockNode(node) => {
                 // if we've just generated an anonymous block box, keep using it.
                 // Otherwise, create a new one.
                 match self.children.last() {
                     Some(&LayoutBox {
-                        box_type: AnonymousBlock,
+                        box_type: AnonymousBlock(_),
                         ..
                     }) => {}
-                    _ => self.children.push(LayoutBox::new(AnonymousBlock)),
+                    _ => self.children.push(LayoutBox::new(AnonymousBlock(node))),
                 }
                 self.children.last_mut().unwrap()
             }
+            LineBox | TextFragment(..) => panic!("Line boxes are only created by layout"),
+        }
+    }
+
+    /// Lay out the inline content of an anonymous block in line boxes, below the previous
+    /// children of the containing block. `first_line` is whether the first line of the
+    /// anonymous block is the first line of its block, which `text-indent` applies to.
+    ///
+    /// ref: https://www.w3.org/TR/CSS2/visuren.html#inline-formatting
+    fn layout_inline_content(
+        &mut self,
+        containing_block: Dimensions,
+        first_line: bool,
+        fonts: &FontCollection,
+    ) {
+        let style = match self.box_type {
+            AnonymousBlock(node) => &node.style,
+            _ => panic!("Only anonymous blocks hold inline content"),
+        };
+        let cb = containing_block.content;
+        self.dimensions.content = Rect {
+            x: cb.x,
+            y: cb.y + cb.height,
+            width: cb.width,
+            height: 0.0,
+        };
+
+        let mut items = Vec::new();
+        inline_items(mem::take(&mut self.children), cb.width, fonts, &mut items);
+        let indent = if first_line {
+            style.text_indent.resolve(cb.width).unwrap_or(0.0)
+        } else {
+            0.0
+        };
+
+        // The inline boxes that are still open at the end of the previous line
+        let mut open = Vec::new();
+        for (index, line) in break_lines(items, cb.width, indent, fonts)
+            .into_iter()
+            .enumerate()
+        {
+            let indent = if index == 0 { indent } else { 0.0 };
+            let child = match line {
+                Line::Inline(line) => self.layout_line(line, indent, &mut open, fonts),
+                Line::Block(mut block) => {
+                    block.layout(self.dimensions, fonts);
+                    block
+                }
+            };
+            self.dimensions.content.height += child.dimensions.margin_box().height;
+            self.children.push(child);
+        }
+    }
+
+    /// Place the items of a line in a line box below the previous lines of this anonymous
+    /// block. The inline boxes in `open` continue from the previous line, and the ones that
+    /// are still open at the end of this line are left in it.
+    fn layout_line(
+        &self,
+        line: LineItems<'a>,
+        indent: f32,
+        open: &mut Vec<&'a StyledNode<'a>>,
+        fonts: &FontCollection,
+    ) -> LayoutBox<'a> {
+        let style = &self.get_container().style;
+        let d = self.dimensions;
+        let cb_width = d.content.width;
+        let mut line_box = LayoutBox::new(LineBox);
+        line_box.dimensions.content = Rect {
+            x: d.content.x,
+            y: d.content.y + d.content.height,
+            width: cb_width,
+            height: 0.0,
+        };
+
+        let has_content = line.items.iter().any(|(item, _)| item.is_content());
+        let start = d.content.x + indent;
+        let mut x = start;
+        // The fragments of the inline boxes that are open at `x`, innermost last. Boxes that
+        // started on a previous line don't get their left edges again.
+        let mut stack = vec![line_box];
+        stack.extend(
+            open.iter()
+                .map(|&node| inline_fragment(node, cb_width, x, false)),
+        );
+        for (item, width) in line.items {
+            match item {
+                InlineItem::Start(node) => {
+                    x += width;
+                    stack.push(inline_fragment(node, cb_width, x, true));
+                    open.push(node);
+                }
+                InlineItem::End(_) => {
+                    let mut fragment = stack.pop().unwrap();
+                    fragment.dimensions.content.width = x - fragment.dimensions.content.x;
+                    x += width;
+                    stack.last_mut().unwrap().children.push(fragment);
+                    open.pop();
+                }
+                InlineItem::Word(node, _) | InlineItem::Space(node) => {
+                    let text = match item {
+                        InlineItem::Word(_, text) => text,
+                        _ => " ",
+                    };
+                    let parent = stack.last_mut().unwrap();
+                    match parent.children.last_mut() {
+                        // Extend the fragment of the text node that's already in this line
+                        Some(LayoutBox {
+                            box_type: TextFragment(fragment_node, fragment_text),
+                            dimensions,
+                            ..
+                        }) if ptr::eq(*fragment_node, node) => {
+                            fragment_text.push_str(text);
+                            dimensions.content.width += width;
+                        }
+                        _ => {
+                            let mut fragment = LayoutBox::new(TextFragment(node, text.to_string()));
+                            fragment.dimensions.content.x = x;
+                            fragment.dimensions.content.width = width;
+                            parent.children.push(fragment);
+                        }
+                    }
+                    x += width;
+                }
+                InlineItem::Atomic(mut atomic) => {
+                    let dx = x - atomic.dimensions.margin_box().x;
+                    atomic.translate(dx, 0.0);
+                    x += width;
+                    stack.last_mut().unwrap().children.push(atomic);
+                }
+                InlineItem::Block(_) => unreachable!("Block-level boxes get their own lines"),
+            }
+        }
+        // Close the boxes that continue on the next line, without their right edges
+        while stack.len() > 1 {
+            let mut fragment = stack.pop().unwrap();
+            let d = &mut fragment.dimensions;
+            d.content.width = x - d.content.x;
+            d.margin.right = 0.0;
+            d.border.right = 0.0;
+            d.padding.right = 0.0;
+            stack.last_mut().unwrap().children.push(fragment);
+        }
+        let mut line_box = stack.pop().unwrap();
+
+        let free = (cb_width - indent - (x - start)).max(0.0);
+        let dx = match style.text_align {
+            TextAlign::Right | TextAlign::End => free,
+            TextAlign::Center => free / 2.0,
+            _ => 0.0,
+        };
+
+        // The line is tall enough for the strut of its block and the boxes in it, aligned
+        // on their baselines. A line without content takes no space.
+        let top = line_box.dimensions.content.y;
+        let (mut ascent, mut descent) = strut(style, fonts);
+        for child in &line_box.children {
+            child.line_extent(&mut ascent, &mut descent, fonts);
+        }
+        if !has_content {
+            ascent = 0.0;
+            descent = 0.0;
+        }
+        line_box.dimensions.content.height = ascent + descent;
+        for child in &mut line_box.children {
+            child.translate(dx, 0.0);
+            child.align_to_baseline(top + ascent, fonts);
+        }
+        line_box
+    }
+
+    /// The node of the box that this anonymous block belongs to
+    fn get_container(&self) -> &'a StyledNode<'a> {
+        match self.box_type {
+            AnonymousBlock(node) => node,
+            _ => panic!("Only anonymous blocks have a container"),
+        }
+    }
+
+    /// Grow `ascent` and `descent` above and below the baseline to fit this inline-level box
+    fn line_extent(&self, ascent: &mut f32, descent: &mut f32, fonts: &FontCollection) {
+        let (box_ascent, box_descent) = match self.box_type {
+            InlineNode(node) | TextFragment(node, _) => strut(&node.style, fonts),
+            InlineBlockNode(_) => (self.dimensions.margin_box().height, 0.0),
+            _ => return,
+        };
+        *ascent = ascent.max(box_ascent);
+        *descent = descent.max(box_descent);
+        if let InlineNode(_) = self.box_type {
+            for child in &self.children {
+                child.line_extent(ascent, descent, fonts);
+            }
+        }
+    }
+
+    /// Set the vertical position of this inline-level box in a line with its baseline at
+    /// `baseline`
+    fn align_to_baseline(&mut self, baseline: f32, fonts: &FontCollection) {
+        match self.box_type {
+            InlineNode(node) | TextFragment(node, _) => {
+                let metrics = fonts.metrics(&node.style);
+                let (ascent, descent) = (metrics.ascent, metrics.descent);
+                self.dimensions.content.y = baseline - ascent;
+                self.dimensions.content.height = ascent + descent;
+                for child in &mut self.children {
+                    child.align_to_baseline(baseline, fonts);
+                }
+            }
+            // The baseline of an inline-block should be that of its last line box, but we use
+            // its bottom margin edge, as for inline-blocks without lines
+            InlineBlockNode(_) => {
+                let margin_box = self.dimensions.margin_box();
+                self.translate(0.0, baseline - (margin_box.y + margin_box.height));
+            }
+            _ => {}
+        }
+    }
+
+    /// Lay out an inline-block and its descendants with its margin box at the origin. The line
+    /// that it's placed in moves it into place.
+    ///
+    /// ref: https://www.w3.org/TR/CSS2/visudet.html#inlineblock-width
+    fn layout_inline_block(&mut self, cb_width: f32, fonts: &FontCollection) {
+        let style = &self.get_style_node().style;
+        let margin = margin(style, cb_width);
+        let border = border(style);
+        let padding = padding(style, cb_width);
+        let edges = margin.left + margin.right + border.left + border.right;
+        let edges = edges + padding.left + padding.right;
+        // An `auto` width shrinks to fit the content
+        let width = style.width.resolve(cb_width).unwrap_or_else(|| {
+            let (min_content, max_content) = self.content_widths(fonts);
+            min_content.max(cb_width - edges).min(max_content)
+        });
+
+        let d = &mut self.dimensions;
+        d.margin = margin;
+        d.border = border;
+        d.padding = padding;
+        d.content = Rect {
+            x: margin.left + border.left + padding.left,
+            y: margin.top + border.top + padding.top,
+            width,
+            height: 0.0,
+        };
+        self.layout_block_children(fonts);
+        self.calculate_block_height();
+    }
+
+    /// The min-content and max-content widths of the children of a block container, before
+    /// layout: the narrowest it can be without overflowing, and the width it takes without
+    /// breaking lines
+    ///
+    /// ref: https://www.w3.org/TR/css-sizing-3/#intrinsic-sizes
+    fn content_widths(&self, fonts: &FontCollection) -> (f32, f32) {
+        let mut widths = IntrinsicWidths::default();
+        for child in &self.children {
+            match child.box_type {
+                AnonymousBlock(_) => {
+                    child.inline_content_widths(&mut widths, fonts);
+                    widths.end_line();
+                }
+                _ => {
+                    let (min_content, max_content) = child.outer_widths(fonts);
+                    widths.min_content = widths.min_content.max(min_content);
+                    widths.max_content = widths.max_content.max(max_content);
+                }
+            }
+        }
+        (widths.min_content, widths.max_content)
+    }
+
+    /// The intrinsic widths of a block-level box or an inline-block, with its margin box
+    fn outer_widths(&self, fonts: &FontCollection) -> (f32, f32) {
+        let style = &self.get_style_node().style;
+        let (margin, border, padding) = (margin(style, 0.0), border(style), padding(style, 0.0));
+        let edges = margin.left + margin.right + border.left + border.right;
+        let edges = edges + padding.left + padding.right;
+        let (min_content, max_content) = match style.width {
+            ref width if !width.has_percentage() && width.resolve(0.0).is_some() => {
+                let width = width.resolve(0.0).unwrap();
+                (width, width)
+            }
+            _ => self.content_widths(fonts),
+        };
+        (min_content + edges, max_content + edges)
+    }
+
+    /// Add the inline content of this box to `widths`
+    fn inline_content_widths(&self, widths: &mut IntrinsicWidths, fonts: &FontCollection) {
+        for child in &self.children {
+            match child.box_type {
+                InlineNode(node) => match node.node().node_type {
+                    NodeType::Text(ref text) => {
+                        for (space, run) in text_runs(text) {
+                            if space {
+                                widths.add_space(fonts.text_width(" ", &node.style));
+                            } else {
+                                widths.add_word(fonts.text_width(run, &node.style));
+                            }
+                        }
+                    }
+                    NodeType::Element(_) => {
+                        let style = &node.style;
+                        let (margin, border, padding) =
+                            (margin(style, 0.0), border(style), padding(style, 0.0));
+                        widths.line += margin.left + border.left + padding.left;
+                        child.inline_content_widths(widths, fonts);
+                        widths.line += margin.right + border.right + padding.right;
+                    }
+                },
+                InlineBlockNode(_) => {
+                    let (min_content, max_content) = child.outer_widths(fonts);
+                    widths.min_content = widths.min_content.max(min_content);
+                    widths.line += max_content;
+                    widths.space = false;
+                }
+                _ => {
+                    widths.end_line();
+                    let (min_content, max_content) = child.outer_widths(fonts);
+                    widths.min_content = widths.min_content.max(min_content);
+                    widths.max_content = widths.max_content.max(max_content);
+                }
+            }
+        }
+    }
+
+    /// Move this box and its descendants
+    fn translate(&mut self, dx: f32, dy: f32) {
+        self.dimensions.content.x += dx;
+        self.dimensions.content.y += dy;
+        for child in &mut self.children {
+            child.translate(dx, dy);
         }
     }
 }
 
+/// A piece of inline content, before it's broken into lines
+enum InlineItem<'a> {
+    /// The start of an inline box, with its left margin, border and padding
+    Start(&'a StyledNode<'a>),
+    /// The end of an inline box, with its right margin, border and padding
+    End(&'a StyledNode<'a>),
+    /// A run of text without whitespace, which lines don't break inside
+    Word(&'a StyledNode<'a>, &'a str),
+    /// A run of whitespace in text, which collapses to one space, or to nothing at the start
+    /// and end of a line
+    Space(&'a StyledNode<'a>),
+    /// An inline-block, laid out at the origin
+    Atomic(LayoutBox<'a>),
+    /// A block-level box inside an inline box, which goes between lines
+    Block(LayoutBox<'a>),
+}
+
+impl<'a> InlineItem<'a> {
+    /// The horizontal space that the item takes in a line
+    fn width(&self, cb_width: f32, fonts: &FontCollection) -> f32 {
+        match *self {
+            InlineItem::Start(node) => {
+                let style = &node.style;
+                margin(style, cb_width).left
+                    + style.border_left_width
+                    + padding(style, cb_width).left
+            }
+            InlineItem::End(node) => {
+                let style = &node.style;
+                margin(style, cb_width).right
+                    + style.border_right_width
+                    + padding(style, cb_width).right
+            }
+            InlineItem::Word(node, text) => fonts.text_width(text, &node.style),
+            InlineItem::Space(node) => fonts.text_width(" ", &node.style),
+            InlineItem::Atomic(ref atomic) => atomic.dimensions.margin_box().width,
+            InlineItem::Block(_) => 0.0,
+        }
+    }
+
+    /// Is the item something that a line can break before, rather than part of a box's edges
+    /// or whitespace?
+    fn is_content(&self) -> bool {
+        matches!(self, InlineItem::Word(..) | InlineItem::Atomic(_))
+    }
+}
+
+/// Flatten the inline-level boxes of an anonymous block into a sequence of items. Inline-blocks
+/// are laid out, so their widths are known.
+fn inline_items<'a>(
+    boxes: Vec<LayoutBox<'a>>,
+    cb_width: f32,
+    fonts: &FontCollection,
+    items: &mut Vec<InlineItem<'a>>,
+) {
+    for mut child in boxes {
+        match child.box_type {
+            InlineNode(node) => match node.node().node_type {
+                NodeType::Text(ref text) => {
+                    items.extend(text_runs(text).map(|(space, run)| match space {
+                        true => InlineItem::Space(node),
+                        false => InlineItem::Word(node, run),
+                    }));
+                }
+                NodeType::Element(_) => {
+                    items.push(InlineItem::Start(node));
+                    inline_items(child.children, cb_width, fonts, items);
+                    items.push(InlineItem::End(node));
+                }
+            },
+            InlineBlockNode(_) => {
+                child.layout_inline_block(cb_width, fonts);
+                items.push(InlineItem::Atomic(child));
+            }
+            BlockNode(_) => items.push(InlineItem::Block(child)),
+            AnonymousBlock(_) | LineBox | TextFragment(..) => {
+                panic!("Unexpected box in inline content")
+            }
+        }
+    }
+}
+
+/// Split text into runs of whitespace and runs of other characters. The flag is whether the
+/// run is whitespace.
+fn text_runs(text: &str) -> impl Iterator<Item = (bool, &str)> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let space = rest.chars().next()?.is_ascii_whitespace();
+        let end = rest
+            .find(|c: char| c.is_ascii_whitespace() != space)
+            .unwrap_or(rest.len());
+        let (run, tail) = rest.split_at(end);
+        rest = tail;
+        Some((space, run))
+    })
+}
+
+/// The result of breaking inline content into lines
+enum Line<'a> {
+    Inline(LineItems<'a>),
+    Block(LayoutBox<'a>),
+}
+
+/// The items of a line, with their widths
+#[derive(Default)]
+struct LineItems<'a> {
+    items: Vec<(InlineItem<'a>, f32)>,
+    width: f32,
+}
+
+impl<'a> LineItems<'a> {
+    fn push(&mut self, item: InlineItem<'a>, width: f32) {
+        self.items.push((item, width));
+        self.width += width;
+    }
+
+    fn has_content(&self) -> bool {
+        self.items.iter().any(|(item, _)| item.is_content())
+    }
+
+    /// Does a space here collapse into the previous one, or the start of the line?
+    fn collapses_space(&self) -> bool {
+        let last = self
+            .items
+            .iter()
+            .rev()
+            .map(|(item, _)| item)
+            .find(|item| !matches!(item, InlineItem::Start(_) | InlineItem::End(_)));
+        !matches!(last, Some(InlineItem::Word(..) | InlineItem::Atomic(_)))
+    }
+
+    /// Remove the spaces at the end of the line. Inline boxes that start after the last
+    /// content of the line are moved to the returned items, to begin the next line.
+    fn finish(&mut self) -> LineItems<'a> {
+        let end = self
+            .items
+            .iter()
+            .rposition(|(item, _)| item.is_content())
+            .map_or(0, |index| index + 1);
+        let mut next = LineItems::default();
+        for (item, width) in self.items.split_off(end) {
+            match item {
+                InlineItem::Space(_) => {}
+                InlineItem::Start(_) => next.push(item, width),
+                _ if !next.items.is_empty() => next.push(item, width),
+                _ => self.items.push((item, width)),
+            }
+        }
+        self.width = sum(self.items.iter().map(|&(_, width)| width));
+        next
+    }
+}
+
+/// Break inline content into lines that fit in `cb_width`, filling each line with as many items
+/// as fit. The first line is indented by `indent`.
+fn break_lines<'a>(
+    items: Vec<InlineItem<'a>>,
+    cb_width: f32,
+    indent: f32,
+    fonts: &FontCollection,
+) -> Vec<Line<'a>> {
+    let mut lines = Vec::new();
+    let mut line = LineItems::default();
+    for item in items {
+        let width = item.width(cb_width, fonts);
+        let available = if lines.is_empty() {
+            cb_width - indent
+        } else {
+            cb_width
+        };
+        match item {
+            InlineItem::Block(block) => {
+                let next = line.finish();
+                if !line.items.is_empty() {
+                    lines.push(Line::Inline(mem::replace(&mut line, next)));
+                } else {
+                    line = next;
+                }
+                lines.push(Line::Block(block));
+                continue;
+            }
+            InlineItem::Space(_) if line.collapses_space() => continue,
+            InlineItem::Word(..) | InlineItem::Atomic(_)
+                if line.has_content() && line.width + width > available =>
+            {
+                let next = line.finish();
+                lines.push(Line::Inline(mem::replace(&mut line, next)));
+            }
+            _ => {}
+        }
+        line.push(item, width);
+    }
+    let rest = line.finish();
+    line.width += rest.width;
+    line.items.extend(rest.items);
+    if !line.items.is_empty() {
+        lines.push(Line::Inline(line));
+    }
+    lines
+}
+
+/// A fragment of the inline box of `node` with its content starting at `x`. Fragments that
+/// aren't the first of the box don't get its left edges. Inline boxes take no vertical space
+/// for their margins, but their padding and borders are painted around the content area.
+fn inline_fragment<'a>(
+    node: &'a StyledNode<'a>,
+    cb_width: f32,
+    x: f32,
+    first: bool,
+) -> LayoutBox<'a> {
+    let style = &node.style;
+    let mut fragment = LayoutBox::new(InlineNode(node));
+    let d = &mut fragment.dimensions;
+    d.margin.right = margin(style, cb_width).right;
+    d.border = border(style);
+    d.padding = padding(style, cb_width);
+    if first {
+        d.margin.left = margin(style, cb_width).left;
+    } else {
+        d.border.left = 0.0;
+        d.padding.left = 0.0;
+    }
+    d.content.x = x;
+    fragment
+}
+
+/// The min-content and max-content widths of content, as it's added
+#[derive(Default)]
+struct IntrinsicWidths {
+    min_content: f32,
+    max_content: f32,
+    /// The width of the current line, if no lines are broken
+    line: f32,
+    /// Does the current line end with a space?
+    space: bool,
+}
+
+impl IntrinsicWidths {
+    fn add_word(&mut self, width: f32) {
+        self.min_content = self.min_content.max(width);
+        self.line += width;
+        self.space = false;
+    }
+
+    fn add_space(&mut self, width: f32) {
+        if self.line > 0.0 && !self.space {
+            self.line += width;
+            self.space = true;
+        }
+    }
+
+    fn end_line(&mut self) {
+        self.max_content = self.max_content.max(self.line);
+        self.line = 0.0;
+        self.space = false;
+    }
+}
+
+/// The margins of a box, with `auto` as 0 and percentages of the containing block width
+fn margin(style: &ComputedStyle, cb_width: f32) -> EdgeSizes {
+    EdgeSizes {
+        left: style.margin_left.resolve(cb_width).unwrap_or(0.0),
+        right: style.margin_right.resolve(cb_width).unwrap_or(0.0),
+        top: style.margin_top.resolve(cb_width).unwrap_or(0.0),
+        bottom: style.margin_bottom.resolve(cb_width).unwrap_or(0.0),
+    }
+}
+
+fn border(style: &ComputedStyle) -> EdgeSizes {
+    EdgeSizes {
+        left: style.border_left_width,
+        right: style.border_right_width,
+        top: style.border_top_width,
+        bottom: style.border_bottom_width,
+    }
+}
+
+/// The padding of a box, with percentages of the containing block width
+fn padding(style: &ComputedStyle, cb_width: f32) -> EdgeSizes {
+    EdgeSizes {
+        left: style.padding_left.resolve(cb_width).unwrap_or(0.0),
+        right: style.padding_right.resolve(cb_width).unwrap_or(0.0),
+        top: style.padding_top.resolve(cb_width).unwrap_or(0.0),
+        bottom: style.padding_bottom.resolve(cb_width).unwrap_or(0.0),
+    }
+}
+
+/// The used value of `line-height`. `normal` uses the font's recommended line spacing.
+///
+/// ref: https://www.w3.org/TR/CSS2/visudet.html#propdef-line-height
+fn line_height(style: &ComputedStyle, fonts: &FontCollection) -> f32 {
+    match style.line_height {
+        LineHeight::Number(factor) => factor * style.font_size,
+        LineHeight::Length(px) => px,
+        LineHeight::Normal => {
+            let metrics = fonts.metrics(style);
+            metrics.ascent + metrics.descent + metrics.line_gap
+        }
+    }
+}
+
+/// The space that text in `style` needs above and below the baseline: the ascent and descent of
+/// its first available font, with half the leading added to each
+///
+/// ref: https://www.w3.org/TR/CSS2/visudet.html#leading
+fn strut(style: &ComputedStyle, fonts: &FontCollection) -> (f32, f32) {
+    let metrics = fonts.metrics(style);
+    let half_leading = (line_height(style, fonts) - (metrics.ascent + metrics.descent)) / 2.0;
+    (
+        metrics.ascent + half_leading,
+        metrics.descent + half_leading,
+    )
+}
+
 impl Rect {
     pub fn expanded_by(self, edge: EdgeSizes) -> Rect {
         Rect {
@@ -329,3 +1025,223 @@ where
 {
     iter.fold(0., |a, b| a + b)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_layout_tree, layout_tree, AnonymousBlock, BlockNode, Dimensions, InlineBlockNode,
+        InlineNode, LayoutBox, LineBox, TextFragment,
+    };
+    use crate::css;
+    use crate::dom::NodeType;
+    use crate::font::FontCollection;
+    use crate::html;
+    use crate::media::Device;
+    use crate::properties::initial_style;
+    use crate::style::{style_tree, Origin};
+
+    /// The box tree, e.g. `block(div)[anonymous[inline(span)[]]]`
+    fn describe(layout_box: &LayoutBox) -> String {
+        let (kind, node) = match layout_box.box_type {
+            BlockNode(node) => ("block", Some(node)),
+            InlineNode(node) => ("inline", Some(node)),
+            InlineBlockNode(node) => ("inline-block", Some(node)),
+            AnonymousBlock(_) => ("anonymous", None),
+            LineBox => ("line", None),
+            TextFragment(_, ref text) => return format!("{:?}", text),
+        };
+        let name = match node.map(|node| &node.node().node_type) {
+            Some(NodeType::Element(elem)) => format!("({})", elem.tag_name),
+            Some(NodeType::Text(_)) => "(text)".to_string(),
+            None => String::new(),
+        };
+        let children: Vec<String> = layout_box.children.iter().map(describe).collect();
+        format!("{}{}[{}]", kind, name, children.join(", "))
+    }
+
+    fn box_tree(source: &str, css: &str) -> String {
+        let root = html::parse(source.to_string());
+        let stylesheet = css::parse(css.to_string());
+        let styled = style_tree(&root, &[(Origin::Author, &stylesheet)], &Device::default());
+        describe(&build_layout_tree(&styled))
+    }
+
+    #[test]
+    fn test_box_types() {
+        let css = "div, li { display: block; } .ib { display: inline-block; } \
+                   .flex { display: inline flex; } li { display: list-item; } \
+                   table { display: table; } td { display: table-cell; } \
+                   col { display: table-column; } .c { display: contents; } \
+                   .none { display: none; }";
+        assert_eq!(
+            "block(div)[anonymous[inline(b)[], inline-block(i)[], inline-block(u)[]], \
+             block(li)[], block(table)[block(td)[]]]",
+            box_tree(
+                r#"<div><b></b><i class="ib"></i><u class="flex"></u><li></li>
+                   <table><col></col><td></td></table></div>"#,
+                css
+            )
+        );
+        assert_eq!(
+            "block(div)[anonymous[inline(b)[], inline(i)[]], block(li)[], anonymous[inline(u)[]]]",
+            box_tree(
+                r#"<div><b></b><p class="c"><i></i><li></li><s class="none"></s></p><u></u></div>"#,
+                css
+            )
+        );
+        // The root can't be `display: contents`
+        assert_eq!(
+            "block(div)[anonymous[inline(b)[]]]",
+            box_tree(r#"<div class="c"><b></b></div>"#, css)
+        );
+    }
+
+    /// Style and lay out a document in a viewport `width` px wide, and pass the root box to `f`
+    fn with_layout(source: &str, css: &str, width: f32, f: impl FnOnce(&LayoutBox)) {
+        let root = html::parse(source.to_string());
+        let stylesheet = css::parse(css.to_string());
+        let styled = style_tree(&root, &[(Origin::Author, &stylesheet)], &Device::default());
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = width;
+        f(&layout_tree(&styled, viewport, &FontCollection::default()));
+    }
+
+    /// The line boxes in the tree, in order
+    fn lines<'b, 'a>(layout_box: &'b LayoutBox<'a>) -> Vec<&'b LayoutBox<'a>> {
+        match layout_box.box_type {
+            LineBox => vec![layout_box],
+            _ => layout_box.children.iter().flat_map(lines).collect(),
+        }
+    }
+
+    /// The text of a line
+    fn text(layout_box: &LayoutBox) -> String {
+        match layout_box.box_type {
+            TextFragment(_, ref text) => text.clone(),
+            _ => layout_box.children.iter().map(text).collect(),
+        }
+    }
+
+    const CSS: &str = "div { display: block; font-size: 10px; } .ib { display: inline-block; }";
+
+    /// The width of `text` at 10px in the fallback font
+    fn width(text: &str) -> f32 {
+        let mut style = (**initial_style()).clone();
+        style.font_size = 10.0;
+        FontCollection::default().text_width(text, &style)
+    }
+
+    fn assert_close(expected: f32, actual: f32) {
+        assert!(
+            (expected - actual).abs() < 0.01,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    /// The `normal` line height of the fallback font at 10px: its ascent and descent are
+    /// 9.28px and 2.36px
+    const NORMAL: f32 = 11.64;
+
+    #[test]
+    fn test_line_breaking() {
+        let source = "<div>  aaaa  bb\n cc <b>dd</b>ee</div>";
+        with_layout(source, CSS, width("aaaa bb") + 1.0, |root| {
+            let lines = lines(root);
+            let texts: Vec<String> = lines.iter().map(|line| text(line)).collect();
+            assert_eq!(vec!["aaaa bb", "cc ddee"], texts);
+            assert_close(NORMAL, lines[1].dimensions.content.y);
+            assert_close(2.0 * NORMAL, root.dimensions.content.height);
+        });
+        // A word that doesn't fit on its own overflows
+        with_layout("<div>a bbbbbbbbbb c</div>", CSS, 40.0, |root| {
+            let texts: Vec<String> = lines(root).iter().map(|line| text(line)).collect();
+            assert_eq!(vec!["a", "bbbbbbbbbb", "c"], texts);
+        });
+        // Whitespace between blocks takes no space
+        with_layout("<div> <div>a</div> </div>", CSS, 40.0, |root| {
+            assert_close(NORMAL, root.dimensions.content.height);
+        });
+    }
+
+    #[test]
+    fn test_line_height() {
+        let css = "div { display: block; font-size: 10px; line-height: 2; } \
+                   b { font-size: 20px; } i { line-height: 50px; }";
+        with_layout("<div>a<b>b</b></div>", css, 100.0, |root| {
+            // The strut of the block is 13.46px above the baseline and 6.54px below; the text
+            // of `b` needs 26.92px and 13.08px
+            let line = lines(root)[0];
+            assert_close(40.0, line.dimensions.content.height);
+            let b = &line.children[1].dimensions.content;
+            assert_close(26.92 - 18.56, b.y);
+            assert_close(2.0 * (9.28 + 2.36), b.height);
+            assert_close(26.92 - 9.28, line.children[0].dimensions.content.y);
+        });
+        with_layout("<div>a<i>b</i></div>", css, 100.0, |root| {
+            assert_close(50.0, root.dimensions.content.height);
+        });
+    }
+
+    #[test]
+    fn test_inline_box_edges() {
+        let css = format!(
+            "{} b {{ padding-left: 10px; margin-right: 5px; border: 2px solid; }}",
+            CSS
+        );
+        with_layout("<div>a<b>b</b>c</div>", &css, 100.0, |root| {
+            let line = lines(root)[0];
+            let b = &line.children[1];
+            assert_close(width("a") + 12.0, b.dimensions.content.x);
+            assert_close(width("b"), b.dimensions.content.width);
+            assert_eq!(2.0, b.dimensions.border.top);
+            let c = width("a") + 12.0 + width("b") + 7.0;
+            assert_close(c, line.children[2].dimensions.content.x);
+        });
+        // A box split across lines only has its left edges on the first line, and its right
+        // edges on the last
+        let available = width("a bb") + 12.0 + 1.0;
+        with_layout("<div>a <b>bb cc</b></div>", &css, available, |root| {
+            let lines = lines(root);
+            let (first, last) = (&lines[0].children[1], &lines[1].children[0]);
+            assert_eq!("a bb", text(lines[0]));
+            assert_eq!(
+                (10.0, 0.0),
+                (first.dimensions.padding.left, first.dimensions.margin.right)
+            );
+            assert_eq!(
+                (0.0, 5.0),
+                (last.dimensions.padding.left, last.dimensions.margin.right)
+            );
+            assert_eq!(0.0, last.dimensions.content.x);
+        });
+    }
+
+    #[test]
+    fn test_inline_block_and_alignment() {
+        let css = format!(
+            "{} div {{ text-align: center; }} .ib {{ padding: 1px; }}",
+            CSS
+        );
+        with_layout(
+            r#"<div>a <i class="ib">bb cc</i></div>"#,
+            &css,
+            100.0,
+            |root| {
+                let line = lines(root)[0];
+                let ib = &line.children[1];
+                // Shrink to fit: the inline-block is as wide as its text
+                assert_close(width("bb cc"), ib.dimensions.content.width);
+                // "a " and the inline-block with its padding, centered in 100px
+                let left = (100.0 - (width("a ") + width("bb cc") + 2.0)) / 2.0;
+                assert_close(left, line.children[0].dimensions.content.x);
+                assert_close(left + width("a ") + 1.0, ib.dimensions.content.x);
+                // The bottom margin edge of the inline-block sits on the baseline, and the
+                // strut's descent is below it
+                assert_close(NORMAL + 2.0 + 2.36, line.dimensions.content.height);
+                assert_eq!(1.0, ib.dimensions.content.y);
+            },
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! A toy browser engine: HTML and CSS parsing, styling, block layout, and painting to PNG or
+//! PDF.
+
+pub mod bloom;
+pub mod calc;
+pub mod color;
+pub mod coverage;
+pub mod css;
+pub mod display;
+pub mod dom;
+pub mod font;
+pub mod html;
+pub mod import;
+pub mod layout;
+pub mod media;
+pub mod painting;
+pub mod pdf;
+pub mod properties;
+pub mod raster;
+pub mod serialize;
+pub mod style;
@@ -5,21 +5,61 @@ use std::default::Default;
 use std::fs::File;
 use std::io::{BufWriter, Read};
 
-pub mod css;
-pub mod dom;
-pub mod html;
-pub mod layout;
-pub mod painting;
-pub mod style;
-pub mod pdf;
+use browser_in_rust::dom::ElementData;
+use browser_in_rust::serialize::{Format, ToCss};
+use browser_in_rust::{css, font, html, import, layout, media, painting, pdf, style};
 
 fn main() {
     // Parse command-line options:
     let mut opts = getopts::Options::new();
     opts.optopt("h", "html", "HTML document", "FILENAME");
-    opts.optopt("c", "css", "CSS stylesheet", "FILENAME");
+    opts.optmulti("c", "css", "Author CSS stylesheet (repeatable)", "FILENAME");
+    opts.optmulti(
+        "u",
+        "user-css",
+        "User CSS stylesheet (repeatable)",
+        "FILENAME",
+    );
     opts.optopt("o", "output", "Output file", "FILENAME");
     opts.optopt("f", "format", "Output file format", "png| pdf");
+    opts.optopt("W", "width", "Viewport width in px (default 800)", "PX");
+    opts.optopt("H", "height", "Viewport height in px (default 600)", "PX");
+    opts.optopt(
+        "m",
+        "media",
+        "Media type for @media queries",
+        "screen| print",
+    );
+    opts.optopt("", "color-scheme", "Preferred color scheme", "light| dark");
+    opts.optflag(
+        "",
+        "transparent",
+        "Paint PNG output on a transparent background instead of white",
+    );
+    opts.optmulti(
+        "",
+        "fonts",
+        "Directory of TrueType and OpenType fonts (repeatable)",
+        "DIR",
+    );
+    opts.optopt(
+        "",
+        "explain",
+        "Print the cascade of the elements matching a selector",
+        "SELECTOR",
+    );
+    opts.optopt(
+        "",
+        "coverage",
+        "Report which CSS rules and selectors matched no elements",
+        "FILENAME",
+    );
+    opts.optopt(
+        "",
+        "coverage-format",
+        "Coverage report format (default text)",
+        "text| json",
+    );
 
     let matches = opts.parse(std::env::args().skip(1)).unwrap();
     let str_arg = |flag: &str, default: &str| -> String {
@@ -34,19 +74,84 @@ fn main() {
     };
 
     // Read input files:
-    let html = read_source(str_arg("h", "examples/perf-rainbow.html"));  
-    let css = read_source(str_arg("c", "examples/perf-rainbow.css"));
+    let html = read_source(str_arg("h", "examples/perf-rainbow.html"));
+    let mut author_css = matches.opt_strs("c");
+    if author_css.is_empty() {
+        author_css.push("examples/perf-rainbow.css".to_string());
+    }
+    let mut stylesheets = vec![(style::Origin::UserAgent, style::user_agent_stylesheet())];
+    stylesheets.extend(
+        matches
+            .opt_strs("u")
+            .into_iter()
+            .map(|filename| (style::Origin::User, filename))
+            .chain(
+                author_css
+                    .into_iter()
+                    .map(|filename| (style::Origin::Author, filename)),
+            )
+            .map(|(origin, filename)| {
+                let path = std::path::Path::new(&filename);
+                (
+                    origin,
+                    import::load_stylesheet(path, &import::FileLoader).unwrap(),
+                )
+            }),
+    );
 
-    //Since we dont have an actual window, hard-code the "viewport" size
+    //Since we dont have an actual window, the "viewport" size comes from the command line
     let mut viewport: layout::Dimensions = Default::default();
-    viewport.content.width = 800.0;
-    viewport.content.height = 600.0;
+    viewport.content.width = str_arg("W", "800").parse().expect("Invalid viewport width");
+    viewport.content.height = str_arg("H", "600")
+        .parse()
+        .expect("Invalid viewport height");
+
+    let media_type = match &str_arg("m", if png { "screen" } else { "print" })[..] {
+        "screen" => media::MediaType::Screen,
+        "print" => media::MediaType::Print,
+        x => panic!("Unknown media type: {}", x),
+    };
+    let color_scheme = match &str_arg("color-scheme", "light")[..] {
+        "light" => media::ColorScheme::Light,
+        "dark" => media::ColorScheme::Dark,
+        x => panic!("Unknown color scheme: {}", x),
+    };
+    let device = media::Device::new(media_type, &viewport, color_scheme);
 
     //Parsing and rendering;
     let root_node = html::parse(html);
-    let stylesheet = css::parse(css);
-    let style_root = style::style_tree(&root_node, &stylesheet);
-    let layout_root = layout::layout_tree(&style_root, viewport);
+    let stylesheets: Vec<_> = stylesheets
+        .iter()
+        .map(|(origin, stylesheet)| (*origin, stylesheet))
+        .collect();
+    if let Some(selectors) = matches.opt_str("explain") {
+        let selectors = css::parse_selector_list(selectors);
+        let explanations = style::explain(&root_node, &selectors, &stylesheets, &device);
+        print_explanations(&explanations);
+    }
+    let style_root = match matches.opt_str("coverage") {
+        Some(filename) => {
+            let (style_root, coverage) =
+                style::style_tree_with_coverage(&root_node, &stylesheets, &device);
+            let coverage = coverage.without_user_agent();
+            let report = match &str_arg("coverage-format", "text")[..] {
+                "text" => coverage.to_text(),
+                "json" => coverage.to_json(),
+                x => panic!("Unknown coverage format: {}", x),
+            };
+            std::fs::write(&filename, report).unwrap();
+            println!("Saved coverage report as {}", filename);
+            style_root
+        }
+        None => style::style_tree_parallel(&root_node, &stylesheets, &device),
+    };
+    let mut fonts = font::FontCollection::default();
+    for dir in matches.opt_strs("fonts") {
+        fonts
+            .load_directory(std::path::Path::new(&dir))
+            .unwrap_or_else(|err| panic!("Can't load fonts from {}: {}", dir, err));
+    }
+    let layout_root = layout::layout_tree(&style_root, viewport, &fonts);
 
     // Create the output file:
     let filename = str_arg("o", if png { "output.png" } else { "output.pdf" });
@@ -54,15 +159,30 @@ fn main() {
 
     // Write to the file
     let ok = if png {
-        let canvas = painting::paint(&layout_root, viewport.content);
+        let background = if matches.opt_present("transparent") {
+            css::Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            }
+        } else {
+            css::Color {
+                r: 255,
+                g: 255,
+                b: 255,
+                a: 255,
+            }
+        };
+        let canvas = painting::paint(&layout_root, viewport.content, &fonts, background);
         let (w, h) = (canvas.width as u32, canvas.height as u32);
         let img = image::ImageBuffer::from_fn(w, h, move |x, y| {
-            let color = canvas.pixels[(y * w + x) as usize];
+            let color = canvas.color(x as usize, y as usize);
             image::Pixel::from_channels(color.r, color.g, color.b, color.a)
         });
         image::ImageRgba8(img).save(&mut file, image::PNG).is_ok()
     } else {
-        pdf::render(&layout_root, viewport.content, &mut file).is_ok()
+        pdf::render(&layout_root, viewport.content, &fonts, &mut file).is_ok()
     };
     if ok {
         println!("Saved output as {}", filename)
@@ -79,3 +199,56 @@ fn read_source(filename: String) -> String {
         .unwrap();
     str
 }
+
+/// Print each property of each element with the declarations for it, from highest to lowest
+/// precedence, e.g.:
+///
+/// ```text
+/// p.note
+///   color
+///     won         red          .note (0, 1, 0) Author main.css:3:1
+///     overridden  blue         p (0, 0, 1) Author main.css:1:1
+/// ```
+fn print_explanations(explanations: &[style::Explanation]) {
+    if explanations.is_empty() {
+        println!("No elements match");
+    }
+    for explanation in explanations {
+        println!("{}", describe(explanation.element));
+        for (name, entries) in &explanation.properties {
+            println!("  {}", name);
+            for entry in entries {
+                let mut value = entry.value.to_css(Format::Minified);
+                if entry.important {
+                    value += " !important";
+                }
+                let source = match (entry.selector, entry.location) {
+                    (Some(selector), Some(location)) => format!(
+                        "{} {:?} {:?} {}",
+                        selector.to_css(Format::Minified),
+                        entry.specificity,
+                        entry.origin,
+                        location
+                    ),
+                    _ => "style attribute".to_string(),
+                };
+                let status = if entry.won { "won" } else { "overridden" };
+                println!("    {:<11} {:<12} {}", status, value, source);
+            }
+        }
+    }
+}
+
+/// The tag name, id and classes of an element, e.g. `p#intro.note`
+fn describe(elem: &ElementData) -> String {
+    let mut description = elem.tag_name.clone();
+    if let Some(id) = elem.id() {
+        description += &format!("#{}", id);
+    }
+    if let Some(classes) = elem.attributes.get("class") {
+        for class in classes.split_whitespace() {
+            description += &format!(".{}", class);
+        }
+    }
+    description
+}
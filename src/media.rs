@@ -0,0 +1,401 @@
+//! Media queries, and the device they are evaluated against.
+//!
+//! ref: https://www.w3.org/TR/mediaqueries-4/
+
+use crate::css::{LengthContext, Value, DEFAULT_FONT_SIZE};
+use crate::layout::Dimensions;
+
+/// The output device that media queries are evaluated against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Device {
+    pub media_type: MediaType,
+    /// The size of the viewport in px
+    pub width: f32,
+    pub height: f32,
+    pub color_scheme: ColorScheme,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaType {
+    Screen,
+    Print,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorScheme {
+    Light,
+    Dark,
+}
+
+/// A comma-separated list of media queries. It matches if any of its queries match, or if it
+/// is empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQueryList(pub Vec<MediaQuery>);
+
+/// A single media query, e.g. `not screen and (min-width: 600px)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaQuery {
+    /// Was the query preceded by `not`?
+    pub negated: bool,
+    /// The media type, or `None` for `all`
+    pub media_type: Option<String>,
+    pub condition: Option<MediaCondition>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaCondition {
+    Feature(MediaFeature),
+    Not(Box<MediaCondition>),
+    And(Vec<MediaCondition>),
+    Or(Vec<MediaCondition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MediaFeature {
+    /// `(color)`: true if the feature has a non-zero, non-`none` value
+    Boolean(String),
+    /// `(orientation: portrait)`. `min-` and `max-` features are parsed as `Range`s.
+    Plain(String, Value),
+    /// `(width >= 600px)`, with the feature name always on the left
+    Range(String, Comparison, Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+impl Default for Device {
+    fn default() -> Device {
+        Device {
+            media_type: MediaType::Screen,
+            width: 800.0,
+            height: 600.0,
+            color_scheme: ColorScheme::Light,
+        }
+    }
+}
+
+impl Device {
+    /// A device whose viewport is the content area of `viewport`
+    pub fn new(media_type: MediaType, viewport: &Dimensions, color_scheme: ColorScheme) -> Device {
+        Device {
+            media_type,
+            width: viewport.content.width,
+            height: viewport.content.height,
+            color_scheme,
+        }
+    }
+
+    /// Do all of `media` match this device? Used for rules nested in several `@media` blocks.
+    pub fn matches_all(&self, media: &[MediaQueryList]) -> bool {
+        media.iter().all(|list| list.matches(self))
+    }
+
+    /// Lengths in media queries are relative to the initial font size and the viewport
+    fn length_context(&self) -> LengthContext {
+        LengthContext {
+            font_size: DEFAULT_FONT_SIZE,
+            root_font_size: DEFAULT_FONT_SIZE,
+            viewport_width: self.width,
+            viewport_height: self.height,
+            percentage_basis: 0.0,
+        }
+    }
+
+    /// Return the value of a media feature as a number (lengths in px), or `None` if the
+    /// feature is unknown or not numeric
+    fn numeric_feature(&self, name: &str) -> Option<f32> {
+        match name {
+            "width" => Some(self.width),
+            "height" => Some(self.height),
+            "aspect-ratio" => Some(self.width / self.height),
+            // We only render to RGB images, with 8 bits per channel
+            "color" => Some(8.0),
+            "monochrome" | "grid" => Some(0.0),
+            _ => None,
+        }
+    }
+
+    /// Return the value of a discrete media feature, or `None` if it is unknown or numeric
+    fn keyword_feature(&self, name: &str) -> Option<&'static str> {
+        match name {
+            "orientation" if self.height >= self.width => Some("portrait"),
+            "orientation" => Some("landscape"),
+            "prefers-color-scheme" => match self.color_scheme {
+                ColorScheme::Light => Some("light"),
+                ColorScheme::Dark => Some("dark"),
+            },
+            "hover" | "any-hover" | "pointer" | "any-pointer" => Some("none"),
+            "scan" => Some("progressive"),
+            _ => None,
+        }
+    }
+
+    /// Convert a media feature value to a number, or `None` if it isn't numeric
+    fn numeric_value(&self, value: &Value) -> Option<f32> {
+        match value {
+            Value::Length(..) | Value::Calc(_) => Some(value.to_px(&self.length_context())),
+            Value::Number(n) => Some(*n),
+            // A ratio, e.g. `16/9`
+            Value::List(components) => match &components[..] {
+                [Value::Number(a), Value::Delim('/'), Value::Number(b)] => Some(a / b),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl MediaQueryList {
+    pub fn matches(&self, device: &Device) -> bool {
+        self.0.is_empty() || self.0.iter().any(|query| query.matches(device))
+    }
+}
+
+impl MediaQuery {
+    pub fn matches(&self, device: &Device) -> bool {
+        let type_matches = match self.media_type.as_deref() {
+            None | Some("all") => true,
+            Some("screen") => device.media_type == MediaType::Screen,
+            Some("print") => device.media_type == MediaType::Print,
+            // Unknown and deprecated media types never match
+            Some(_) => false,
+        };
+        let condition_matches = self
+            .condition
+            .as_ref()
+            .is_none_or(|condition| condition.matches(device));
+        (type_matches && condition_matches) != self.negated
+    }
+}
+
+impl MediaCondition {
+    pub fn matches(&self, device: &Device) -> bool {
+        match self {
+            MediaCondition::Feature(feature) => feature.matches(device),
+            MediaCondition::Not(condition) => !condition.matches(device),
+            MediaCondition::And(conditions) => conditions.iter().all(|c| c.matches(device)),
+            MediaCondition::Or(conditions) => conditions.iter().any(|c| c.matches(device)),
+        }
+    }
+}
+
+impl MediaFeature {
+    /// Evaluate the feature. Unknown features and invalid values never match.
+    pub fn matches(&self, device: &Device) -> bool {
+        match self {
+            MediaFeature::Boolean(name) => match device.numeric_feature(name) {
+                Some(value) => value != 0.0,
+                None => device
+                    .keyword_feature(name)
+                    .is_some_and(|value| value != "none"),
+            },
+            MediaFeature::Plain(name, value) => match (device.numeric_feature(name), value) {
+                (Some(actual), value) => device
+                    .numeric_value(value)
+                    .is_some_and(|expected| Comparison::Equal.test(actual, expected)),
+                (None, Value::Keyword(expected)) => device
+                    .keyword_feature(name)
+                    .is_some_and(|actual| expected.eq_ignore_ascii_case(actual)),
+                (None, _) => false,
+            },
+            MediaFeature::Range(name, comparison, value) => {
+                match (device.numeric_feature(name), device.numeric_value(value)) {
+                    (Some(actual), Some(expected)) => comparison.test(actual, expected),
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+impl Comparison {
+    /// Compare a feature value (left) with a value from the query (right)
+    fn test(self, actual: f32, expected: f32) -> bool {
+        // Allow for rounding errors, e.g. in ratios
+        let equal = (actual - expected).abs() < 1e-3;
+        match self {
+            Comparison::Equal => equal,
+            Comparison::LessThan => actual < expected && !equal,
+            Comparison::LessThanOrEqual => actual < expected || equal,
+            Comparison::GreaterThan => actual > expected && !equal,
+            Comparison::GreaterThanOrEqual => actual > expected || equal,
+        }
+    }
+
+    /// The comparison with its operands swapped, e.g. `<` for `>`
+    pub fn flip(self) -> Comparison {
+        match self {
+            Comparison::Equal => Comparison::Equal,
+            Comparison::LessThan => Comparison::GreaterThan,
+            Comparison::LessThanOrEqual => Comparison::GreaterThanOrEqual,
+            Comparison::GreaterThan => Comparison::LessThan,
+            Comparison::GreaterThanOrEqual => Comparison::LessThanOrEqual,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css;
+    use crate::css::Unit;
+
+    /// Parse the media query list of `@media <query> { ... }`
+    fn query(source: &str) -> MediaQueryList {
+        let stylesheet = css::parse(format!("@media {} {{ x {{ }} }}", source));
+        stylesheet.rules[0].media[0].clone()
+    }
+
+    fn device(width: f32, height: f32) -> Device {
+        Device {
+            width,
+            height,
+            ..Device::default()
+        }
+    }
+
+    fn matches(source: &str, device: &Device) -> bool {
+        query(source).matches(device)
+    }
+
+    #[test]
+    fn test_parse_media_query() {
+        let feature = |name: &str, comparison, px| {
+            MediaCondition::Feature(MediaFeature::Range(
+                name.to_string(),
+                comparison,
+                Value::Length(px, Unit::Px),
+            ))
+        };
+        assert_eq!(
+            MediaQueryList(vec![
+                MediaQuery {
+                    negated: true,
+                    media_type: Some("screen".to_string()),
+                    condition: Some(MediaCondition::And(vec![
+                        feature("width", Comparison::GreaterThanOrEqual, 600.0),
+                        MediaCondition::Feature(MediaFeature::Plain(
+                            "orientation".to_string(),
+                            Value::Keyword("landscape".to_string())
+                        )),
+                    ])),
+                },
+                MediaQuery {
+                    negated: false,
+                    media_type: None,
+                    condition: Some(MediaCondition::And(vec![
+                        feature("width", Comparison::GreaterThan, 400.0),
+                        feature("width", Comparison::LessThanOrEqual, 700.0),
+                    ])),
+                },
+            ]),
+            query("not screen and (min-width: 600px) and (orientation: landscape), (400px < width <= 700px)")
+        );
+    }
+
+    #[test]
+    fn test_media_types() {
+        let screen = Device::default();
+        let print = Device {
+            media_type: MediaType::Print,
+            ..Device::default()
+        };
+        assert!(matches("all", &screen));
+        assert!(matches("screen", &screen));
+        assert!(!matches("print", &screen));
+        assert!(matches("print", &print));
+        assert!(matches("ONLY print", &print));
+        assert!(!matches("not print", &print));
+        assert!(matches("not print", &screen));
+        assert!(!matches("tv", &screen));
+        assert!(matches("tv, screen", &screen));
+    }
+
+    #[test]
+    fn test_media_features() {
+        let landscape = device(800.0, 600.0);
+        let portrait = device(400.0, 600.0);
+        assert!(matches("(min-width: 600px)", &landscape));
+        assert!(!matches("(min-width: 600px)", &portrait));
+        assert!(matches("(max-width: 25em)", &portrait));
+        assert!(matches("(width: 800px)", &landscape));
+        assert!(matches("(width > 500px)", &landscape));
+        assert!(matches("(500px >= width)", &portrait));
+        assert!(matches("(300px < width < 500px)", &portrait));
+        assert!(!matches("(300px < width < 500px)", &landscape));
+        assert!(matches("(orientation: portrait)", &portrait));
+        assert!(matches("(orientation: landscape)", &landscape));
+        assert!(matches("(aspect-ratio: 4/3)", &landscape));
+        assert!(matches("(min-aspect-ratio: 1/1)", &landscape));
+        assert!(matches("(color)", &landscape));
+        assert!(!matches("(monochrome)", &landscape));
+        assert!(!matches("(unknown-feature: 1)", &landscape));
+        assert!(!matches("(hover)", &landscape));
+    }
+
+    #[test]
+    fn test_media_conditions() {
+        let landscape = device(800.0, 600.0);
+        assert!(matches("not (max-width: 600px)", &landscape));
+        assert!(matches(
+            "(max-width: 600px) or (orientation: landscape)",
+            &landscape
+        ));
+        assert!(!matches(
+            "(max-width: 600px) and (orientation: landscape)",
+            &landscape
+        ));
+        assert!(matches(
+            "screen and ((max-width: 600px) or (min-height: 500px))",
+            &landscape
+        ));
+        assert!(matches("(not (color)) or (width >= 800px)", &landscape));
+    }
+
+    #[test]
+    fn test_prefers_color_scheme() {
+        let dark = Device {
+            color_scheme: ColorScheme::Dark,
+            ..Device::default()
+        };
+        assert!(matches("(prefers-color-scheme: dark)", &dark));
+        assert!(!matches("(prefers-color-scheme: light)", &dark));
+        assert!(matches("(prefers-color-scheme: light)", &Device::default()));
+    }
+
+    #[test]
+    fn test_invalid_media_queries() {
+        let not_all = MediaQuery {
+            negated: true,
+            media_type: Some("all".to_string()),
+            condition: None,
+        };
+        // Only the invalid query in the list is dropped
+        let list = query("screen, (min-resolution: 2dppx)");
+        assert_eq!(not_all, list.0[1]);
+        assert!(list.matches(&Device::default()));
+
+        for source in [
+            "(width: 600px) and (color) or (monochrome)",
+            "screen and",
+            "(width <)",
+            "(width: 600px",
+            "screen print",
+        ] {
+            let list = query(source);
+            assert_eq!(vec![not_all.clone()], list.0, "{}", source);
+            assert!(!list.matches(&Device::default()));
+        }
+
+        // The rest of the stylesheet still loads
+        let stylesheet = css::parse("@media (width <) { a { } } b { }".to_string());
+        assert_eq!(2, stylesheet.rules.len());
+        assert!(css::parse("@media screen and".to_string()).rules.is_empty());
+    }
+}
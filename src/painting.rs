@@ -1,15 +1,92 @@
-use crate::css::{Color, Value};
-use crate::layout::{AnonymousBlock, BlockNode, InlineNode, LayoutBox, Rect};
+use crate::css::Color;
+use crate::font::{Font, FontCollection, Point};
+use crate::layout::{
+    AnonymousBlock, BlockNode, InlineBlockNode, InlineNode, LayoutBox, LineBox, Rect, TextFragment,
+};
+use crate::properties::ComputedStyle;
+use crate::raster::Rasterizer;
+use std::sync::Arc;
 
 pub struct Canvas {
-    pub pixels: Vec<Color>,
+    pub pixels: Vec<Pixel>,
     pub width: usize,
     pub height: usize,
 }
-///Paint a tree of LayoutBoxes to an array of pixels
-pub fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
-    let display_list = build_display_list(layout_root);
-    let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize);
+
+/// A color with premultiplied alpha: the channels are from 0 to 1, and already multiplied by
+/// the alpha. Compositing is simpler this way, and transparent pixels have no color to bleed
+/// into their neighbors.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Pixel {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl From<Color> for Pixel {
+    fn from(color: Color) -> Pixel {
+        let a = f32::from(color.a) / 255.0;
+        let channel = |value: u8| f32::from(value) / 255.0 * a;
+        Pixel {
+            r: channel(color.r),
+            g: channel(color.g),
+            b: channel(color.b),
+            a,
+        }
+    }
+}
+
+impl Pixel {
+    /// The color, with straight alpha
+    pub fn to_color(self) -> Color {
+        let channel = |value: f32| match self.a {
+            0.0 => 0,
+            a => (value / a * 255.0).round().clamp(0.0, 255.0) as u8,
+        };
+        Color {
+            r: channel(self.r),
+            g: channel(self.g),
+            b: channel(self.b),
+            a: (self.a * 255.0).round() as u8,
+        }
+    }
+
+    /// The pixel with its opacity multiplied by `factor`, e.g. the coverage of a shape
+    fn scale(self, factor: f32) -> Pixel {
+        Pixel {
+            r: self.r * factor,
+            g: self.g * factor,
+            b: self.b * factor,
+            a: self.a * factor,
+        }
+    }
+
+    /// This pixel painted over `destination`, which shows through as much as this is
+    /// transparent
+    ///
+    /// ref: https://www.w3.org/TR/compositing-1/#porterduffcompositingoperators_srcover
+    fn over(self, destination: Pixel) -> Pixel {
+        let rest = 1.0 - self.a;
+        Pixel {
+            r: self.r + destination.r * rest,
+            g: self.g + destination.g * rest,
+            b: self.b + destination.b * rest,
+            a: self.a + destination.a * rest,
+        }
+    }
+}
+
+///Paint a tree of LayoutBoxes to an array of pixels, over a `background` color that may be
+///transparent
+pub fn paint(
+    layout_root: &LayoutBox,
+    bounds: Rect,
+    fonts: &FontCollection,
+    background: Color,
+) -> Canvas {
+    let display_list = build_display_list(layout_root, fonts);
+    let mut canvas = Canvas::new(bounds.width as usize, bounds.height as usize, background);
     for item in display_list {
         canvas.paint_item(&item);
     }
@@ -18,18 +95,17 @@ pub fn paint(layout_root: &LayoutBox, bounds: Rect) -> Canvas {
 
 impl Canvas {
     // Create a blank canvas
-    fn new(width: usize, height: usize) -> Canvas {
-        let white = Color {
-            r: 255,
-            g: 255,
-            b: 255,
-            a: 255,
-        };
-       Canvas {
-           pixels: vec![white; width * height],
-           width: width,
-           height: height,
-       }
+    pub fn new(width: usize, height: usize, background: Color) -> Canvas {
+        Canvas {
+            pixels: vec![Pixel::from(background); width * height],
+            width,
+            height,
+        }
+    }
+
+    /// The color of the pixel at (x, y), with straight alpha as image formats expect
+    pub fn color(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x].to_color()
     }
 
     // color pixels from left to right and top to down
@@ -41,24 +117,84 @@ impl Canvas {
                 let y0 = rect.y.clamp(0.0, self.height as f32) as usize;
                 let x1 = (rect.x + rect.width).clamp(0.0, self.width as f32) as usize;
                 let y1 = (rect.y + rect.height).clamp(0.0, self.height as f32) as usize;
+                // Nothing to paint for empty or inverted rectangles, e.g. with a negative width
+                if x1 <= x0 || y1 <= y0 {
+                    return;
+                }
 
+                let source = Pixel::from(color);
                 for y in y0..y1 {
-                    for x in x0..x1 {
-                        // TODO: alpha compositing with existing pixel
-                        self.pixels[y * self.width + x] = color;
+                    for pixel in &mut self.pixels[y * self.width + x0..y * self.width + x1] {
+                        *pixel = source.over(*pixel);
                     }
                 }
             }
+            DisplayCommand::Text(ref text) => {
+                for glyph in &text.glyphs {
+                    self.paint_glyph(text, glyph);
+                }
+            }
         }
     }
-}
 
-trait Clamp {
-    fn clamp(self, lower: Self, upper: Self) -> Self;
-}
-impl Clamp for f32 {
-    fn clamp(self, lower: f32, upper: f32) -> f32 {
-        self.max(lower).min(upper)
+    /// Rasterize a glyph at its position, and paint its color over the pixels it covers
+    fn paint_glyph(&mut self, text: &Text, glyph: &PositionedGlyph) {
+        // Font units have y up, and the canvas has y down
+        let scale = text.size / text.font.units_per_em();
+        let outline: Vec<_> = text
+            .font
+            .outline(glyph.id)
+            .into_iter()
+            .map(|segment| {
+                segment.map(|p| Point {
+                    x: glyph.x + p.x * scale,
+                    y: glyph.y - p.y * scale,
+                })
+            })
+            .collect();
+        let points: Vec<Point> = outline
+            .iter()
+            .flat_map(|segment| segment.points())
+            .collect();
+        if points.is_empty() {
+            return;
+        }
+        let (x0, y0, x1, y1) = points.iter().fold(
+            (
+                f32::INFINITY,
+                f32::INFINITY,
+                f32::NEG_INFINITY,
+                f32::NEG_INFINITY,
+            ),
+            |(x0, y0, x1, y1), p| (x0.min(p.x), y0.min(p.y), x1.max(p.x), y1.max(p.y)),
+        );
+        let (left, top) = (x0.floor(), y0.floor());
+        if x1 < 0.0 || y1 < 0.0 || left >= self.width as f32 || top >= self.height as f32 {
+            return;
+        }
+
+        // The rasterizer needs 2 px to the right of the outline
+        let width = (x1.ceil() - left) as usize + 2;
+        let height = (y1.ceil() - top) as usize;
+        let source = Pixel::from(text.color);
+        let mut rasterizer = Rasterizer::new(width, height);
+        for segment in outline {
+            rasterizer.add(segment.map(|p| Point {
+                x: p.x - left,
+                y: p.y - top,
+            }));
+        }
+        for (i, coverage) in rasterizer.coverage().into_iter().enumerate() {
+            let x = left as isize + (i % width) as isize;
+            let y = top as isize + (i / width) as isize;
+            if coverage > 0.0
+                && (0..self.width as isize).contains(&x)
+                && (0..self.height as isize).contains(&y)
+            {
+                let pixel = &mut self.pixels[y as usize * self.width + x as usize];
+                *pixel = source.scale(coverage).over(*pixel);
+            }
+        }
     }
 }
 
@@ -67,27 +203,74 @@ type DisplayList = Vec<DisplayCommand>;
 #[derive(Debug)]
 pub enum DisplayCommand {
     SolidColor(Color, Rect),
-    // insert more commands here
+    Text(Text),
 }
 
-pub fn build_display_list(layout_root: &LayoutBox) -> DisplayList {
+/// Glyphs in one font, size and color
+#[derive(Debug)]
+pub struct Text {
+    pub font: Arc<Font>,
+    /// The font size in px
+    pub size: f32,
+    pub color: Color,
+    pub glyphs: Vec<PositionedGlyph>,
+}
+
+/// A glyph and the position of its origin on the baseline, in px
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PositionedGlyph {
+    pub id: u16,
+    pub x: f32,
+    pub y: f32,
+}
+
+pub fn build_display_list(layout_root: &LayoutBox, fonts: &FontCollection) -> DisplayList {
     let mut list = Vec::new();
-    render_layout_box(&mut list, layout_root);
-    return list;
+    render_layout_box(&mut list, layout_root, fonts);
+    list
 }
 
-fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox) {
+fn render_layout_box(list: &mut DisplayList, layout_box: &LayoutBox, fonts: &FontCollection) {
     render_background(list, layout_box);
     render_borders(list, layout_box);
-    // TODO: render text
+    render_text(list, layout_box, fonts);
 
     for child in &layout_box.children {
-        render_layout_box(list, child);
+        render_layout_box(list, child, fonts);
+    }
+}
+
+/// Add the glyphs of a text fragment, on the baseline of its first available font
+fn render_text(list: &mut DisplayList, layout_box: &LayoutBox, fonts: &FontCollection) {
+    let (style, text) = match layout_box.box_type {
+        TextFragment(node, ref text) => (&node.style, text),
+        _ => return,
+    };
+    if style.color.a == 0 {
+        return;
+    }
+    let content = layout_box.dimensions.content;
+    let baseline = content.y + fonts.metrics(style).ascent;
+    for run in fonts.shape(text, style) {
+        list.push(DisplayCommand::Text(Text {
+            font: run.font,
+            size: style.font_size,
+            color: style.color,
+            glyphs: run
+                .glyphs
+                .into_iter()
+                .map(|(id, x)| PositionedGlyph {
+                    id,
+                    x: content.x + x,
+                    y: baseline,
+                })
+                .collect(),
+        }));
     }
 }
 
 fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
-    if let Some(color) = get_color(layout_box, "background") {
+    if let Some(color) = get_color(layout_box, |style| style.background_color) {
         list.push(DisplayCommand::SolidColor(
             color,
             layout_box.dimensions.border_box(),
@@ -96,77 +279,162 @@ fn render_background(list: &mut DisplayList, layout_box: &LayoutBox) {
 }
 
 fn render_borders(list: &mut DisplayList, layout_box: &LayoutBox) {
-    let color = match get_color(layout_box, "border-color") {
-        Some(color) => color,
-        _ => return,
-    };
-
     let d = &layout_box.dimensions;
     let border_box = d.border_box();
 
     // Left border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: d.border.left,
-            height: border_box.height,
-        },
-    ));
-
-    // Right border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: d.border.left,
-            height: border_box.height,
-        },
-    ));
+    if let Some(color) = get_color(layout_box, |style| style.border_left_color) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: d.border.left,
+                height: border_box.height,
+            },
+        ));
+    }
 
     // Right border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x + border_box.width - d.border.right,
-            y: border_box.y,
-            width: d.border.right,
-            height: border_box.height,
-        },
-    ));
+    if let Some(color) = get_color(layout_box, |style| style.border_right_color) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x + border_box.width - d.border.right,
+                y: border_box.y,
+                width: d.border.right,
+                height: border_box.height,
+            },
+        ));
+    }
 
     // Top border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y,
-            width: border_box.width,
-            height: d.border.top,
-        },
-    ));
-
-    //Bottom border
-    list.push(DisplayCommand::SolidColor(
-        color,
-        Rect {
-            x: border_box.x,
-            y: border_box.y + border_box.height - d.border.bottom,
-            width: border_box.width,
-            height: d.border.bottom,
-        },
-    ));
-}
-
-/// Return the specified color for CSS property `name`, or None if no color was specified
-fn get_color(layout_box: &LayoutBox, name: &str) -> Option<Color> {
+    if let Some(color) = get_color(layout_box, |style| style.border_top_color) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y,
+                width: border_box.width,
+                height: d.border.top,
+            },
+        ));
+    }
+
+    // Bottom border
+    if let Some(color) = get_color(layout_box, |style| style.border_bottom_color) {
+        list.push(DisplayCommand::SolidColor(
+            color,
+            Rect {
+                x: border_box.x,
+                y: border_box.y + border_box.height - d.border.bottom,
+                width: border_box.width,
+                height: d.border.bottom,
+            },
+        ));
+    }
+}
+
+/// Return the computed color that `property` picks from the style of a box, or None if it is
+/// fully transparent
+fn get_color(layout_box: &LayoutBox, property: fn(&ComputedStyle) -> Color) -> Option<Color> {
     match layout_box.box_type {
-        BlockNode(style) | InlineNode(style) => match style.value(name) {
-            Some(Value::ColorValue(color)) => Some(color),
-            _ => None,
-        },
-        AnonymousBlock => None,
+        BlockNode(style) | InlineNode(style) | InlineBlockNode(style) => {
+            Some(property(&style.style)).filter(|color| color.a > 0)
+        }
+        AnonymousBlock(_) | LineBox | TextFragment(..) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_display_list, paint, Canvas, DisplayCommand};
+    use crate::css::{self, Color};
+    use crate::font::FontCollection;
+    use crate::html;
+    use crate::layout::{layout_tree, Dimensions, Rect};
+    use crate::media::Device;
+    use crate::style::{style_tree, Origin};
+
+    #[test]
+    fn test_paint_text() {
+        let root = html::parse("<p>Hi</p>".to_string());
+        let stylesheet = css::parse("p { display: block; color: #ff0000 }".to_string());
+        let styled = style_tree(&root, &[(Origin::Author, &stylesheet)], &Device::default());
+        let mut viewport: Dimensions = Default::default();
+        viewport.content.width = 40.0;
+        viewport.content.height = 20.0;
+        let fonts = FontCollection::default();
+        let layout_root = layout_tree(&styled, viewport, &fonts);
+
+        let list = build_display_list(&layout_root, &fonts);
+        let glyphs: Vec<_> = list
+            .iter()
+            .flat_map(|item| match item {
+                DisplayCommand::Text(text) => text.glyphs.clone(),
+                _ => Vec::new(),
+            })
+            .collect();
+        assert_eq!(2, glyphs.len());
+        assert!(glyphs[0].x < glyphs[1].x);
+
+        // Text is drawn in its color, with anti-aliased edges, and the rest stays white
+        let canvas = paint(&layout_root, viewport.content, &fonts, WHITE);
+        let colors: Vec<Color> = canvas.pixels.iter().map(|pixel| pixel.to_color()).collect();
+        let red = rgba(255, 0, 0, 255);
+        assert!(colors.contains(&red));
+        assert!(colors.iter().any(|&color| color != red && color != WHITE));
+        assert!(colors
+            .iter()
+            .all(|color| color.r == 255 && color.g == color.b));
+        assert!(colors[canvas.width * 19..]
+            .iter()
+            .all(|&color| color == WHITE));
+    }
+
+    const WHITE: Color = rgba(255, 255, 255, 255);
+
+    const fn rgba(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    #[test]
+    fn test_compositing() {
+        let rect = |x, width| Rect {
+            x,
+            y: 0.0,
+            width,
+            height: 1.0,
+        };
+        let paint = |background, items: &[DisplayCommand]| {
+            let mut canvas = Canvas::new(3, 1, background);
+            for item in items {
+                canvas.paint_item(item);
+            }
+            (0..3).map(|x| canvas.color(x, 0)).collect::<Vec<_>>()
+        };
+
+        // Half-transparent red over white
+        let red = DisplayCommand::SolidColor(rgba(255, 0, 0, 128), rect(0.0, 2.0));
+        let blue = DisplayCommand::SolidColor(rgba(0, 0, 255, 128), rect(1.0, 2.0));
+        let pink = rgba(255, 127, 127, 255);
+        assert_eq!(vec![pink, pink, WHITE], paint(WHITE, &[red]));
+
+        // On a transparent canvas, colors keep their alpha, and red over blue is partly both
+        let transparent = rgba(0, 0, 0, 0);
+        let red = DisplayCommand::SolidColor(rgba(255, 0, 0, 128), rect(0.0, 2.0));
+        assert_eq!(
+            vec![
+                rgba(255, 0, 0, 128),
+                rgba(170, 0, 85, 192),
+                rgba(0, 0, 255, 128)
+            ],
+            paint(transparent, &[blue, red])
+        );
+        assert_eq!(vec![transparent; 3], paint(transparent, &[]));
+
+        // A rectangle with a negative width paints nothing
+        let inverted = DisplayCommand::SolidColor(rgba(255, 0, 0, 255), rect(2.0, -50.0));
+        assert_eq!(vec![WHITE; 3], paint(WHITE, &[inverted]));
     }
 }
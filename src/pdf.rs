@@ -1,8 +1,7 @@
-
-
+use crate::font::FontCollection;
 use crate::layout::{LayoutBox, Rect};
 use crate::painting::{build_display_list, DisplayCommand};
-use std::io::{self, Seek, SeekFrom, Write};
+use std::io::{self, Seek, Write};
 
 fn px_to_pt(value: f32) -> f32 {
     // 96px = 1in = 72pt
@@ -13,9 +12,10 @@ fn px_to_pt(value: f32) -> f32 {
 pub fn render<W: Write + Seek>(
     layout_root: &LayoutBox,
     bounds: Rect,
+    fonts: &FontCollection,
     file: &mut W,
 ) -> io::Result<()> {
-    let display_list = build_display_list(layout_root);
+    let display_list = build_display_list(layout_root, fonts);
     let mut pdf = Pdf::new(file)?;
     // We map CSS pt to Poscript points (which is the default length unit in PDF).
     pdf.render_page(px_to_pt(bounds.width), px_to_pt(bounds.height), |output| {
@@ -43,6 +43,8 @@ fn render_item<W: Write>(item: &DisplayCommand, output: &mut W) -> io::Result<()
                 rect.height
             )
         }
+        // FIXME: text needs the fonts embedded in the PDF
+        DisplayCommand::Text(_) => Ok(()),
     }
 }
 
@@ -70,7 +72,7 @@ impl<'a, W: Write + Seek> Pdf<'a, W> {
 
     /// Return the current read/write position in the output file.
     fn tell(&mut self) -> io::Result<u64> {
-        self.output.seek(SeekFrom::Current(0))
+        self.output.stream_position()
     }
 
     fn render_page<F>(&mut self, width: f32, height: f32, render_contents: F) -> io::Result<()>
@@ -183,4 +185,4 @@ impl<'a, W: Write + Seek> Pdf<'a, W> {
         writeln!(self.output, "%%EOF")?;
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,1010 @@
+//! The registry of the CSS properties we support: the values they accept, whether they are
+//! inherited, their initial values, how they animate, and the typed `ComputedStyle` that holds
+//! their computed values.
+
+use crate::calc::{CalcNode, CalcType};
+use crate::css::{self, Color, LengthContext, Unit, Value, DEFAULT_FONT_SIZE};
+pub use crate::display::Display;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+
+pub struct Property {
+    pub name: &'static str,
+    /// Does the property take its parent's computed value when it isn't specified?
+    pub inherited: bool,
+    /// The initial value, as CSS text
+    pub initial: &'static str,
+    /// The values the property accepts, besides CSS-wide keywords
+    pub grammar: Grammar,
+    pub animation: Animation,
+}
+
+/// A value grammar, e.g. `auto | <length-percentage>`
+///
+/// ref: https://www.w3.org/TR/css-values-4/#value-defs
+#[derive(Debug, Clone, Copy)]
+pub enum Grammar {
+    /// One of these keywords, in any case
+    Keywords(&'static [&'static str]),
+    /// A length, `0`, or a `calc()` of lengths
+    Length,
+    /// A length or a percentage, or a `calc()` of them
+    LengthPercentage,
+    Number,
+    /// A color, or `currentcolor`
+    Color,
+    Or(&'static Grammar, &'static Grammar),
+    /// Values of the grammar that aren't negative. Math functions aren't checked: their results
+    /// are clamped once computed instead.
+    NonNegative(&'static Grammar),
+    /// A number from `min` to `max`, inclusive. Math functions are clamped once computed.
+    NumberRange(f32, f32),
+    /// One to this many values separated by spaces
+    Repeat(&'static Grammar, usize),
+    /// Values that the function accepts, for grammars that don't fit the others
+    Check(fn(&Value) -> bool),
+    /// Anything. We don't interpret the value.
+    Any,
+}
+
+/// How a property is animated
+///
+/// ref: https://www.w3.org/TR/web-animations-1/#animation-type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Animation {
+    /// The value flips from one to the other halfway through
+    Discrete,
+    /// Values are interpolated, e.g. lengths and colors
+    ByComputedValue,
+}
+
+const LENGTH_PERCENTAGE_OR_AUTO: Grammar =
+    Grammar::Or(&Grammar::Keywords(&["auto"]), &Grammar::LengthPercentage);
+const NON_NEGATIVE_LENGTH_PERCENTAGE: Grammar = Grammar::NonNegative(&Grammar::LengthPercentage);
+const SIZE: Grammar = Grammar::Or(
+    &Grammar::Keywords(&["auto"]),
+    &NON_NEGATIVE_LENGTH_PERCENTAGE,
+);
+const BORDER_STYLE: Grammar = Grammar::Keywords(&[
+    "none", "hidden", "dotted", "dashed", "solid", "double", "groove", "ridge", "inset", "outset",
+]);
+const BORDER_WIDTH: Grammar = Grammar::Or(
+    &Grammar::Keywords(&["thin", "medium", "thick"]),
+    &Grammar::NonNegative(&Grammar::Length),
+);
+const DISPLAY: Grammar = Grammar::Check(is_display);
+const FONT_SIZE: Grammar = Grammar::Or(
+    &Grammar::Keywords(&[
+        "xx-small",
+        "x-small",
+        "small",
+        "medium",
+        "large",
+        "x-large",
+        "xx-large",
+        "xxx-large",
+        "larger",
+        "smaller",
+    ]),
+    &NON_NEGATIVE_LENGTH_PERCENTAGE,
+);
+const FONT_WEIGHT: Grammar = Grammar::Or(
+    &Grammar::Keywords(&["normal", "bold", "bolder", "lighter"]),
+    &Grammar::NumberRange(1.0, 1000.0),
+);
+const LINE_HEIGHT: Grammar = Grammar::Or(
+    &Grammar::Keywords(&["normal"]),
+    &Grammar::NonNegative(&Grammar::Or(&Grammar::Number, &Grammar::LengthPercentage)),
+);
+
+fn is_display(value: &Value) -> bool {
+    Display::parse(value).is_some()
+}
+
+impl Grammar {
+    /// Does `value` match the grammar?
+    pub fn matches(&self, value: &Value) -> bool {
+        match (*self, value) {
+            (Grammar::Keywords(keywords), Value::Keyword(keyword)) => {
+                keywords.iter().any(|k| keyword.eq_ignore_ascii_case(k))
+            }
+            (Grammar::Length, Value::Length(_, unit)) => *unit != Unit::Percent,
+            (Grammar::LengthPercentage, Value::Length(..)) => true,
+            (Grammar::Length | Grammar::LengthPercentage, Value::Number(n)) => *n == 0.0,
+            (Grammar::Length, Value::Calc(node)) => node.calc_type() == Ok(CalcType::Length),
+            (Grammar::LengthPercentage, Value::Calc(node)) => matches!(
+                node.calc_type(),
+                Ok(CalcType::Length | CalcType::Percentage | CalcType::LengthPercentage)
+            ),
+            (Grammar::Number, Value::Number(_)) => true,
+            (Grammar::NumberRange(min, max), Value::Number(n)) => (min..=max).contains(n),
+            (Grammar::Number | Grammar::NumberRange(..), Value::Calc(node)) => {
+                node.calc_type() == Ok(CalcType::Number)
+            }
+            (Grammar::Color, Value::ColorValue(_)) => true,
+            (Grammar::Color, Value::Keyword(keyword)) => {
+                keyword.eq_ignore_ascii_case("currentcolor")
+            }
+            (Grammar::Or(a, b), value) => a.matches(value) || b.matches(value),
+            (Grammar::NonNegative(_), Value::Length(n, _) | Value::Number(n)) if *n < 0.0 => false,
+            (Grammar::NonNegative(grammar), value) => grammar.matches(value),
+            (Grammar::Repeat(grammar, max), Value::List(components)) => {
+                components.len() <= max && components.iter().all(|c| grammar.matches(c))
+            }
+            (Grammar::Repeat(grammar, _), value) => grammar.matches(value),
+            (Grammar::Check(check), value) => check(value),
+            (Grammar::Any, _) => true,
+            _ => false,
+        }
+    }
+
+    /// Clamp a computed value to the range of the grammar. Values from math functions may be
+    /// out of range, e.g. `calc(10px - 20px)` for a `width`.
+    ///
+    /// ref: https://www.w3.org/TR/css-values-4/#calc-range
+    pub fn clamp(&self, value: Value) -> Value {
+        match (*self, value) {
+            (Grammar::NonNegative(grammar), value) => match grammar.clamp(value) {
+                Value::Length(n, unit) => Value::Length(n.max(0.0), unit),
+                Value::Number(n) => Value::Number(n.max(0.0)),
+                value => value,
+            },
+            (Grammar::NumberRange(min, max), Value::Number(n)) => Value::Number(n.clamp(min, max)),
+            (Grammar::Or(a, b), value) => b.clamp(a.clamp(value)),
+            (Grammar::Repeat(grammar, _), Value::List(components)) => Value::List(
+                components
+                    .into_iter()
+                    .map(|component| grammar.clamp(component))
+                    .collect(),
+            ),
+            (Grammar::Repeat(grammar, _), value) => grammar.clamp(value),
+            (_, value) => value,
+        }
+    }
+}
+
+impl Property {
+    /// Is `value` valid for the property? CSS-wide keywords always are, and so are values with
+    /// `var()`, which are checked once the variables are substituted.
+    pub fn accepts(&self, value: &Value) -> bool {
+        match value {
+            Value::Keyword(keyword)
+                if ["inherit", "initial", "unset", "revert"]
+                    .iter()
+                    .any(|k| keyword.eq_ignore_ascii_case(k)) =>
+            {
+                true
+            }
+            Value::Unparsed(_) => true,
+            value => self.grammar.matches(value),
+        }
+    }
+}
+
+/// Conversion between the computed values of a property and its typed field in `ComputedStyle`
+pub trait ComputedValue: Sized {
+    /// Convert a computed value: lengths are in px, except percentages, and keywords are
+    /// lowercase. Invalid values become the default of the type.
+    fn from_value(value: &Value) -> Self;
+    fn to_value(&self) -> Value;
+}
+
+/// Define the table of known properties, and the `ComputedStyle` struct with one field per
+/// property.
+///
+/// Computed values are calculated in the order of the table, so `font-size` and `color` must
+/// come first.
+macro_rules! properties {
+    ($($name:literal => $field:ident: $type:ty {
+        inherited: $inherited:literal,
+        initial: $initial:literal,
+        grammar: $grammar:expr,
+        animation: $animation:ident,
+    })*) => {
+        /// All known longhand properties, in the order their computed values are calculated
+        pub const PROPERTIES: &[Property] = &[
+            $(Property {
+                name: $name,
+                inherited: $inherited,
+                initial: $initial,
+                grammar: $grammar,
+                animation: Animation::$animation,
+            },)*
+        ];
+
+        /// The computed values of all known properties of a node
+        #[derive(Debug, Clone, PartialEq)]
+        pub struct ComputedStyle {
+            $(pub $field: $type,)*
+            /// Custom properties and unknown properties, with their specified values
+            pub other: HashMap<String, Value>,
+        }
+
+        impl ComputedStyle {
+            /// The style where every property has its initial value
+            fn new_initial() -> ComputedStyle {
+                ComputedStyle {
+                    $($field: ComputedValue::from_value(&parse_initial($name, $initial)),)*
+                    other: HashMap::new(),
+                }
+            }
+
+            /// The style of a node with no specified values: inherited properties take the
+            /// values of `parent`, the others their initial values. Custom properties aren't
+            /// included.
+            pub fn inherit(parent: &ComputedStyle) -> ComputedStyle {
+                let initial = initial_style();
+                ComputedStyle {
+                    $($field: if $inherited {
+                        parent.$field.clone()
+                    } else {
+                        initial.$field.clone()
+                    },)*
+                    other: HashMap::new(),
+                }
+            }
+
+            /// Set property `name` from its computed value. Return false if it's unknown.
+            pub fn set(&mut self, name: &str, value: &Value) -> bool {
+                match name {
+                    $($name => self.$field = ComputedValue::from_value(value),)*
+                    _ => return false,
+                }
+                true
+            }
+
+            /// Copy the value of property `name` from `other`
+            pub fn copy_from(&mut self, name: &str, other: &ComputedStyle) {
+                match name {
+                    $($name => self.$field = other.$field.clone(),)*
+                    _ => {}
+                }
+            }
+
+            /// Return the computed value of property `name`, or `None` if it isn't set
+            pub fn get(&self, name: &str) -> Option<Value> {
+                match name {
+                    $($name => Some(self.$field.to_value()),)*
+                    _ => self.other.get(name).cloned(),
+                }
+            }
+        }
+    };
+}
+
+properties! {
+    "font-size" => font_size: f32 {
+        inherited: true,
+        initial: "16px",
+        grammar: FONT_SIZE,
+        animation: ByComputedValue,
+    }
+    "color" => color: Color {
+        inherited: true,
+        initial: "black",
+        grammar: Grammar::Color,
+        animation: ByComputedValue,
+    }
+    "font-family" => font_family: FontFamily {
+        inherited: true,
+        initial: "serif",
+        grammar: Grammar::Any,
+        animation: Discrete,
+    }
+    "font-style" => font_style: FontStyle {
+        inherited: true,
+        initial: "normal",
+        grammar: Grammar::Keywords(&["normal", "italic", "oblique"]),
+        animation: Discrete,
+    }
+    "font-weight" => font_weight: FontWeight {
+        inherited: true,
+        initial: "normal",
+        grammar: FONT_WEIGHT,
+        animation: ByComputedValue,
+    }
+    "line-height" => line_height: LineHeight {
+        inherited: true,
+        initial: "normal",
+        grammar: LINE_HEIGHT,
+        animation: ByComputedValue,
+    }
+    "text-align" => text_align: TextAlign {
+        inherited: true,
+        initial: "start",
+        grammar: Grammar::Keywords(&[
+            "start", "end", "left", "right", "center", "justify", "match-parent",
+        ]),
+        animation: Discrete,
+    }
+    "text-indent" => text_indent: LengthPercentageOrAuto {
+        inherited: true,
+        initial: "0px",
+        grammar: Grammar::LengthPercentage,
+        animation: ByComputedValue,
+    }
+    "white-space" => white_space: WhiteSpace {
+        inherited: true,
+        initial: "normal",
+        grammar: Grammar::Keywords(&[
+            "normal", "pre", "nowrap", "pre-wrap", "pre-line", "break-spaces",
+        ]),
+        animation: Discrete,
+    }
+    "list-style-type" => list_style_type: ListStyleType {
+        inherited: true,
+        initial: "disc",
+        grammar: Grammar::Keywords(&[
+            "disc", "circle", "square", "decimal", "decimal-leading-zero", "lower-roman",
+            "upper-roman", "lower-alpha", "upper-alpha", "lower-latin", "upper-latin",
+            "lower-greek", "none",
+        ]),
+        animation: Discrete,
+    }
+    "visibility" => visibility: Visibility {
+        inherited: true,
+        initial: "visible",
+        grammar: Grammar::Keywords(&["visible", "hidden", "collapse"]),
+        animation: Discrete,
+    }
+    "border-collapse" => border_collapse: BorderCollapse {
+        inherited: true,
+        initial: "separate",
+        grammar: Grammar::Keywords(&["separate", "collapse"]),
+        animation: Discrete,
+    }
+    "border-spacing" => border_spacing: BorderSpacing {
+        inherited: true,
+        initial: "0px",
+        grammar: Grammar::Repeat(&Grammar::NonNegative(&Grammar::Length), 2),
+        animation: ByComputedValue,
+    }
+    "display" => display: Display {
+        inherited: false,
+        initial: "inline",
+        grammar: DISPLAY,
+        animation: Discrete,
+    }
+    "position" => position: Position {
+        inherited: false,
+        initial: "static",
+        grammar: Grammar::Keywords(&["static", "relative", "absolute", "fixed", "sticky"]),
+        animation: Discrete,
+    }
+    "box-sizing" => box_sizing: BoxSizing {
+        inherited: false,
+        initial: "content-box",
+        grammar: Grammar::Keywords(&["content-box", "border-box"]),
+        animation: Discrete,
+    }
+    "width" => width: LengthPercentageOrAuto {
+        inherited: false,
+        initial: "auto",
+        grammar: SIZE,
+        animation: ByComputedValue,
+    }
+    "height" => height: LengthPercentageOrAuto {
+        inherited: false,
+        initial: "auto",
+        grammar: SIZE,
+        animation: ByComputedValue,
+    }
+    "margin-top" => margin_top: LengthPercentageOrAuto {
+        inherited: false,
+        initial: "0px",
+        grammar: LENGTH_PERCENTAGE_OR_AUTO,
+        animation: ByComputedValue,
+    }
+    "margin-right" => margin_right: LengthPercentageOrAuto {
+        inherited: false,
+        initial: "0px",
+        grammar: LENGTH_PERCENTAGE_OR_AUTO,
+        animation: ByComputedValue,
+    }
+    "margin-bottom" => margin_bottom: LengthPercentageOrAuto {
+        inherited: false,
+        initial: "0px",
+        grammar: LENGTH_PERCENTAGE_OR_AUTO,
+        animation: ByComputedValue,
+    }
+    "margin-left" => margin_left: LengthPercentageOrAuto {
+        inherited: false,
+        initial: "0px",
+        grammar: LENGTH_PERCENTAGE_OR_AUTO,
+        animation: ByComputedValue,
+    }
+    "padding-top" => padding_top: LengthPercentageOrAuto {
+        inherited: false,
+        initial: "0px",
+        grammar: NON_NEGATIVE_LENGTH_PERCENTAGE,
+        animation: ByComputedValue,
+    }
+    "padding-right" => padding_right: LengthPercentageOrAuto {
+        inherited: false,
+        initial: "0px",
+        grammar: NON_NEGATIVE_LENGTH_PERCENTAGE,
+        animation: ByComputedValue,
+    }
+    "padding-bottom" => padding_bottom: LengthPercentageOrAuto {
+        inherited: false,
+        initial: "0px",
+        grammar: NON_NEGATIVE_LENGTH_PERCENTAGE,
+        animation: ByComputedValue,
+    }
+    "padding-left" => padding_left: LengthPercentageOrAuto {
+        inherited: false,
+        initial: "0px",
+        grammar: NON_NEGATIVE_LENGTH_PERCENTAGE,
+        animation: ByComputedValue,
+    }
+    "border-top-style" => border_top_style: BorderStyle {
+        inherited: false,
+        initial: "none",
+        grammar: BORDER_STYLE,
+        animation: Discrete,
+    }
+    "border-right-style" => border_right_style: BorderStyle {
+        inherited: false,
+        initial: "none",
+        grammar: BORDER_STYLE,
+        animation: Discrete,
+    }
+    "border-bottom-style" => border_bottom_style: BorderStyle {
+        inherited: false,
+        initial: "none",
+        grammar: BORDER_STYLE,
+        animation: Discrete,
+    }
+    "border-left-style" => border_left_style: BorderStyle {
+        inherited: false,
+        initial: "none",
+        grammar: BORDER_STYLE,
+        animation: Discrete,
+    }
+    // `medium` is 3px
+    "border-top-width" => border_top_width: f32 {
+        inherited: false,
+        initial: "3px",
+        grammar: BORDER_WIDTH,
+        animation: ByComputedValue,
+    }
+    "border-right-width" => border_right_width: f32 {
+        inherited: false,
+        initial: "3px",
+        grammar: BORDER_WIDTH,
+        animation: ByComputedValue,
+    }
+    "border-bottom-width" => border_bottom_width: f32 {
+        inherited: false,
+        initial: "3px",
+        grammar: BORDER_WIDTH,
+        animation: ByComputedValue,
+    }
+    "border-left-width" => border_left_width: f32 {
+        inherited: false,
+        initial: "3px",
+        grammar: BORDER_WIDTH,
+        animation: ByComputedValue,
+    }
+    "border-top-color" => border_top_color: Color {
+        inherited: false,
+        initial: "currentcolor",
+        grammar: Grammar::Color,
+        animation: ByComputedValue,
+    }
+    "border-right-color" => border_right_color: Color {
+        inherited: false,
+        initial: "currentcolor",
+        grammar: Grammar::Color,
+        animation: ByComputedValue,
+    }
+    "border-bottom-color" => border_bottom_color: Color {
+        inherited: false,
+        initial: "currentcolor",
+        grammar: Grammar::Color,
+        animation: ByComputedValue,
+    }
+    "border-left-color" => border_left_color: Color {
+        inherited: false,
+        initial: "currentcolor",
+        grammar: Grammar::Color,
+        animation: ByComputedValue,
+    }
+    "background-color" => background_color: Color {
+        inherited: false,
+        initial: "transparent",
+        grammar: Grammar::Color,
+        animation: ByComputedValue,
+    }
+}
+
+fn parse_initial(name: &str, initial: &str) -> Value {
+    css::parse_declaration_list(format!("{}: {}", name, initial))
+        .remove(0)
+        .value
+}
+
+/// The style where every property has its initial value
+pub fn initial_style() -> &'static Arc<ComputedStyle> {
+    static INITIAL_STYLE: OnceLock<Arc<ComputedStyle>> = OnceLock::new();
+    INITIAL_STYLE.get_or_init(|| Arc::new(ComputedStyle::new_initial()))
+}
+
+impl Property {
+    /// The initial value, parsed
+    pub fn initial_value(&self) -> Value {
+        parse_initial(self.name, self.initial)
+    }
+}
+
+/// Return the property called `name`, or `None` if it's unknown or a shorthand
+pub fn lookup(name: &str) -> Option<&'static Property> {
+    PROPERTIES.iter().find(|property| property.name == name)
+}
+
+/// Define an enum of keywords, with the first variant as the default
+macro_rules! keywords {
+    ($(#[$meta:meta])* $name:ident { $($variant:ident = $keyword:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)*
+        }
+
+        impl ComputedValue for $name {
+            fn from_value(value: &Value) -> Self {
+                let keywords = [$(($keyword, $name::$variant),)*];
+                match value {
+                    Value::Keyword(keyword) => keywords
+                        .iter()
+                        .find(|(k, _)| keyword.eq_ignore_ascii_case(k))
+                        .map_or(keywords[0].1, |&(_, variant)| variant),
+                    _ => keywords[0].1,
+                }
+            }
+
+            fn to_value(&self) -> Value {
+                let keyword = match self {
+                    $($name::$variant => $keyword,)*
+                };
+                Value::Keyword(keyword.to_string())
+            }
+        }
+    };
+}
+
+keywords! {
+    Position {
+        Static = "static",
+        Relative = "relative",
+        Absolute = "absolute",
+        Fixed = "fixed",
+        Sticky = "sticky",
+    }
+}
+
+keywords! {
+    BoxSizing {
+        ContentBox = "content-box",
+        BorderBox = "border-box",
+    }
+}
+
+keywords! {
+    Visibility {
+        Visible = "visible",
+        Hidden = "hidden",
+        Collapse = "collapse",
+    }
+}
+
+keywords! {
+    FontStyle {
+        Normal = "normal",
+        Italic = "italic",
+        Oblique = "oblique",
+    }
+}
+
+keywords! {
+    TextAlign {
+        Start = "start",
+        End = "end",
+        Left = "left",
+        Right = "right",
+        Center = "center",
+        Justify = "justify",
+        MatchParent = "match-parent",
+    }
+}
+
+keywords! {
+    WhiteSpace {
+        Normal = "normal",
+        Pre = "pre",
+        Nowrap = "nowrap",
+        PreWrap = "pre-wrap",
+        PreLine = "pre-line",
+        BreakSpaces = "break-spaces",
+    }
+}
+
+keywords! {
+    ListStyleType {
+        Disc = "disc",
+        Circle = "circle",
+        Square = "square",
+        Decimal = "decimal",
+        DecimalLeadingZero = "decimal-leading-zero",
+        LowerRoman = "lower-roman",
+        UpperRoman = "upper-roman",
+        LowerAlpha = "lower-alpha",
+        UpperAlpha = "upper-alpha",
+        LowerLatin = "lower-latin",
+        UpperLatin = "upper-latin",
+        LowerGreek = "lower-greek",
+        None = "none",
+    }
+}
+
+keywords! {
+    BorderCollapse {
+        Separate = "separate",
+        Collapse = "collapse",
+    }
+}
+
+keywords! {
+    BorderStyle {
+        None = "none",
+        Hidden = "hidden",
+        Dotted = "dotted",
+        Dashed = "dashed",
+        Solid = "solid",
+        Double = "double",
+        Groove = "groove",
+        Ridge = "ridge",
+        Inset = "inset",
+        Outset = "outset",
+    }
+}
+
+impl BorderStyle {
+    /// Does a border with this style have a width?
+    pub fn is_visible(self) -> bool {
+        !matches!(self, BorderStyle::None | BorderStyle::Hidden)
+    }
+}
+
+/// A length in px, a percentage, a mix of both, or `auto`
+#[derive(Debug, Clone, PartialEq)]
+pub enum LengthPercentageOrAuto {
+    Auto,
+    Length(f32),
+    Percentage(f32),
+    /// A `calc()` expression with lengths in px and percentages
+    Calc(Box<CalcNode>),
+}
+
+impl LengthPercentageOrAuto {
+    /// The length in px, with percentages of `basis`, or `None` for `auto`
+    pub fn resolve(&self, basis: f32) -> Option<f32> {
+        match *self {
+            LengthPercentageOrAuto::Auto => None,
+            LengthPercentageOrAuto::Length(px) => Some(px),
+            LengthPercentageOrAuto::Percentage(percent) => Some(percent / 100.0 * basis),
+            LengthPercentageOrAuto::Calc(ref node) => Some(node.to_px(&LengthContext {
+                font_size: DEFAULT_FONT_SIZE,
+                root_font_size: DEFAULT_FONT_SIZE,
+                viewport_width: 0.0,
+                viewport_height: 0.0,
+                percentage_basis: basis,
+            })),
+        }
+    }
+
+    /// Does the value depend on the size of the containing block?
+    pub fn has_percentage(&self) -> bool {
+        match self {
+            LengthPercentageOrAuto::Percentage(_) => true,
+            LengthPercentageOrAuto::Calc(node) => node.has_percentage(),
+            _ => false,
+        }
+    }
+}
+
+impl ComputedValue for LengthPercentageOrAuto {
+    fn from_value(value: &Value) -> Self {
+        match value {
+            Value::Length(percent, Unit::Percent) => LengthPercentageOrAuto::Percentage(*percent),
+            Value::Length(px, _) | Value::Number(px) => LengthPercentageOrAuto::Length(*px),
+            Value::Calc(node) => LengthPercentageOrAuto::Calc(node.clone()),
+            _ => LengthPercentageOrAuto::Auto,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            LengthPercentageOrAuto::Auto => Value::Keyword("auto".to_string()),
+            LengthPercentageOrAuto::Length(px) => Value::Length(*px, Unit::Px),
+            LengthPercentageOrAuto::Percentage(percent) => Value::Length(*percent, Unit::Percent),
+            LengthPercentageOrAuto::Calc(node) => Value::Calc(node.clone()),
+        }
+    }
+}
+
+/// Lengths in px
+impl ComputedValue for f32 {
+    fn from_value(value: &Value) -> Self {
+        match *value {
+            Value::Length(px, _) | Value::Number(px) => px,
+            _ => 0.0,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Length(*self, Unit::Px)
+    }
+}
+
+/// Colors. `currentcolor` is resolved before the value is set.
+impl ComputedValue for Color {
+    fn from_value(value: &Value) -> Self {
+        match *value {
+            Value::ColorValue(color) => color,
+            _ => Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0,
+            },
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::ColorValue(*self)
+    }
+}
+
+/// The family names of `font-family`, lowercase. Names may be quoted, or written as several
+/// identifiers, e.g. `"Times New Roman", DejaVu Sans, serif`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFamily(pub Vec<String>);
+
+impl ComputedValue for FontFamily {
+    fn from_value(value: &Value) -> Self {
+        let names = value
+            .components()
+            .split(|component| *component == Value::Delim(','))
+            .map(|name| {
+                let words: Vec<String> = name
+                    .iter()
+                    .filter_map(|word| match word {
+                        Value::Keyword(word) | Value::String(word) => {
+                            Some(word.to_ascii_lowercase())
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                words.join(" ")
+            })
+            .filter(|name| !name.is_empty())
+            .collect();
+        FontFamily(names)
+    }
+
+    /// Names with spaces are quoted
+    fn to_value(&self) -> Value {
+        let mut components = Vec::new();
+        for name in &self.0 {
+            if !components.is_empty() {
+                components.push(Value::Delim(','));
+            }
+            components.push(if name.contains(' ') {
+                Value::String(name.clone())
+            } else {
+                Value::Keyword(name.clone())
+            });
+        }
+        match components.len() {
+            1 => components.remove(0),
+            _ => Value::List(components),
+        }
+    }
+}
+
+/// A numeric font weight, from 1 to 1000. `bolder` and `lighter` are resolved before the value
+/// is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FontWeight(pub u16);
+
+impl ComputedValue for FontWeight {
+    fn from_value(value: &Value) -> Self {
+        FontWeight(match value {
+            Value::Number(weight) => weight.clamp(1.0, 1000.0) as u16,
+            Value::Keyword(keyword) if keyword.eq_ignore_ascii_case("bold") => 700,
+            _ => 400,
+        })
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Number(f32::from(self.0))
+    }
+}
+
+/// A `line-height`. Lengths and percentages are resolved to px before the value is set.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineHeight {
+    Normal,
+    /// A multiple of the font size
+    Number(f32),
+    Length(f32),
+}
+
+impl ComputedValue for LineHeight {
+    fn from_value(value: &Value) -> Self {
+        match *value {
+            Value::Number(factor) => LineHeight::Number(factor),
+            Value::Length(px, _) => LineHeight::Length(px),
+            _ => LineHeight::Normal,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match *self {
+            LineHeight::Normal => Value::Keyword("normal".to_string()),
+            LineHeight::Number(factor) => Value::Number(factor),
+            LineHeight::Length(px) => Value::Length(px, Unit::Px),
+        }
+    }
+}
+
+/// The horizontal and vertical `border-spacing`, in px
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BorderSpacing {
+    pub horizontal: f32,
+    pub vertical: f32,
+}
+
+impl ComputedValue for BorderSpacing {
+    fn from_value(value: &Value) -> Self {
+        match value.components() {
+            [horizontal, vertical] => BorderSpacing {
+                horizontal: f32::from_value(horizontal),
+                vertical: f32::from_value(vertical),
+            },
+            [both] => BorderSpacing {
+                horizontal: f32::from_value(both),
+                vertical: f32::from_value(both),
+            },
+            _ => BorderSpacing {
+                horizontal: 0.0,
+                vertical: 0.0,
+            },
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        if self.horizontal == self.vertical {
+            self.horizontal.to_value()
+        } else {
+            Value::List(vec![self.horizontal.to_value(), self.vertical.to_value()])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        initial_style, BorderSpacing, BorderStyle, ComputedStyle, ComputedValue, Display,
+        FontFamily, FontWeight, LengthPercentageOrAuto, LineHeight, TextAlign,
+    };
+    use crate::calc::CalcNode;
+    use crate::css::{self, Unit, Value};
+
+    #[test]
+    fn test_initial_and_inherited_values() {
+        let initial = initial_style();
+        assert_eq!(16.0, initial.font_size);
+        assert_eq!(Display::INLINE, initial.display);
+        assert_eq!(BorderStyle::None, initial.border_top_style);
+        assert_eq!(LengthPercentageOrAuto::Auto, initial.width);
+        assert_eq!(
+            Some(Value::Length(3.0, Unit::Px)),
+            initial.get("border-left-width")
+        );
+
+        let mut parent = ComputedStyle::inherit(initial);
+        assert!(parent.set("font-size", &Value::Length(20.0, Unit::Px)));
+        assert!(parent.set("display", &Value::Keyword("List-Item".to_string())));
+        assert!(parent.set("margin-left", &Value::Length(10.0, Unit::Percent)));
+        assert!(!parent.set("--custom", &Value::Number(1.0)));
+        assert_eq!(Display::LIST_ITEM, parent.display);
+
+        let child = ComputedStyle::inherit(&parent);
+        assert_eq!(20.0, child.font_size);
+        assert_eq!(Display::INLINE, child.display);
+        assert_eq!(LengthPercentageOrAuto::Length(0.0), child.margin_left);
+    }
+
+    #[test]
+    fn test_resolve_lengths() {
+        assert_eq!(None, LengthPercentageOrAuto::Auto.resolve(200.0));
+        assert_eq!(
+            Some(5.0),
+            LengthPercentageOrAuto::Length(5.0).resolve(200.0)
+        );
+        assert_eq!(
+            Some(50.0),
+            LengthPercentageOrAuto::Percentage(25.0).resolve(200.0)
+        );
+        let calc = LengthPercentageOrAuto::Calc(Box::new(CalcNode::Sum(vec![
+            CalcNode::Length(10.0, Unit::Percent),
+            CalcNode::Length(4.0, Unit::Px),
+        ])));
+        assert!(calc.has_percentage());
+        assert_eq!(Some(24.0), calc.resolve(200.0));
+    }
+
+    #[test]
+    fn test_non_negative_values() {
+        let accepts = |name: &str, css: &str| {
+            !css::parse_declaration_list(format!("{}: {}", name, css)).is_empty()
+        };
+        for &(name, css) in &[
+            ("width", "-50px"),
+            ("height", "-1%"),
+            ("padding-left", "-2px"),
+            ("border-left-width", "-5px"),
+            ("font-size", "-1em"),
+            ("line-height", "-2"),
+            ("line-height", "-10px"),
+        ] {
+            assert!(!accepts(name, css), "{}: {}", name, css);
+        }
+        for &(name, css) in &[
+            ("width", "0"),
+            ("height", "auto"),
+            ("padding-left", "10%"),
+            ("border-left-width", "thin"),
+            ("font-size", "12px"),
+            ("line-height", "1.5"),
+            ("width", "calc(10% - 20px)"),
+            ("width", "calc(-10px)"),
+            ("margin-left", "-50px"),
+        ] {
+            assert!(accepts(name, css), "{}: {}", name, css);
+        }
+    }
+
+    #[test]
+    fn test_typed_values() {
+        // `font-family` accepts any value
+        let value = |css: &str| {
+            css::parse_declaration_list(format!("font-family: {}", css))
+                .remove(0)
+                .value
+        };
+        let family = FontFamily::from_value(&value("\"Times New Roman\", DejaVu  Sans, serif"));
+        assert_eq!(vec!["times new roman", "dejavu sans", "serif"], family.0);
+        assert_eq!(family, FontFamily::from_value(&family.to_value()));
+
+        let initial = initial_style();
+        assert_eq!(FontWeight(400), initial.font_weight);
+        assert_eq!(LineHeight::Normal, initial.line_height);
+        assert_eq!(TextAlign::Start, initial.text_align);
+        assert_eq!(FontWeight(700), FontWeight::from_value(&value("bold")));
+
+        let mut style = ComputedStyle::inherit(initial);
+        assert!(style.set("border-spacing", &value("1px 2px")));
+        assert_eq!(
+            BorderSpacing {
+                horizontal: 1.0,
+                vertical: 2.0
+            },
+            style.border_spacing
+        );
+        assert!(style.set("text-align", &value("CENTER")));
+        assert_eq!(TextAlign::Center, style.text_align);
+    }
+}
@@ -0,0 +1,265 @@
+//! Anti-aliased rasterization of outlines, e.g. glyphs.
+//!
+//! Each edge adds the signed area it covers to an accumulation buffer, and the running sum
+//! along each row is the coverage of each pixel, with the non-zero winding rule.
+//!
+//! ref: https://medium.com/@raphlinus/inside-the-fastest-font-renderer-in-the-world-75ae5270c445
+
+use crate::font::{Point, Segment};
+
+/// How far curves may be from the lines they're drawn with, in px
+const TOLERANCE: f32 = 0.05;
+
+pub struct Rasterizer {
+    width: usize,
+    height: usize,
+    accumulation: Vec<f32>,
+}
+
+impl Rasterizer {
+    /// A rasterizer for an area of `width` by `height` px. Edges must stay within it, and at
+    /// least 2 px left of its right side.
+    pub fn new(width: usize, height: usize) -> Rasterizer {
+        Rasterizer {
+            width,
+            height,
+            // Edges at the end of the last row can spill over
+            accumulation: vec![0.0; width * height + 2],
+        }
+    }
+
+    /// Add an edge of the outline, in px with y down. Curves are drawn as lines.
+    pub fn add(&mut self, segment: Segment) {
+        match segment {
+            Segment::Line(p0, p1) => self.line(p0, p1),
+            Segment::Quad(p0, p1, p2) => {
+                let deviation = length(p0.x - 2.0 * p1.x + p2.x, p0.y - 2.0 * p1.y + p2.y);
+                self.curve(deviation / 4.0, |t| {
+                    let u = 1.0 - t;
+                    blend(&[(u * u, p0), (2.0 * u * t, p1), (t * t, p2)])
+                });
+            }
+            Segment::Cubic(p0, p1, p2, p3) => {
+                let deviation = length(p0.x - 2.0 * p1.x + p2.x, p0.y - 2.0 * p1.y + p2.y)
+                    .max(length(p1.x - 2.0 * p2.x + p3.x, p1.y - 2.0 * p2.y + p3.y));
+                self.curve(deviation * 3.0 / 4.0, |t| {
+                    let u = 1.0 - t;
+                    blend(&[
+                        (u * u * u, p0),
+                        (3.0 * u * u * t, p1),
+                        (3.0 * u * t * t, p2),
+                        (t * t * t, p3),
+                    ])
+                });
+            }
+        }
+    }
+
+    /// Add a curve as lines between points at even steps of `t`. The distance between a
+    /// curve and its chord shrinks with the square of the number of steps.
+    fn curve(&mut self, deviation: f32, point: impl Fn(f32) -> Point) {
+        let steps = ((deviation / TOLERANCE).sqrt().ceil() as usize).clamp(1, 100);
+        let mut previous = point(0.0);
+        for i in 1..=steps {
+            let next = point(i as f32 / steps as f32);
+            self.line(previous, next);
+            previous = next;
+        }
+    }
+
+    /// Add a line, by the area it covers to its right in each pixel of each row it crosses
+    fn line(&mut self, p0: Point, p1: Point) {
+        if p0.y == p1.y {
+            return;
+        }
+        // Edges going down add coverage, and edges going up take it away
+        let (direction, p0, p1) = if p0.y < p1.y {
+            (1.0, p0, p1)
+        } else {
+            (-1.0, p1, p0)
+        };
+        let dxdy = (p1.x - p0.x) / (p1.y - p0.y);
+        let y_start = p0.y.max(0.0);
+        let y_end = p1.y.min(self.height as f32);
+        let mut x = p0.x + (y_start - p0.y) * dxdy;
+        if y_start >= y_end {
+            return;
+        }
+        for row in y_start as usize..y_end.ceil() as usize {
+            let row_start = row * self.width;
+            let dy = ((row + 1) as f32).min(y_end) - (row as f32).max(y_start);
+            let x_next = x + dxdy * dy;
+            let d = dy * direction;
+            let (x0, x1) = if x < x_next { (x, x_next) } else { (x_next, x) };
+            let (x0, x1) = (x0.max(0.0), x1.max(0.0));
+            let x0_floor = x0.floor();
+            let x0i = x0_floor as usize;
+            let x1_ceil = x1.ceil();
+            let x1i = x1_ceil as usize;
+            let cells = &mut self.accumulation[row_start..];
+            if x1i <= x0i + 1 {
+                // Within one pixel: the part right of the line's middle is covered, and the
+                // rest of the row after it
+                let middle = 0.5 * (x + x_next) - x0_floor;
+                cells[x0i] += d * (1.0 - middle);
+                cells[x0i + 1] += d * middle;
+            } else {
+                // Across several pixels, the covered area grows along the line: a triangle in
+                // the first pixel, trapezoids in between, and the rest in the last
+                let s = 1.0 / (x1 - x0);
+                let x0f = x0 - x0_floor;
+                let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+                let x1f = x1 - x1_ceil + 1.0;
+                let am = 0.5 * s * x1f * x1f;
+                cells[x0i] += d * a0;
+                if x1i == x0i + 2 {
+                    cells[x0i + 1] += d * (1.0 - a0 - am);
+                } else {
+                    let a1 = s * (1.5 - x0f);
+                    cells[x0i + 1] += d * (a1 - a0);
+                    for cell in &mut cells[x0i + 2..x1i - 1] {
+                        *cell += d * s;
+                    }
+                    let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                    cells[x1i - 1] += d * (1.0 - a2 - am);
+                }
+                cells[x1i] += d * am;
+            }
+            x = x_next;
+        }
+    }
+
+    /// The coverage of each pixel from 0 to 1, row by row
+    pub fn coverage(&self) -> Vec<f32> {
+        let mut sum = 0.0;
+        self.accumulation[..self.width * self.height]
+            .iter()
+            .map(|area| {
+                sum += area;
+                sum.abs().min(1.0)
+            })
+            .collect()
+    }
+}
+
+fn length(x: f32, y: f32) -> f32 {
+    (x * x + y * y).sqrt()
+}
+
+/// The sum of points with weights
+fn blend(points: &[(f32, Point)]) -> Point {
+    points
+        .iter()
+        .fold(Point::default(), |sum, &(weight, point)| Point {
+            x: sum.x + weight * point.x,
+            y: sum.y + weight * point.y,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rasterizer;
+    use crate::font::{Point, Segment};
+
+    fn polygon(width: usize, height: usize, points: &[(f32, f32)]) -> Vec<f32> {
+        let mut rasterizer = Rasterizer::new(width, height);
+        for (i, &(x, y)) in points.iter().enumerate() {
+            let (x1, y1) = points[(i + 1) % points.len()];
+            rasterizer.add(Segment::Line(Point { x, y }, Point { x: x1, y: y1 }));
+        }
+        rasterizer.coverage()
+    }
+
+    fn assert_close(expected: &[f32], actual: &[f32]) {
+        assert_eq!(expected.len(), actual.len());
+        for (e, a) in expected.iter().zip(actual) {
+            assert!(
+                (e - a).abs() < 1e-4,
+                "expected {:?}, got {:?}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn test_square() {
+        let coverage = polygon(4, 3, &[(1.0, 1.0), (2.0, 1.0), (2.0, 2.0), (1.0, 2.0)]);
+        #[rustfmt::skip]
+        assert_close(&[
+            0.0, 0.0, 0.0, 0.0,
+            0.0, 1.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0,
+        ], &coverage);
+
+        // Either winding fills the shape
+        let coverage = polygon(4, 3, &[(1.0, 1.0), (1.0, 2.0), (2.0, 2.0), (2.0, 1.0)]);
+        assert_eq!(1.0, coverage[5]);
+    }
+
+    #[test]
+    fn test_anti_aliasing() {
+        // Half of the first pixel, split vertically
+        let coverage = polygon(4, 1, &[(0.5, 0.0), (2.0, 0.0), (2.0, 1.0), (0.5, 1.0)]);
+        assert_close(&[0.5, 1.0, 0.0, 0.0], &coverage);
+
+        // A triangle below the diagonal of a pixel
+        let coverage = polygon(3, 1, &[(0.0, 0.0), (1.0, 1.0), (0.0, 1.0)]);
+        assert_close(&[0.5, 0.0, 0.0], &coverage);
+
+        // A triangle across three pixels covers a quarter of the first
+        let coverage = polygon(5, 1, &[(0.0, 1.0), (2.0, 0.0), (2.0, 1.0)]);
+        assert_close(&[0.25, 0.75, 0.0, 0.0, 0.0], &coverage);
+    }
+
+    #[test]
+    fn test_curves() {
+        // A circle of radius 4 from four cubic curves covers about 16π pixels, a little less
+        // since it's drawn as a polygon inside it
+        let k = 4.0 * 0.5523;
+        let p = |x: f32, y: f32| Point {
+            x: x + 5.0,
+            y: y + 5.0,
+        };
+        let mut rasterizer = Rasterizer::new(12, 10);
+        rasterizer.add(Segment::Cubic(
+            p(4.0, 0.0),
+            p(4.0, k),
+            p(k, 4.0),
+            p(0.0, 4.0),
+        ));
+        rasterizer.add(Segment::Cubic(
+            p(0.0, 4.0),
+            p(-k, 4.0),
+            p(-4.0, k),
+            p(-4.0, 0.0),
+        ));
+        rasterizer.add(Segment::Cubic(
+            p(-4.0, 0.0),
+            p(-4.0, -k),
+            p(-k, -4.0),
+            p(0.0, -4.0),
+        ));
+        rasterizer.add(Segment::Cubic(
+            p(0.0, -4.0),
+            p(k, -4.0),
+            p(4.0, -k),
+            p(4.0, 0.0),
+        ));
+        let area: f32 = rasterizer.coverage().iter().sum();
+        let circle = 16.0 * std::f32::consts::PI;
+        assert!(area < circle && area > circle - 0.75, "{}", area);
+
+        // A quadratic curve to the middle of the square's bottom edge
+        let mut rasterizer = Rasterizer::new(4, 2);
+        let (a, b, c) = (p(-5.0, -5.0), p(-3.0, -5.0), p(-3.0, -3.0));
+        rasterizer.add(Segment::Line(a, b));
+        rasterizer.add(Segment::Line(b, c));
+        rasterizer.add(Segment::Quad(c, p(-5.0, -3.0), a));
+        let area: f32 = rasterizer.coverage().iter().sum();
+        // The area between a parabola and its chord is 2/3 of the triangle of its end and
+        // control points
+        let shape = 2.0 + 4.0 / 3.0;
+        assert!(area < shape && area > shape - 0.1, "{}", area);
+    }
+}
@@ -0,0 +1,868 @@
+//! Serialization of stylesheets back to CSS text, pretty-printed or minified.
+//!
+//! Parsing the output gives back an equal stylesheet.
+
+use crate::calc::CalcNode;
+use crate::css::{
+    Color, Combinator, Declaration, Rule, Selector, SimpleSelector, Stylesheet, Unit, Value,
+};
+use crate::media::{Comparison, MediaCondition, MediaFeature, MediaQuery, MediaQueryList};
+
+/// How to lay out the CSS text
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// One declaration per line, indented with two spaces, with blank lines between rules
+    Pretty,
+    /// No optional whitespace, and shorter forms of numbers and colors
+    Minified,
+}
+
+/// Types that can be written as CSS text
+pub trait ToCss {
+    /// Append the CSS text of `self` to `dest`
+    fn write_css(&self, dest: &mut Writer);
+
+    /// Return the CSS text of `self`
+    fn to_css(&self, format: Format) -> String {
+        let mut dest = Writer {
+            format,
+            output: String::new(),
+            indent: 0,
+        };
+        self.write_css(&mut dest);
+        dest.output
+    }
+}
+
+/// The CSS text being written
+pub struct Writer {
+    format: Format,
+    output: String,
+    /// The nesting depth of the current block
+    indent: usize,
+}
+
+impl Writer {
+    fn push(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    /// Write `pretty` when pretty-printing, otherwise `minified`
+    fn either(&mut self, pretty: &str, minified: &str) {
+        match self.format {
+            Format::Pretty => self.push(pretty),
+            Format::Minified => self.push(minified),
+        }
+    }
+
+    /// Start a new, indented line when pretty-printing
+    fn newline(&mut self) {
+        if self.format == Format::Pretty {
+            self.output.push('\n');
+            self.output.push_str(&"  ".repeat(self.indent));
+        }
+    }
+
+    fn open_block(&mut self) {
+        self.either(" {", "{");
+        self.indent += 1;
+    }
+
+    fn close_block(&mut self) {
+        self.indent -= 1;
+        self.newline();
+        self.push("}");
+    }
+
+    /// Write items separated by commas
+    fn comma_separated<T>(&mut self, items: &[T], mut write: impl FnMut(&mut Writer, &T)) {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.either(", ", ",");
+            }
+            write(self, item);
+        }
+    }
+
+    fn number(&mut self, n: f32) {
+        let text = n.to_string();
+        match self.format {
+            Format::Pretty => self.push(&text),
+            // `0.5` becomes `.5`
+            Format::Minified => match text.strip_prefix("0.") {
+                Some(fraction) => self.push(&format!(".{}", fraction)),
+                None => match text.strip_prefix("-0.") {
+                    Some(fraction) => self.push(&format!("-.{}", fraction)),
+                    None => self.push(&text),
+                },
+            },
+        }
+    }
+}
+
+impl ToCss for Stylesheet {
+    fn write_css(&self, dest: &mut Writer) {
+        // Top-level rules are separated by blank lines
+        let mut first = true;
+        let mut separate = |dest: &mut Writer| {
+            if dest.indent > 0 {
+                dest.newline();
+            } else if !first {
+                dest.newline();
+                dest.newline();
+            }
+            first = false;
+        };
+
+        for import in &self.imports {
+            separate(dest);
+            dest.push("@import url(\"");
+            dest.push(&import.url);
+            dest.push("\")");
+            if !import.media.0.is_empty() {
+                dest.push(" ");
+                import.media.write_css(dest);
+            }
+            dest.push(";");
+        }
+
+        // Consecutive rules in the same `@media` blocks are written in the same blocks
+        let mut open_media: &[MediaQueryList] = &[];
+        for rule in &self.rules {
+            let common = open_media
+                .iter()
+                .zip(&rule.media)
+                .take_while(|(a, b)| a == b)
+                .count();
+            for _ in common..open_media.len() {
+                dest.close_block();
+            }
+            for media in &rule.media[common..] {
+                separate(dest);
+                dest.push("@media ");
+                media.write_css(dest);
+                dest.open_block();
+            }
+            separate(dest);
+            rule.write_css(dest);
+            open_media = &rule.media;
+        }
+        for _ in open_media {
+            dest.close_block();
+        }
+        if dest.format == Format::Pretty && !dest.output.is_empty() {
+            dest.push("\n");
+        }
+    }
+}
+
+/// Writes the selectors and declarations of the rule. The `@media` blocks it is nested in are
+/// written by its stylesheet.
+impl ToCss for Rule {
+    fn write_css(&self, dest: &mut Writer) {
+        dest.comma_separated(&self.selectors, |dest, selector| selector.write_css(dest));
+        dest.open_block();
+        let mut declarations = &self.declarations[..];
+        while let Some(first) = declarations.first() {
+            // Write the longhands set by a shorthand that contains `var()` as the shorthand
+            let count = match shorthand(first) {
+                Some((name, css)) => declarations
+                    .iter()
+                    .take_while(|d| {
+                        d.important == first.important && shorthand(d) == Some((name, css))
+                    })
+                    .count(),
+                None => 1,
+            };
+            dest.newline();
+            match shorthand(first) {
+                Some((name, css)) => {
+                    write_declaration(dest, name, first.important, |dest| dest.push(css))
+                }
+                None => write_declaration(dest, &first.name, first.important, |dest| {
+                    first.value.write_css(dest)
+                }),
+            }
+            // The last `;` is optional
+            if count < declarations.len() || dest.format == Format::Pretty {
+                dest.push(";");
+            }
+            declarations = &declarations[count..];
+        }
+        dest.close_block();
+    }
+}
+
+/// The name of the shorthand and the unparsed value that set a declaration, if any
+fn shorthand(declaration: &Declaration) -> Option<(&str, &str)> {
+    match declaration.value {
+        Value::Unparsed(ref unparsed) => unparsed
+            .shorthand
+            .as_deref()
+            .map(|name| (name, &*unparsed.css)),
+        _ => None,
+    }
+}
+
+fn write_declaration(
+    dest: &mut Writer,
+    name: &str,
+    important: bool,
+    write_value: impl FnOnce(&mut Writer),
+) {
+    dest.push(name);
+    dest.either(": ", ":");
+    write_value(dest);
+    if important {
+        dest.either(" !important", "!important");
+    }
+}
+
+impl ToCss for Selector {
+    fn write_css(&self, dest: &mut Writer) {
+        match *self {
+            Selector::Simple(ref simple) => write_simple_selector(simple, dest),
+            Selector::Complex(ref ancestors, ref subject) => {
+                for (simple, combinator) in ancestors {
+                    write_simple_selector(simple, dest);
+                    match combinator {
+                        Combinator::Descendant => dest.push(" "),
+                        Combinator::Child => dest.either(" > ", ">"),
+                    }
+                }
+                write_simple_selector(subject, dest);
+            }
+        }
+    }
+}
+
+fn write_simple_selector(simple: &SimpleSelector, dest: &mut Writer) {
+    if simple.tag_name.is_none() && simple.id.is_none() && simple.class.is_empty() {
+        dest.push("*");
+    }
+    if let Some(ref tag_name) = simple.tag_name {
+        dest.push(tag_name);
+    }
+    if let Some(ref id) = simple.id {
+        dest.push("#");
+        dest.push(id);
+    }
+    for class in &simple.class {
+        dest.push(".");
+        dest.push(class);
+    }
+}
+
+impl ToCss for Value {
+    fn write_css(&self, dest: &mut Writer) {
+        match *self {
+            Value::Keyword(ref keyword) => dest.push(keyword),
+            Value::String(ref string) => write_string(dest, string),
+            Value::Url(ref url) => {
+                dest.push("url(");
+                write_string(dest, url);
+                dest.push(")");
+            }
+            Value::Length(value, unit) => {
+                dest.number(value);
+                dest.push(unit_name(unit));
+            }
+            Value::Number(n) => dest.number(n),
+            Value::ColorValue(color) => write_color(dest, color),
+            Value::List(ref components) => {
+                for (i, component) in components.iter().enumerate() {
+                    match component {
+                        Value::Delim(',') => dest.either(", ", ","),
+                        Value::Delim(_) => {}
+                        _ if i == 0 || matches!(components[i - 1], Value::Delim(_)) => {}
+                        _ => dest.push(" "),
+                    }
+                    if !matches!(component, Value::Delim(',')) {
+                        component.write_css(dest);
+                    }
+                }
+            }
+            Value::Delim(c) => dest.push(&c.to_string()),
+            Value::Calc(ref node) => match **node {
+                CalcNode::Min(_) | CalcNode::Max(_) | CalcNode::Clamp(..) => node.write_css(dest),
+                _ => {
+                    dest.push("calc(");
+                    node.write_css(dest);
+                    dest.push(")");
+                }
+            },
+            Value::Unparsed(ref unparsed) => dest.push(&unparsed.css),
+        }
+    }
+}
+
+/// Write a string in double quotes, escaping quotes and backslashes
+fn write_string(dest: &mut Writer, string: &str) {
+    dest.push("\"");
+    dest.push(&string.replace('\\', "\\\\").replace('"', "\\\""));
+    dest.push("\"");
+}
+
+fn unit_name(unit: Unit) -> &'static str {
+    match unit {
+        Unit::Px => "px",
+        Unit::Pt => "pt",
+        Unit::Pc => "pc",
+        Unit::Cm => "cm",
+        Unit::Mm => "mm",
+        Unit::In => "in",
+        Unit::Q => "q",
+        Unit::Em => "em",
+        Unit::Rem => "rem",
+        Unit::Ex => "ex",
+        Unit::Ch => "ch",
+        Unit::Vw => "vw",
+        Unit::Vh => "vh",
+        Unit::Vmin => "vmin",
+        Unit::Vmax => "vmax",
+        Unit::Percent => "%",
+    }
+}
+
+/// Write a color as `#rrggbb`, or `#rrggbbaa` if it isn't opaque. When minifying, use `#rgb` or
+/// `#rgba` if possible.
+fn write_color(dest: &mut Writer, color: Color) {
+    let mut channels = vec![color.r, color.g, color.b];
+    if color.a != 255 {
+        channels.push(color.a);
+    }
+    let short = dest.format == Format::Minified && channels.iter().all(|c| c >> 4 == c & 0xf);
+    dest.push("#");
+    for channel in channels {
+        if short {
+            dest.push(&format!("{:x}", channel & 0xf));
+        } else {
+            dest.push(&format!("{:02x}", channel));
+        }
+    }
+}
+
+/// Writes the inside of a math function
+impl ToCss for CalcNode {
+    fn write_css(&self, dest: &mut Writer) {
+        match self {
+            CalcNode::Number(n) => dest.number(*n),
+            CalcNode::Length(value, unit) => {
+                dest.number(*value);
+                dest.push(unit_name(*unit));
+            }
+            CalcNode::Sum(terms) => {
+                for (i, term) in terms.iter().enumerate() {
+                    match term {
+                        CalcNode::Negate(term) if i > 0 => {
+                            // `+` and `-` must be surrounded by whitespace
+                            dest.push(" - ");
+                            write_calc_operand(dest, term);
+                        }
+                        term => {
+                            if i > 0 {
+                                dest.push(" + ");
+                            }
+                            write_calc_operand(dest, term);
+                        }
+                    }
+                }
+            }
+            CalcNode::Product(factors) => {
+                for (i, factor) in factors.iter().enumerate() {
+                    match factor {
+                        CalcNode::Invert(factor) if i > 0 => {
+                            dest.either(" / ", "/");
+                            write_calc_operand(dest, factor);
+                        }
+                        factor => {
+                            if i > 0 {
+                                dest.either(" * ", "*");
+                            }
+                            write_calc_operand(dest, factor);
+                        }
+                    }
+                }
+            }
+            CalcNode::Negate(node) => {
+                dest.number(-1.0);
+                dest.either(" * ", "*");
+                write_calc_operand(dest, node);
+            }
+            CalcNode::Invert(node) => {
+                dest.number(1.0);
+                dest.either(" / ", "/");
+                write_calc_operand(dest, node);
+            }
+            CalcNode::Min(nodes) => write_calc_function(dest, "min", nodes),
+            CalcNode::Max(nodes) => write_calc_function(dest, "max", nodes),
+            CalcNode::Clamp(min, value, max) => {
+                let arguments = [(**min).clone(), (**value).clone(), (**max).clone()];
+                write_calc_function(dest, "clamp", &arguments)
+            }
+        }
+    }
+}
+
+/// Write an operand of a sum or product, with parentheses if it's an operation itself
+fn write_calc_operand(dest: &mut Writer, node: &CalcNode) {
+    match node {
+        CalcNode::Sum(_) | CalcNode::Product(_) | CalcNode::Negate(_) | CalcNode::Invert(_) => {
+            dest.push("(");
+            node.write_css(dest);
+            dest.push(")");
+        }
+        _ => node.write_css(dest),
+    }
+}
+
+fn write_calc_function(dest: &mut Writer, name: &str, arguments: &[CalcNode]) {
+    dest.push(name);
+    dest.push("(");
+    dest.comma_separated(arguments, |dest, argument| argument.write_css(dest));
+    dest.push(")");
+}
+
+impl ToCss for MediaQueryList {
+    fn write_css(&self, dest: &mut Writer) {
+        dest.comma_separated(&self.0, |dest, query| query.write_css(dest));
+    }
+}
+
+impl ToCss for MediaQuery {
+    fn write_css(&self, dest: &mut Writer) {
+        match (&self.media_type, &self.condition) {
+            (Some(media_type), condition) => {
+                if self.negated {
+                    dest.push("not ");
+                }
+                dest.push(media_type);
+                if let Some(condition) = condition {
+                    dest.push(" and ");
+                    // `or` isn't allowed at the top level after a media type
+                    match condition {
+                        MediaCondition::Or(_) => write_media_in_parens(dest, condition),
+                        condition => condition.write_css(dest),
+                    }
+                }
+            }
+            (None, Some(condition)) => condition.write_css(dest),
+            (None, None) => dest.push("all"),
+        }
+    }
+}
+
+impl ToCss for MediaCondition {
+    fn write_css(&self, dest: &mut Writer) {
+        let mut write_list = |conditions: &[MediaCondition], operator: &str| {
+            for (i, condition) in conditions.iter().enumerate() {
+                if i > 0 {
+                    dest.push(operator);
+                }
+                write_media_in_parens(dest, condition);
+            }
+        };
+        match self {
+            MediaCondition::Feature(feature) => {
+                dest.push("(");
+                feature.write_css(dest);
+                dest.push(")");
+            }
+            MediaCondition::Not(condition) => {
+                dest.push("not ");
+                write_media_in_parens(dest, condition);
+            }
+            MediaCondition::And(conditions) => write_list(conditions, " and "),
+            MediaCondition::Or(conditions) => write_list(conditions, " or "),
+        }
+    }
+}
+
+/// Write a media condition inside parentheses, unless it's a feature, which has its own
+fn write_media_in_parens(dest: &mut Writer, condition: &MediaCondition) {
+    match condition {
+        MediaCondition::Feature(_) => condition.write_css(dest),
+        _ => {
+            dest.push("(");
+            condition.write_css(dest);
+            dest.push(")");
+        }
+    }
+}
+
+impl ToCss for MediaFeature {
+    fn write_css(&self, dest: &mut Writer) {
+        match self {
+            MediaFeature::Boolean(name) => dest.push(name),
+            MediaFeature::Plain(name, value) => {
+                dest.push(name);
+                dest.either(": ", ":");
+                value.write_css(dest);
+            }
+            MediaFeature::Range(name, comparison, value) => {
+                dest.push(name);
+                let operator = match comparison {
+                    Comparison::Equal => "=",
+                    Comparison::LessThan => "<",
+                    Comparison::LessThanOrEqual => "<=",
+                    Comparison::GreaterThan => ">",
+                    Comparison::GreaterThanOrEqual => ">=",
+                };
+                dest.either(&format!(" {} ", operator), operator);
+                value.write_css(dest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::css::{self, Declaration, Import, SimpleSelector, UnparsedValue};
+    use proptest::prelude::*;
+
+    const SOURCE: &str = "
+        @import 'base.css' print;
+        h1, p.intro { margin: 0 auto; color: rgba(255, 0, 0, 0.5) !important; }
+        @media screen and (min-width: 600px) {
+            p { font-size: 1.5em; }
+            @media (orientation: landscape) { #main { width: calc(100% - 20px); } }
+        }
+        * { --gap: { 2px }; padding: var(--gap) 0; }
+    ";
+
+    #[test]
+    fn test_pretty_print() {
+        let stylesheet = css::parse(SOURCE.to_string());
+        let expected = r#"@import url("base.css") print;
+
+p.intro, h1 {
+  margin-top: 0;
+  margin-right: auto;
+  margin-bottom: 0;
+  margin-left: auto;
+  color: #ff000080 !important;
+}
+
+@media screen and (width >= 600px) {
+  p {
+    font-size: 1.5em;
+  }
+  @media (orientation: landscape) {
+    #main {
+      width: calc(100% + -20px);
+    }
+  }
+}
+
+* {
+  --gap: { 2px };
+  padding: var(--gap) 0;
+}
+"#;
+        assert_eq!(expected, stylesheet.to_css(Format::Pretty));
+    }
+
+    #[test]
+    fn test_minify() {
+        let stylesheet = css::parse(SOURCE.to_string());
+        let expected = concat!(
+            r#"@import url("base.css") print;"#,
+            "p.intro,h1{margin-top:0;margin-right:auto;margin-bottom:0;margin-left:auto;",
+            "color:#ff000080!important}",
+            "@media screen and (width>=600px){p{font-size:1.5em}",
+            "@media (orientation:landscape){#main{width:calc(100% + -20px)}}}",
+            "*{--gap:{ 2px };padding:var(--gap) 0}",
+        );
+        assert_eq!(expected, stylesheet.to_css(Format::Minified));
+        let value = |source: &str| {
+            // `font-family` accepts any value
+            css::parse_declaration_list(format!("font-family: {}", source))
+                .remove(0)
+                .value
+                .to_css(Format::Minified)
+        };
+        assert_eq!(
+            ".5em -.25 #abc #1234 #abcdef",
+            value("0.5em -0.25 #aabbcc #11223344 #abcdef")
+        );
+        assert_eq!("1px/2 a,b", value("1px / 2 a , b"));
+        assert_eq!("min(10%,1em)", value("min(10%, 1em)"));
+        assert_eq!("calc(2*min(10%,1em))", value("calc(min(10%, 1em) * 2)"));
+    }
+
+    /// The selectors, declarations and media queries of a rule
+    type RuleContents<'a> = (&'a [Selector], &'a [Declaration], &'a [MediaQueryList]);
+
+    /// The rules and imports of a stylesheet, without the locations of the rules
+    fn contents(stylesheet: &Stylesheet) -> (Vec<RuleContents<'_>>, &[Import]) {
+        let rules = stylesheet
+            .rules
+            .iter()
+            .map(|rule| (&rule.selectors[..], &rule.declarations[..], &rule.media[..]))
+            .collect();
+        (rules, &stylesheet.imports)
+    }
+
+    /// Serializing and re-parsing a stylesheet gives an equal stylesheet, except that its rules
+    /// may have moved
+    fn assert_round_trip(stylesheet: &Stylesheet) {
+        for format in [Format::Pretty, Format::Minified] {
+            let source = stylesheet.to_css(format);
+            let reparsed = css::parse(source.clone());
+            assert_eq!(contents(stylesheet), contents(&reparsed), "{}", source);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_sources() {
+        let sources = [
+            SOURCE,
+            include_str!("../examples/test.css"),
+            include_str!("../examples/perf-rainbow.css"),
+            "div, p#x.a.b, .c { border: 1px solid currentColor; background: transparent; }",
+            "p { width: calc((100% - 3 * 10px) / 4 + 1em); height: clamp(1em, 10vw, 2in); }",
+            "p { font-family: 12px/1.5 serif -1.5em +2REM 50% 1e3vw 2.5e-2Q .5vmin -3 -webkit-box; }",
+            "@media not print and ((color) or (hover)), (400px < width <= 700px) { p { color: red } }",
+            "@media (not (color)) or (aspect-ratio: 16/9) { p { border-left: var(--b) !important } }",
+            "p { color: hsl(120deg 100% 50%); --empty:; --x: var(--y, (1px)) ';' }",
+        ];
+        for source in sources {
+            assert_round_trip(&css::parse(source.to_string()));
+        }
+    }
+
+    fn keyword() -> impl Strategy<Value = Value> {
+        // Keywords that can't be mistaken for colors
+        prop_oneof![
+            Just("auto".to_string()),
+            Just("solid".to_string()),
+            "x[a-z0-9-]{0,5}",
+        ]
+        .prop_map(Value::Keyword)
+    }
+
+    fn component() -> impl Strategy<Value = Value> {
+        let units = [
+            Unit::Px,
+            Unit::Pt,
+            Unit::Q,
+            Unit::Em,
+            Unit::Ex,
+            Unit::Vmin,
+            Unit::Percent,
+        ];
+        prop_oneof![
+            keyword(),
+            "[a-zA-Z '\"\\\\]{0,8}".prop_map(Value::String),
+            "[a-z./ ]{0,8}".prop_map(Value::Url),
+            (-1e4f32..1e4, proptest::sample::select(units.to_vec()))
+                .prop_map(|(value, unit)| Value::Length(value, unit)),
+            (-1e4f32..1e4).prop_map(Value::Number),
+            any::<[u8; 4]>().prop_map(|[r, g, b, a]| Value::ColorValue(Color { r, g, b, a })),
+        ]
+    }
+
+    fn value() -> impl Strategy<Value = Value> {
+        let list = proptest::collection::vec(
+            (
+                component(),
+                proptest::sample::select(vec![None, Some(','), Some('/')]),
+            ),
+            2..5,
+        )
+        .prop_map(|components| {
+            let last = components.len() - 1;
+            let mut list = Vec::new();
+            for (i, (component, delim)) in components.into_iter().enumerate() {
+                list.push(component);
+                match delim {
+                    Some(delim) if i < last => list.push(Value::Delim(delim)),
+                    _ => {}
+                }
+            }
+            Value::List(list)
+        });
+        prop_oneof![component(), list]
+    }
+
+    fn declaration() -> impl Strategy<Value = Declaration> {
+        let units = vec![Unit::Px, Unit::Em, Unit::Vmin, Unit::Percent];
+        let length = |min: f32| {
+            (min..1e4, proptest::sample::select(units.clone()))
+                .prop_map(|(value, unit)| Value::Length(value, unit))
+        };
+        let color =
+            any::<[u8; 4]>().prop_map(|[r, g, b, a]| Value::ColorValue(Color { r, g, b, a }));
+        let keyword = |keywords: Vec<&'static str>| {
+            proptest::sample::select(keywords).prop_map(|k| Value::Keyword(k.to_string()))
+        };
+        let unparsed = |css: &str| {
+            Value::Unparsed(UnparsedValue {
+                css: css.to_string(),
+                shorthand: None,
+            })
+        };
+        let custom = vec![unparsed(""), unparsed("1px solid"), unparsed("{ a: b; }")];
+        let name_and_value = prop_oneof![
+            // Each property gets values it accepts
+            (Just("font-family".to_string()), value()),
+            (Just("color".to_string()), color),
+            (
+                Just("margin-top".to_string()),
+                prop_oneof![length(-1e4), Just(Value::Keyword("auto".to_string()))]
+            ),
+            (
+                Just("width".to_string()),
+                prop_oneof![length(0.0), Just(Value::Keyword("auto".to_string()))]
+            ),
+            (
+                Just("display".to_string()),
+                keyword(vec!["inherit", "none", "block", "inline-block"])
+            ),
+            (
+                Just("border-left-style".to_string()),
+                keyword(vec!["initial", "none", "solid", "dashed"])
+            ),
+            ("--[a-z]{1,5}", proptest::sample::select(custom)),
+            Just(("width".to_string(), unparsed("var(--w, 1px)"))),
+        ];
+        (name_and_value, any::<bool>()).prop_map(|((name, value), important)| Declaration {
+            name,
+            value,
+            important,
+        })
+    }
+
+    fn simple_selector() -> impl Strategy<Value = SimpleSelector> {
+        (
+            proptest::option::of("[a-z][a-z0-9]{0,4}"),
+            proptest::option::of("[a-z][a-z0-9_-]{0,5}"),
+            proptest::collection::vec("[a-z][a-z0-9_-]{0,5}", 0..3),
+        )
+            .prop_map(|(tag_name, id, class)| SimpleSelector {
+                tag_name,
+                id,
+                class,
+            })
+    }
+
+    fn selector() -> impl Strategy<Value = Selector> {
+        let combinator = prop_oneof![Just(Combinator::Descendant), Just(Combinator::Child)];
+        (
+            proptest::collection::vec((simple_selector(), combinator), 0..3),
+            simple_selector(),
+        )
+            .prop_map(|(ancestors, subject)| {
+                if ancestors.is_empty() {
+                    Selector::Simple(subject)
+                } else {
+                    Selector::Complex(ancestors, subject)
+                }
+            })
+    }
+
+    fn media_condition() -> impl Strategy<Value = MediaCondition> {
+        let comparisons = vec![
+            Comparison::Equal,
+            Comparison::LessThan,
+            Comparison::LessThanOrEqual,
+            Comparison::GreaterThan,
+            Comparison::GreaterThanOrEqual,
+        ];
+        let feature = prop_oneof![
+            Just(MediaFeature::Boolean("color".to_string())),
+            Just(MediaFeature::Plain(
+                "orientation".to_string(),
+                Value::Keyword("portrait".to_string())
+            )),
+            (
+                proptest::sample::select(vec!["width", "height"]),
+                proptest::sample::select(comparisons.clone()),
+                -1e4f32..1e4,
+            )
+                .prop_map(|(name, comparison, px)| {
+                    MediaFeature::Range(name.to_string(), comparison, Value::Length(px, Unit::Px))
+                }),
+            (proptest::sample::select(comparisons), 1..20u8, 1..20u8).prop_map(
+                |(comparison, a, b)| {
+                    let ratio = vec![
+                        Value::Number(a as f32),
+                        Value::Delim('/'),
+                        Value::Number(b as f32),
+                    ];
+                    MediaFeature::Range("aspect-ratio".to_string(), comparison, Value::List(ratio))
+                }
+            ),
+        ];
+        feature
+            .prop_map(MediaCondition::Feature)
+            .prop_recursive(3, 12, 3, |inner| {
+                prop_oneof![
+                    inner.clone().prop_map(|c| MediaCondition::Not(Box::new(c))),
+                    proptest::collection::vec(inner.clone(), 2..4).prop_map(MediaCondition::And),
+                    proptest::collection::vec(inner, 2..4).prop_map(MediaCondition::Or),
+                ]
+            })
+    }
+
+    fn media_query_list() -> impl Strategy<Value = MediaQueryList> {
+        let query = prop_oneof![
+            (
+                any::<bool>(),
+                proptest::sample::select(vec!["all", "screen", "print"]),
+                proptest::option::of(media_condition()),
+            )
+                .prop_map(|(negated, media_type, condition)| MediaQuery {
+                    negated,
+                    media_type: Some(media_type.to_string()),
+                    condition,
+                }),
+            media_condition().prop_map(|condition| MediaQuery {
+                negated: false,
+                media_type: None,
+                condition: Some(condition),
+            }),
+        ];
+        proptest::collection::vec(query, 1..3).prop_map(MediaQueryList)
+    }
+
+    fn rule() -> impl Strategy<Value = Rule> {
+        (
+            proptest::collection::vec(selector(), 1..4),
+            proptest::collection::vec(declaration(), 0..5),
+            proptest::collection::vec(media_query_list(), 0..3),
+        )
+            .prop_map(|(mut selectors, declarations, media)| {
+                // The parser sorts selectors by decreasing specificity
+                selectors.sort_by_key(|s| std::cmp::Reverse(s.specificity()));
+                Rule {
+                    selectors,
+                    declarations,
+                    media,
+                    location: Default::default(),
+                }
+            })
+    }
+
+    fn stylesheet() -> impl Strategy<Value = Stylesheet> {
+        let import = ("[a-z]{1,6}\\.css", proptest::option::of(media_query_list())).prop_map(
+            |(url, media)| Import {
+                url,
+                media: media.unwrap_or(MediaQueryList(Vec::new())),
+            },
+        );
+        (
+            proptest::collection::vec(import, 0..3),
+            proptest::collection::vec(rule(), 0..6),
+        )
+            .prop_map(|(imports, rules)| Stylesheet { rules, imports })
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(stylesheet in stylesheet()) {
+            assert_round_trip(&stylesheet);
+        }
+    }
+}
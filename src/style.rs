@@ -1,6 +1,21 @@
-use crate::css::{Rule, Selector, SimpleSelector, Specificity, Stylesheet, Value};
+use crate::bloom::{self, AncestorFilter};
+use crate::calc::{CalcNode, CalcType};
+use crate::coverage::{Coverage, RuleCoverage};
+use crate::css::{
+    self, Combinator, Declaration, LengthContext, Rule, Selector, SimpleSelector, SourceLocation,
+    Specificity, Stylesheet, Unit, UnparsedValue, Value, DEFAULT_FONT_SIZE,
+};
 use crate::dom::{ElementData, Node, NodeType};
-use std::collections::HashMap;
+use crate::font;
+use crate::media::Device;
+use crate::properties::{self, ComputedStyle, ComputedValue, PROPERTIES};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+pub use crate::properties::Display;
 
 /// Map from CSS property names to values
 type PropertyMap = HashMap<String, Value>;
@@ -9,111 +24,1096 @@ type PropertyMap = HashMap<String, Value>;
 #[derive(Debug, PartialEq)]
 pub struct StyledNode<'a> {
     node: &'a Node, // pointer to a DOM node
+    /// The winners of the cascade, with `var()` substituted
     pub specified_values: PropertyMap,
+    /// The values that layout and painting use: every known property has one, inherited and
+    /// initial values are filled in, lengths are in px and colors are resolved
+    pub style: Arc<ComputedStyle>,
     pub children: Vec<StyledNode<'a>>,
 }
 
-#[derive(PartialEq)]
-pub enum Display {
-    Inline,
-    Block,
-    None,
-}
-
 impl<'a> StyledNode<'a> {
-    /// Return the specified value of a property if it exists, otherwise `None`
+    /// The DOM node that this node styles
+    pub fn node(&self) -> &'a Node {
+        self.node
+    }
+
+    /// Return the computed value of a property if it exists, otherwise `None`
     pub fn value(&self, name: &str) -> Option<Value> {
-        self.specified_values.get(name).cloned()
+        self.style.get(name)
     }
 
-    /// Return the specified value of property `name, or property `fallback_name` if that
-    /// doesn't exist, or value `defalut` if neither does
-    pub fn lookup(&self, name: &str, fallback_name: &str, default: &Value) -> Value {
-        self.value(name)
-            .unwrap_or_else(|| self.value(fallback_name).unwrap_or_else(|| default.clone()))
+    /// Return the computed value of property `name`, or value `default` if it doesn't exist
+    pub fn lookup(&self, name: &str, default: &Value) -> Value {
+        self.value(name).unwrap_or_else(|| default.clone())
     }
 
-    /// The value of the `display property (defaults to inline)
+    /// The value of the `display` property
     pub fn display(&self) -> Display {
-        match self.value("display") {
-            Some(Value::Keyword(s)) => match &*s {
-                "block" => Display::Block,
-                "none" => Display::None,
-                _ => Display::Inline,
-            },
-            _ => Display::Inline,
+        self.style.display
+    }
+}
+
+/// Where a stylesheet comes from. For normal declarations, later origins take precedence.
+///
+/// ref: https://www.w3.org/TR/css-cascade-4/#cascading-origins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Origin {
+    UserAgent,
+    User,
+    Author,
+}
+
+/// The built-in user-agent stylesheet, with the default rendering of HTML elements.
+///
+/// ref: https://html.spec.whatwg.org/multipage/rendering.html
+pub fn user_agent_stylesheet() -> Stylesheet {
+    css::parse_file(include_str!("ua.css").to_string(), Path::new("ua.css"))
+}
+
+/// The combination of origin and importance, in increasing order of precedence.
+/// `!important` reverses the order of the origins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum CascadeLevel {
+    UserAgentNormal,
+    UserNormal,
+    AuthorNormal,
+    AuthorImportant,
+    UserImportant,
+    UserAgentImportant,
+}
+
+impl CascadeLevel {
+    fn origin(self) -> Origin {
+        match self {
+            CascadeLevel::UserAgentNormal | CascadeLevel::UserAgentImportant => Origin::UserAgent,
+            CascadeLevel::UserNormal | CascadeLevel::UserImportant => Origin::User,
+            CascadeLevel::AuthorNormal | CascadeLevel::AuthorImportant => Origin::Author,
+        }
+    }
+
+    fn new(origin: Origin, important: bool) -> CascadeLevel {
+        match (origin, important) {
+            (Origin::UserAgent, false) => CascadeLevel::UserAgentNormal,
+            (Origin::User, false) => CascadeLevel::UserNormal,
+            (Origin::Author, false) => CascadeLevel::AuthorNormal,
+            (Origin::Author, true) => CascadeLevel::AuthorImportant,
+            (Origin::User, true) => CascadeLevel::UserImportant,
+            (Origin::UserAgent, true) => CascadeLevel::UserAgentImportant,
         }
     }
 }
 
-/// Apply a stylesheet to an entire DOM tree, returning a StyledNode tree.
+/// The position of a declaration in the cascade. Declarations with greater keys win.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct CascadeKey {
+    level: CascadeLevel,
+    /// Was the declaration in the element's `style` attribute? These beat declarations from
+    /// rules at the same level, whatever their specificity.
+    inline: bool,
+    specificity: Specificity,
+    /// The index of the rule across all stylesheets, then of the declaration within the rule
+    source_order: (usize, usize),
+}
+
+/// Apply stylesheets to an entire DOM tree, returning a StyledNode tree.
 ///
-/// This finds only the specified values at the moment. Eventually it should be extended to find the
-/// computed values too, including inherited values.
-pub fn style_tree<'a>(root: &'a Node, stylesheet: &'a Stylesheet) -> StyledNode<'a> {
+/// `stylesheets` are given in the order they were loaded, each tagged with its origin. Rules in
+/// `@media` blocks only apply if their media queries match `device`.
+///
+/// Each node gets its specified values, and computed values that inherit from its parent's.
+pub fn style_tree<'a>(
+    root: &'a Node,
+    stylesheets: &[(Origin, &Stylesheet)],
+    device: &Device,
+) -> StyledNode<'a> {
+    style_tree_with_stats(root, stylesheets, device, false).0
+}
+
+/// Like `style_tree`, but style sibling subtrees in parallel on the rayon thread pool. The
+/// result is the same.
+pub fn style_tree_parallel<'a>(
+    root: &'a Node,
+    stylesheets: &[(Origin, &Stylesheet)],
+    device: &Device,
+) -> StyledNode<'a> {
+    style_tree_with_stats(root, stylesheets, device, true).0
+}
+
+/// Like `style_tree`, or `style_tree_parallel` if `parallel` is set, but also return how often
+/// nodes could share the style of a sibling or cousin
+pub fn style_tree_with_stats<'a>(
+    root: &'a Node,
+    stylesheets: &[(Origin, &Stylesheet)],
+    device: &Device,
+    parallel: bool,
+) -> (StyledNode<'a>, StyleSharingStats) {
+    let traversal = Traversal::new(RuleIndex::new(stylesheets, device, false), parallel);
+    let root = traversal.style_tree(root, device);
+    let stats = traversal
+        .caches
+        .into_iter()
+        .map(|cache| cache.into_inner().unwrap().stats)
+        .fold(StyleSharingStats::default(), |total, stats| {
+            StyleSharingStats {
+                hits: total.hits + stats.hits,
+                misses: total.misses + stats.misses,
+            }
+        });
+    (root, stats)
+}
+
+/// Like `style_tree_parallel`, but also count how many elements each rule and selector
+/// matched. Style sharing is off, so that every element goes through selector matching.
+pub fn style_tree_with_coverage<'a, 'r>(
+    root: &'a Node,
+    stylesheets: &[(Origin, &'r Stylesheet)],
+    device: &Device,
+) -> (StyledNode<'a>, Coverage<'r>) {
+    let traversal = Traversal::new(RuleIndex::new(stylesheets, device, true), true);
+    let root = traversal.style_tree(root, device);
+    let counts = traversal.rules.counts.unwrap();
+    let rules = all_rules(stylesheets)
+        .map(|(source_order, (origin, rule))| RuleCoverage {
+            rule,
+            origin,
+            applies: device.matches_all(&rule.media),
+            matches: counts.rules[source_order].load(Ordering::Relaxed),
+            selectors: rule
+                .selectors
+                .iter()
+                .zip(&counts.selectors[source_order])
+                .map(|(selector, count)| (selector, count.load(Ordering::Relaxed)))
+                .collect(),
+        })
+        .collect();
+    (root, Coverage { rules })
+}
+
+/// The state shared by the whole styling pass
+struct Traversal<'a, 'r> {
+    rules: RuleIndex<'r>,
+    /// One style sharing cache per thread of the thread pool, so that threads don't wait for
+    /// each other, and a last one for the thread that started the pass
+    caches: Vec<Mutex<StyleSharingCache<'a>>>,
+    /// Style the children of a node in parallel?
+    parallel: bool,
+}
+
+impl<'a, 'r> Traversal<'a, 'r> {
+    fn new(rules: RuleIndex<'r>, parallel: bool) -> Traversal<'a, 'r> {
+        // With a single thread, parallel styling is only overhead
+        let parallel = parallel && rayon::current_num_threads() > 1;
+        let threads = if parallel {
+            rayon::current_num_threads()
+        } else {
+            0
+        };
+        Traversal {
+            rules,
+            caches: (0..=threads).map(|_| Default::default()).collect(),
+            parallel,
+        }
+    }
+
+    fn style_tree(&self, root: &'a Node, device: &Device) -> StyledNode<'a> {
+        let context = ComputeContext {
+            device,
+            root_font_size: DEFAULT_FONT_SIZE,
+            parent_style: properties::initial_style(),
+            is_root: true,
+            ancestors: None,
+            filter: &AncestorFilter::default(),
+        };
+        style_node(root, self, &context)
+    }
+
+    /// The style sharing cache of the current thread. Don't hold it while styling other nodes:
+    /// a thread that waits for its children can steal the work of styling another node.
+    fn cache(&self) -> MutexGuard<'_, StyleSharingCache<'a>> {
+        let last = self.caches.len() - 1;
+        let index = rayon::current_thread_index()
+            .filter(|&index| index < last)
+            .unwrap_or(last);
+        self.caches[index].lock().unwrap()
+    }
+}
+
+/// What the computed values of a node depend on, besides its specified values
+struct ComputeContext<'a> {
+    device: &'a Device,
+    /// The computed font size of the root element, for `rem`
+    root_font_size: f32,
+    /// The computed style of the parent node, or the initial style for the root
+    parent_style: &'a Arc<ComputedStyle>,
+    is_root: bool,
+    /// The parent element, for selectors with combinators
+    ancestors: Option<&'a Ancestor<'a>>,
+    /// The names of all the ancestors
+    filter: &'a AncestorFilter,
+}
+
+/// An element and its ancestors, innermost first
+struct Ancestor<'a> {
+    elem: &'a ElementData,
+    parent: Option<&'a Ancestor<'a>>,
+}
+
+/// How many nodes reused a style from the style sharing cache
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StyleSharingStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl StyleSharingStats {
+    /// The fraction of nodes that reused a style
+    pub fn hit_rate(&self) -> f32 {
+        self.hits as f32 / (self.hits + self.misses).max(1) as f32
+    }
+}
+
+/// Everything the style of a node depends on. Nodes with equal keys get the same style.
+#[derive(PartialEq, Eq, Hash)]
+struct SharingKey<'a> {
+    /// The address of the parent's computed style, which siblings and cousins can share. Nodes
+    /// only get the same parent style if their ancestors have the same tags and attributes, so
+    /// selectors with combinators match them the same way.
+    parent: usize,
+    /// The tag name and sorted attributes of an element, or `None` for a text node
+    element: Option<(&'a str, Vec<(&'a str, &'a str)>)>,
+}
+
+/// The styles of the nodes styled so far, so that siblings and cousins with the same tag,
+/// attributes and parent style don't go through selector matching and the cascade again
+#[derive(Default)]
+struct StyleSharingCache<'a> {
+    styles: HashMap<SharingKey<'a>, (PropertyMap, Arc<ComputedStyle>)>,
+    stats: StyleSharingStats,
+}
+
+impl<'a> SharingKey<'a> {
+    /// The key of `node`, or `None` if it can't share its style. Elements with an `id` are
+    /// unique, so they aren't worth caching.
+    fn new(node: &'a Node, parent_style: &Arc<ComputedStyle>) -> Option<SharingKey<'a>> {
+        let element = match node.node_type {
+            NodeType::Element(ref elem) if elem.id().is_some() => return None,
+            NodeType::Element(ref elem) => {
+                let mut attributes: Vec<(&str, &str)> = elem
+                    .attributes
+                    .iter()
+                    .map(|(name, value)| (&**name, &**value))
+                    .collect();
+                attributes.sort_unstable();
+                Some((&*elem.tag_name, attributes))
+            }
+            NodeType::Text(_) => None,
+        };
+        Some(SharingKey {
+            parent: Arc::as_ptr(parent_style) as usize,
+            element,
+        })
+    }
+}
+
+/// Style `node` and its descendants
+fn style_node<'a>(
+    node: &'a Node,
+    traversal: &Traversal<'a, '_>,
+    context: &ComputeContext,
+) -> StyledNode<'a> {
+    // Nodes that share a style skip selector matching, so they would be missing from the
+    // match counts
+    let key = match traversal.rules.counts {
+        Some(_) => None,
+        None => SharingKey::new(node, context.parent_style),
+    };
+    let cached = key
+        .as_ref()
+        .and_then(|key| traversal.cache().styles.get(key).cloned());
+    let (specified_values, style) = match cached {
+        Some(cached) => {
+            traversal.cache().stats.hits += 1;
+            cached
+        }
+        None => {
+            let specified_values = match node.node_type {
+                NodeType::Element(ref elem) => {
+                    let mut values = specified_values(elem, context, &traversal.rules);
+                    substitute_variables(&mut values, &context.parent_style.other);
+                    values
+                }
+                NodeType::Text(_) => HashMap::new(),
+            };
+            let style = Arc::new(computed_style(&specified_values, context));
+            let mut cache = traversal.cache();
+            cache.stats.misses += 1;
+            if let Some(key) = key {
+                cache
+                    .styles
+                    .insert(key, (specified_values.clone(), Arc::clone(&style)));
+            }
+            (specified_values, style)
+        }
+    };
+    // The children of an element have it as their parent, and its names in their filter
+    let mut filter = *context.filter;
+    let ancestor = match node.node_type {
+        NodeType::Element(ref elem) => {
+            filter.insert_element(elem);
+            Some(Ancestor {
+                elem,
+                parent: context.ancestors,
+            })
+        }
+        NodeType::Text(_) => None,
+    };
+    let child_context = ComputeContext {
+        device: context.device,
+        root_font_size: if context.is_root {
+            style.font_size
+        } else {
+            context.root_font_size
+        },
+        parent_style: &style,
+        is_root: false,
+        ancestors: ancestor.as_ref(),
+        filter: &filter,
+    };
+    let style_child = |child| style_node(child, traversal, &child_context);
     StyledNode {
-        node: root,
-        specified_values: match root.node_type {
-            NodeType::Element(ref elem) => specified_values(elem, stylesheet),
-            NodeType::Text(_) => HashMap::new(),
+        node,
+        children: if traversal.parallel {
+            node.children.par_iter().map(style_child).collect()
+        } else {
+            node.children.iter().map(style_child).collect()
         },
-        children: root
-            .children
-            .iter()
-            .map(|child| style_tree(child, stylesheet))
-            .collect(),
+        specified_values,
+        style,
     }
 }
 
-/// Apply style to a single element, returning the specified styles
+/// Apply styles to a single element, returning the specified styles
+fn specified_values(
+    elem: &ElementData,
+    context: &ComputeContext,
+    rules: &RuleIndex,
+) -> PropertyMap {
+    let inline = inline_declarations(elem);
+    let candidates = cascade_order(elem, context.ancestors, context.filter, rules, &inline);
+    cascade(&candidates)
+        .into_iter()
+        .map(|(name, index)| {
+            (
+                name.to_string(),
+                candidates[index].declaration.value.clone(),
+            )
+        })
+        .collect()
+}
+
+/// The declarations of the element's `style` attribute
+fn inline_declarations(elem: &ElementData) -> Vec<Declaration> {
+    elem.attributes
+        .get("style")
+        .map(|style| css::parse_declaration_list(style.clone()))
+        .unwrap_or_default()
+}
+
+/// A declaration that applies to an element. Declarations of the `style` attribute can live
+/// shorter than the rules.
+struct Candidate<'r, 'd> {
+    key: CascadeKey,
+    declaration: &'d Declaration,
+    /// The rule and its selector that matched the element, or `None` for the `style` attribute
+    source: Option<(&'r Rule, &'r Selector)>,
+}
+
+/// The declarations of the rules that match `elem` and of its `inline` style, from highest to
+/// lowest precedence.
 ///
-/// To do: Allow multiple UA/author/user stylesheets, and implement the cascade
-fn specified_values(elem: &ElementData, stylesheet: &Stylesheet) -> PropertyMap {
-    let mut values = HashMap::new();
-    let mut rules = matching_rules(elem, stylesheet);
+/// Declarations are sorted by origin and importance, then whether they're inline, then
+/// specificity, then source order. The declarations of the `style` attribute belong to the
+/// author origin.
+fn cascade_order<'r: 'd, 'd>(
+    elem: &ElementData,
+    ancestors: Option<&Ancestor>,
+    filter: &AncestorFilter,
+    rules: &RuleIndex<'r>,
+    inline: &'d [Declaration],
+) -> Vec<Candidate<'r, 'd>> {
+    let mut candidates: Vec<Candidate> = matching_rules(elem, ancestors, filter, rules)
+        .into_iter()
+        .flat_map(|matched| {
+            matched
+                .rule
+                .declarations
+                .iter()
+                .enumerate()
+                .map(move |(index, declaration)| Candidate {
+                    key: CascadeKey {
+                        level: CascadeLevel::new(matched.origin, declaration.important),
+                        inline: false,
+                        specificity: matched.specificity,
+                        source_order: (matched.source_order, index),
+                    },
+                    declaration,
+                    source: Some((matched.rule, matched.selector)),
+                })
+        })
+        .chain(
+            inline
+                .iter()
+                .enumerate()
+                .map(|(index, declaration)| Candidate {
+                    key: CascadeKey {
+                        level: CascadeLevel::new(Origin::Author, declaration.important),
+                        inline: true,
+                        specificity: (0, 0, 0),
+                        source_order: (0, index),
+                    },
+                    declaration,
+                    source: None,
+                }),
+        )
+        .collect();
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.key));
+    candidates
+}
 
-    // Go through the rules from lowest to highest specificity
-    rules.sort_by(|&(a, _), &(b, _)| a.cmp(&b));
-    for (_, rule) in rules {
-        for declaration in &rule.declarations {
-            // if multiple rules is containd, hash map overwrite the most specified one.
-            values.insert(declaration.name.clone(), declaration.value.clone());
+/// Find the index of the winning declaration of each property in `candidates`, which are in
+/// cascade order. The first declaration for each property wins, unless it is `revert`, which
+/// rolls back to the declarations of the previous origins.
+fn cascade<'d>(candidates: &[Candidate<'_, 'd>]) -> HashMap<&'d str, usize> {
+    let mut winners = HashMap::new();
+    let mut reverted: HashMap<&str, Vec<Origin>> = HashMap::new();
+    for (index, candidate) in candidates.iter().enumerate() {
+        let name = &*candidate.declaration.name;
+        let origin = candidate.key.level.origin();
+        if winners.contains_key(name) || reverted.get(name).is_some_and(|o| o.contains(&origin)) {
+            continue;
+        }
+        match candidate.declaration.value {
+            Value::Keyword(ref keyword) if keyword.eq_ignore_ascii_case("revert") => {
+                reverted.entry(name).or_default().push(origin);
+            }
+            _ => {
+                winners.insert(name, index);
+            }
         }
     }
-    values
+    winners
+}
+
+/// A declaration that applies to an element, as listed by `explain`
+#[derive(Debug, PartialEq)]
+pub struct CascadeEntry<'a> {
+    pub value: Value,
+    pub important: bool,
+    pub origin: Origin,
+    /// The selector of the rule that matched the element, or `None` for the `style` attribute
+    pub selector: Option<&'a Selector>,
+    pub specificity: Specificity,
+    /// Where the rule starts, or `None` for the `style` attribute
+    pub location: Option<&'a SourceLocation>,
+    /// Did the declaration win the cascade? The others were overridden or reverted.
+    pub won: bool,
 }
 
-/// A single CSS rule and the specificity of its most specific matching selector
-type MatchedRule<'a> = (Specificity, &'a Rule);
+/// Why an element has its specified values
+#[derive(Debug, PartialEq)]
+pub struct Explanation<'a, 'n> {
+    pub element: &'n ElementData,
+    /// Each property that has declarations, by name, with its declarations from highest to
+    /// lowest precedence
+    pub properties: Vec<(String, Vec<CascadeEntry<'a>>)>,
+}
 
-/// Find all CSS rules that match the given element
-fn matching_rules<'a>(elem: &ElementData, stylesheet: &'a Stylesheet) -> Vec<MatchedRule<'a>> {
-    // For now, we just do a linear scan of all the rules.  For large
-    // documents, it would be more efficient to store the rules in hash tables
-    // based on tag name, id, class, etc.
-    stylesheet
-        .rules
+/// List the declarations that apply to each element matching one of `selectors`, in document
+/// order, and which of them won the cascade. Values are as written, before `var()` substitution.
+pub fn explain<'a, 'n>(
+    root: &'n Node,
+    selectors: &[Selector],
+    stylesheets: &[(Origin, &'a Stylesheet)],
+    device: &Device,
+) -> Vec<Explanation<'a, 'n>> {
+    let rules = RuleIndex::new(stylesheets, device, false);
+    let mut explanations = Vec::new();
+    explain_node(
+        root,
+        selectors,
+        &rules,
+        None,
+        &AncestorFilter::default(),
+        &mut explanations,
+    );
+    explanations
+}
+
+fn explain_node<'a, 'n>(
+    node: &'n Node,
+    selectors: &[Selector],
+    rules: &RuleIndex<'a>,
+    ancestors: Option<&Ancestor>,
+    filter: &AncestorFilter,
+    explanations: &mut Vec<Explanation<'a, 'n>>,
+) {
+    let elem = match node.node_type {
+        NodeType::Element(ref elem) => elem,
+        NodeType::Text(_) => return,
+    };
+    if selectors
         .iter()
-        .filter_map(|rule| match_rule(elem, rule))
-        .collect()
+        .any(|selector| matches(elem, ancestors, selector))
+    {
+        let inline = inline_declarations(elem);
+        let candidates = cascade_order(elem, ancestors, filter, rules, &inline);
+        let winners: HashSet<usize> = cascade(&candidates).into_values().collect();
+        let mut properties: BTreeMap<String, Vec<CascadeEntry>> = BTreeMap::new();
+        for (index, candidate) in candidates.iter().enumerate() {
+            let declaration = candidate.declaration;
+            properties
+                .entry(declaration.name.clone())
+                .or_default()
+                .push(CascadeEntry {
+                    value: declaration.value.clone(),
+                    important: declaration.important,
+                    origin: candidate.key.level.origin(),
+                    selector: candidate.source.map(|(_, selector)| selector),
+                    specificity: candidate.key.specificity,
+                    location: candidate.source.map(|(rule, _)| &rule.location),
+                    won: winners.contains(&index),
+                });
+        }
+        explanations.push(Explanation {
+            element: elem,
+            properties: properties.into_iter().collect(),
+        });
+    }
+
+    let mut filter = *filter;
+    filter.insert_element(elem);
+    let ancestor = Ancestor {
+        elem,
+        parent: ancestors,
+    };
+    for child in &node.children {
+        explain_node(
+            child,
+            selectors,
+            rules,
+            Some(&ancestor),
+            &filter,
+            explanations,
+        );
+    }
+}
+
+/// Calculate the computed value of every known property from the specified values of a node.
+/// Properties that aren't specified, or are set to `unset`, take their parent's value if they're
+/// inherited and their initial value otherwise. Unknown properties keep their specified values.
+///
+/// ref: https://www.w3.org/TR/css-cascade-4/#computed
+fn computed_style(specified: &PropertyMap, context: &ComputeContext) -> ComputedStyle {
+    let parent = context.parent_style;
+    let mut style = ComputedStyle::inherit(parent);
+    style.other = specified
+        .iter()
+        .filter(|(name, _)| properties::lookup(name).is_none())
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    for property in PROPERTIES {
+        let value = specified.get(property.name);
+        let keyword = match value {
+            Some(Value::Keyword(keyword)) => Some(keyword.to_ascii_lowercase()),
+            Some(_) => None,
+            None if property.inherited => Some("inherit".to_string()),
+            None => Some("initial".to_string()),
+        };
+        let inherit = match keyword.as_deref() {
+            Some("inherit") => true,
+            Some("unset") => property.inherited,
+            _ => false,
+        };
+        match (inherit, keyword.as_deref()) {
+            // `ComputedStyle::inherit` already filled in the values of unspecified properties
+            (true, _) if value.is_some() => style.copy_from(property.name, parent),
+            (true, _) => {}
+            // The initial value `currentcolor` depends on `color`
+            (_, Some("initial" | "unset")) if property.initial == "currentcolor" => {
+                style.set(property.name, &style.color.to_value());
+            }
+            (_, Some("initial" | "unset")) if value.is_some() => {
+                style.copy_from(property.name, properties::initial_style())
+            }
+            (_, Some("initial" | "unset")) => {}
+            _ => {
+                let value =
+                    compute_value(property.name, &specified[property.name], &style, context);
+                style.set(property.name, &property.grammar.clamp(value));
+            }
+        }
+    }
+
+    // Borders with no style have no width
+    for (border_style, width) in [
+        (style.border_top_style, &mut style.border_top_width),
+        (style.border_right_style, &mut style.border_right_width),
+        (style.border_bottom_style, &mut style.border_bottom_width),
+        (style.border_left_style, &mut style.border_left_width),
+    ] {
+        if !border_style.is_visible() {
+            *width = 0.0;
+        }
+    }
+    style
+}
+
+/// Compute the value of property `name` from its specified value. `style` holds the computed
+/// values of the properties that come before it in `PROPERTIES`.
+fn compute_value(
+    name: &str,
+    value: &Value,
+    style: &ComputedStyle,
+    context: &ComputeContext,
+) -> Value {
+    let parent_font_size = context.parent_style.font_size;
+    let length_context = LengthContext {
+        font_size: style.font_size,
+        root_font_size: context.root_font_size,
+        viewport_width: context.device.width,
+        viewport_height: context.device.height,
+        percentage_basis: 0.0,
+    };
+
+    match (name, value) {
+        // `em` and percentages refer to the parent's font size
+        ("font-size", Value::Keyword(keyword)) => Value::Length(
+            match &*keyword.to_ascii_lowercase() {
+                "xx-small" => 9.0,
+                "x-small" => 10.0,
+                "small" => 13.0,
+                "large" => 18.0,
+                "x-large" => 24.0,
+                "xx-large" => 32.0,
+                "xxx-large" => 48.0,
+                "larger" => parent_font_size * 1.2,
+                "smaller" => parent_font_size / 1.2,
+                _ => DEFAULT_FONT_SIZE,
+            },
+            Unit::Px,
+        ),
+        ("font-size", Value::Length(..) | Value::Calc(_)) => Value::Length(
+            value.to_px(&LengthContext {
+                font_size: parent_font_size,
+                percentage_basis: parent_font_size,
+                ..length_context
+            }),
+            Unit::Px,
+        ),
+        ("font-weight", Value::Keyword(keyword)) => {
+            let parent_weight = context.parent_style.font_weight.0;
+            Value::Number(f32::from(match &*keyword.to_ascii_lowercase() {
+                "bold" => 700,
+                "bolder" => font::relative_weight(parent_weight, true),
+                "lighter" => font::relative_weight(parent_weight, false),
+                _ => 400,
+            }))
+        }
+        ("line-height", Value::Calc(node)) if node.calc_type() == Ok(CalcType::Number) => {
+            absolute_lengths(value, &length_context)
+        }
+        // Percentages refer to the element's own font size
+        ("line-height", Value::Length(..) | Value::Calc(_)) => Value::Length(
+            value.to_px(&LengthContext {
+                percentage_basis: style.font_size,
+                ..length_context
+            }),
+            Unit::Px,
+        ),
+        ("color", Value::Keyword(keyword)) if keyword.eq_ignore_ascii_case("currentcolor") => {
+            Value::ColorValue(context.parent_style.color)
+        }
+        (_, Value::Keyword(keyword)) if keyword.eq_ignore_ascii_case("currentcolor") => {
+            Value::ColorValue(style.color)
+        }
+        (_, Value::Keyword(keyword)) if name.starts_with("border-") && name.ends_with("-width") => {
+            match &*keyword.to_ascii_lowercase() {
+                "thin" => Value::Length(1.0, Unit::Px),
+                "thick" => Value::Length(5.0, Unit::Px),
+                _ => Value::Length(3.0, Unit::Px),
+            }
+        }
+        (_, value) => absolute_lengths(value, &length_context),
+    }
+}
+
+/// Convert the lengths in `value` to px, except for percentages, which are resolved during
+/// layout
+fn absolute_lengths(value: &Value, context: &LengthContext) -> Value {
+    match *value {
+        Value::Length(_, Unit::Percent) => value.clone(),
+        Value::Length(..) => Value::Length(value.to_px(context), Unit::Px),
+        Value::Calc(ref node) => match node.resolve_lengths(context) {
+            CalcNode::Length(px, unit) => Value::Length(px, unit),
+            CalcNode::Number(n) => Value::Number(n),
+            node => Value::Calc(Box::new(node)),
+        },
+        Value::List(ref components) => Value::List(
+            components
+                .iter()
+                .map(|component| absolute_lengths(component, context))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+/// Is `name` a custom property, e.g. `--brand-color`?
+fn is_custom_property(name: &str) -> bool {
+    name.starts_with("--")
 }
 
-/// If `rule` matches `elem`, return a `MatchedRule`, Otherwise return `Node`
-fn match_rule<'a>(elem: &ElementData, rule: &'a Rule) -> Option<MatchedRule<'a>> {
-    //Find the first (most specific) matching selector
-    rule.selectors
+/// Compute the custom properties of an element, inheriting those of its parent, and substitute
+/// `var()` functions in its other properties.
+///
+/// A property that refers to a custom property that doesn't exist or is part of a cycle, without
+/// a fallback, is invalid at computed-value time and is removed, as if it hadn't been declared.
+///
+/// ref: https://www.w3.org/TR/css-variables-1/
+fn substitute_variables(values: &mut PropertyMap, parent_values: &PropertyMap) {
+    let mut raw = HashMap::new();
+    for (name, value) in parent_values.iter().chain(values.iter()) {
+        if let Value::Unparsed(unparsed) = value {
+            if is_custom_property(name) {
+                raw.insert(name.clone(), unparsed.css.clone());
+            }
+        }
+    }
+    let mut resolver = VarResolver {
+        raw: &raw,
+        computed: HashMap::new(),
+        resolving: Vec::new(),
+        cyclic: HashSet::new(),
+    };
+
+    for name in raw.keys() {
+        match resolver.resolve(name) {
+            Some(css) => values.insert(
+                name.clone(),
+                Value::Unparsed(UnparsedValue {
+                    css,
+                    shorthand: None,
+                }),
+            ),
+            None => values.remove(name),
+        };
+    }
+
+    let pending: Vec<(String, UnparsedValue)> = values
         .iter()
-        .find(|selector| matches(elem, *selector))
-        .map(|selector| (selector.specificity(), rule))
+        .filter_map(|(name, value)| match value {
+            Value::Unparsed(unparsed) if !is_custom_property(name) => {
+                Some((name.clone(), unparsed.clone()))
+            }
+            _ => None,
+        })
+        .collect();
+    for (name, unparsed) in pending {
+        let value = substitute(&unparsed.css, &mut resolver).and_then(|css| {
+            // Parse the substituted value as a declaration of the property, or of the shorthand
+            // that set it. The parser drops values that don't parse or that the property doesn't
+            // accept, which makes the declaration invalid at computed-value time.
+            let property = unparsed.shorthand.as_deref().unwrap_or(&name);
+            css::parse_declaration_list(format!("{}: {}", property, css))
+                .into_iter()
+                .find(|declaration| declaration.name == name)
+                .map(|declaration| declaration.value)
+        });
+        match value {
+            Some(value) => values.insert(name, value),
+            None => values.remove(&name),
+        };
+    }
+}
+
+/// Resolves references to the custom properties of an element
+struct VarResolver<'a> {
+    /// The values of the custom properties before substitution
+    raw: &'a HashMap<String, String>,
+    /// The substituted values, or `None` for invalid custom properties
+    computed: HashMap<String, Option<String>>,
+    /// The custom properties being resolved, innermost last, to detect cycles
+    resolving: Vec<String>,
+    /// The custom properties that turned out to be part of a cycle
+    cyclic: HashSet<String>,
+}
+
+impl VarResolver<'_> {
+    /// Return the value of custom property `name` with `var()` functions substituted, or `None`
+    /// if it doesn't exist or is invalid
+    fn resolve(&mut self, name: &str) -> Option<String> {
+        if let Some(value) = self.computed.get(name) {
+            return value.clone();
+        }
+        if let Some(start) = self.resolving.iter().position(|n| n == name) {
+            self.cyclic.extend(self.resolving[start..].iter().cloned());
+            return None;
+        }
+        let css = self.raw.get(name)?;
+        self.resolving.push(name.to_string());
+        let value = substitute(css, self);
+        self.resolving.pop();
+
+        // Every property in a cycle is invalid, even if it has a fallback
+        let value = value.filter(|_| !self.cyclic.contains(name));
+        self.computed.insert(name.to_string(), value.clone());
+        value
+    }
+}
+
+/// Replace the `var(<name>, <fallback>?)` functions in `css` by the values of the custom
+/// properties they refer to, or by their fallbacks. Return `None` if a custom property is
+/// invalid and has no fallback.
+fn substitute(css: &str, resolver: &mut VarResolver) -> Option<String> {
+    let mut result = String::new();
+    let mut rest = css;
+    while let Some(start) = rest.to_ascii_lowercase().find("var(") {
+        result.push_str(&rest[..start]);
+        let arguments = &rest[start + "var(".len()..];
+        let end = closing_paren(arguments)?;
+        let (name, fallback) = match arguments[..end].split_once(',') {
+            Some((name, fallback)) => (name.trim(), Some(fallback.trim())),
+            None => (arguments[..end].trim(), None),
+        };
+        let value = match resolver.resolve(name) {
+            Some(value) => value,
+            None => substitute(fallback?, resolver)?,
+        };
+        result.push_str(&value);
+        rest = &arguments[end + 1..];
+    }
+    result.push_str(rest);
+    Some(result)
+}
+
+/// Return the index of the `)` that closes a function whose arguments start `css`
+fn closing_paren(css: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in css.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth == 0 => return Some(i),
+            ')' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// A CSS rule that matched an element
+struct MatchedRule<'a> {
+    /// The rule's most specific selector that matched
+    selector: &'a Selector,
+    specificity: Specificity,
+    origin: Origin,
+    /// The index of the rule across all stylesheets
+    source_order: usize,
+    rule: &'a Rule,
+}
+
+/// A selector of a rule that applies to the device
+struct IndexedSelector<'a> {
+    selector: &'a Selector,
+    /// The position of the selector in its rule
+    selector_index: usize,
+    /// The hashes of the names the ancestors of a matching element must have
+    ancestor_hashes: Vec<u32>,
+    origin: Origin,
+    /// The index of the rule across all stylesheets
+    source_order: usize,
+    rule: &'a Rule,
+}
+
+/// The selectors of all rules whose media queries match the device, in hash tables keyed by the
+/// id, first class or tag name of their rightmost compound selector. An element can only match
+/// the selectors in the buckets of its own id, classes and tag name, and the universal bucket.
+struct RuleIndex<'a> {
+    by_id: HashMap<&'a str, Vec<IndexedSelector<'a>>>,
+    by_class: HashMap<&'a str, Vec<IndexedSelector<'a>>>,
+    by_tag: HashMap<&'a str, Vec<IndexedSelector<'a>>>,
+    universal: Vec<IndexedSelector<'a>>,
+    /// How many elements each rule and selector matched, if we're counting
+    counts: Option<MatchCounts>,
 }
 
-/// Selector matching
-fn matches(elem: &ElementData, selector: &Selector) -> bool {
+/// The number of elements that matched each rule, by source order, and each of its selectors.
+/// Elements are styled in parallel, hence the atomics.
+struct MatchCounts {
+    rules: Vec<AtomicUsize>,
+    selectors: Vec<Vec<AtomicUsize>>,
+}
+
+/// Every rule of `stylesheets`, with its origin and index across all stylesheets
+fn all_rules<'a, 'b>(
+    stylesheets: &'b [(Origin, &'a Stylesheet)],
+) -> impl Iterator<Item = (usize, (Origin, &'a Rule))> + 'b {
+    stylesheets
+        .iter()
+        .flat_map(|&(origin, stylesheet)| stylesheet.rules.iter().map(move |rule| (origin, rule)))
+        .enumerate()
+}
+
+impl<'a> RuleIndex<'a> {
+    /// Index the rules of `stylesheets` that apply to `device`. If `count_matches` is set,
+    /// `matching_rules` counts how many elements each rule and selector matched.
+    fn new(
+        stylesheets: &[(Origin, &'a Stylesheet)],
+        device: &Device,
+        count_matches: bool,
+    ) -> RuleIndex<'a> {
+        let counts = if count_matches {
+            let zeros = |n| (0..n).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
+            Some(MatchCounts {
+                rules: zeros(all_rules(stylesheets).count()),
+                selectors: all_rules(stylesheets)
+                    .map(|(_, (_, rule))| zeros(rule.selectors.len()))
+                    .collect(),
+            })
+        } else {
+            None
+        };
+        let mut index = RuleIndex {
+            by_id: HashMap::new(),
+            by_class: HashMap::new(),
+            by_tag: HashMap::new(),
+            universal: Vec::new(),
+            counts,
+        };
+        let rules = all_rules(stylesheets).filter(|(_, (_, rule))| device.matches_all(&rule.media));
+        for (source_order, (origin, rule)) in rules {
+            for (selector_index, selector) in rule.selectors.iter().enumerate() {
+                let indexed = IndexedSelector {
+                    selector,
+                    selector_index,
+                    ancestor_hashes: bloom::ancestor_hashes(selector),
+                    origin,
+                    source_order,
+                    rule,
+                };
+                let simple = selector.subject();
+                let bucket = if let Some(ref id) = simple.id {
+                    index.by_id.entry(id).or_default()
+                } else if let Some(class) = simple.class.first() {
+                    index.by_class.entry(class).or_default()
+                } else if let Some(ref tag_name) = simple.tag_name {
+                    index.by_tag.entry(tag_name).or_default()
+                } else {
+                    &mut index.universal
+                };
+                bucket.push(indexed);
+            }
+        }
+        index
+    }
+
+    /// The selectors that `elem` might match
+    fn candidates<'b>(
+        &'b self,
+        elem: &'b ElementData,
+    ) -> impl Iterator<Item = &'b IndexedSelector<'a>> {
+        let by_id = elem.id().and_then(|id| self.by_id.get(&**id));
+        let by_class = elem
+            .classes()
+            .into_iter()
+            .filter_map(move |class| self.by_class.get(class));
+        let by_tag = self.by_tag.get(&*elem.tag_name);
+        by_id
+            .into_iter()
+            .chain(by_class)
+            .chain(by_tag)
+            .chain(Some(&self.universal))
+            .flatten()
+    }
+}
+
+/// Find all CSS rules that match the given element
+fn matching_rules<'a>(
+    elem: &ElementData,
+    ancestors: Option<&Ancestor>,
+    filter: &AncestorFilter,
+    rules: &RuleIndex<'a>,
+) -> Vec<MatchedRule<'a>> {
+    let mut matched: Vec<MatchedRule> = rules
+        .candidates(elem)
+        // Reject selectors whose ancestors can't match without walking up the tree
+        .filter(|candidate| {
+            candidate
+                .ancestor_hashes
+                .iter()
+                .all(|&hash| filter.might_contain(hash))
+        })
+        .filter(|candidate| matches(elem, ancestors, candidate.selector))
+        .map(|candidate| {
+            if let Some(ref counts) = rules.counts {
+                counts.selectors[candidate.source_order][candidate.selector_index]
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            MatchedRule {
+                selector: candidate.selector,
+                specificity: candidate.selector.specificity(),
+                origin: candidate.origin,
+                source_order: candidate.source_order,
+                rule: candidate.rule,
+            }
+        })
+        .collect();
+    // Several selectors of a rule can match; the rule applies once, with the specificity of the
+    // most specific one
+    matched.sort_by_key(|m| (m.source_order, std::cmp::Reverse(m.specificity)));
+    matched.dedup_by_key(|m| m.source_order);
+    if let Some(ref counts) = rules.counts {
+        for m in &matched {
+            counts.rules[m.source_order].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    matched
+}
+
+/// Selector matching. `ancestors` starts with the parent of `elem`.
+fn matches(elem: &ElementData, ancestors: Option<&Ancestor>, selector: &Selector) -> bool {
     match *selector {
         Selector::Simple(ref simple_selector) => matches_simple_selector(elem, simple_selector),
+        Selector::Complex(ref left, ref subject) => {
+            matches_simple_selector(elem, subject) && matches_ancestors(left, ancestors)
+        }
     }
 }
 
+/// Do the `ancestors` of an element match the simple selectors on the left of a complex
+/// selector? The last one must match the parent or another ancestor, depending on its
+/// combinator, and the others the ancestors of that one. Backtrack to further ancestors if they
+/// don't.
+fn matches_ancestors(left: &[(SimpleSelector, Combinator)], ancestors: Option<&Ancestor>) -> bool {
+    let ((simple, combinator), rest) = match left.split_last() {
+        Some(last) => last,
+        None => return true,
+    };
+    let mut ancestor = ancestors;
+    while let Some(current) = ancestor {
+        if matches_simple_selector(current.elem, simple) && matches_ancestors(rest, current.parent)
+        {
+            return true;
+        }
+        if *combinator == Combinator::Child {
+            return false;
+        }
+        ancestor = current.parent;
+    }
+    false
+}
+
 // if css's selector doesnt match html's tag, it is unused
 fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> bool {
     //Check type selector
@@ -137,18 +1137,25 @@ fn matches_simple_selector(elem: &ElementData, selector: &SimpleSelector) -> boo
     }
 
     // We didn't find any non-matching selector components
-    return true;
+    true
 }
 
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
 
-    use super::{style_tree, StyledNode};
+    use std::sync::Arc;
+
+    use super::{
+        explain, matches, style_tree, style_tree_parallel, style_tree_with_stats,
+        user_agent_stylesheet, Ancestor, Display, Origin, PropertyMap, StyleSharingStats,
+    };
+    use crate::bloom::{ancestor_hashes, AncestorFilter};
     use crate::css;
-    use crate::css::{Color, Value};
-    use crate::dom::text;
+    use crate::css::{Color, Unit, UnparsedValue, Value};
+    use crate::dom::{elem, text, AttrMap, Node, NodeType};
     use crate::html;
+    use crate::media::Device;
 
     #[test]
     fn test_style_tree_overwrite() {
@@ -178,16 +1185,656 @@ mod tests {
                 a: 255,
             }),
         );
-        let text = text(String::from("Hello"));
-        let expected = StyledNode {
-            node: &root,
-            specified_values,
-            children: vec![StyledNode {
-                node: &text,
-                specified_values: HashMap::new(),
-                children: vec![],
-            }],
-        };
-        assert_eq!(expected, style_tree(&root, &css));
+        let styled = style_tree(&root, &[(Origin::Author, &css)], &Device::default());
+        assert_eq!(specified_values, styled.specified_values);
+        assert_eq!(&text(String::from("Hello")), styled.children[0].node);
+        assert!(styled.children[0].specified_values.is_empty());
+        assert!(styled.children[0].children.is_empty());
+    }
+
+    #[test]
+    fn test_clamp_math_functions() {
+        let root = html::parse(String::from("<p></p>"));
+        let css = css::parse(String::from(
+            "p { width: calc(-10px); padding-left: calc(10px - 20%); font-size: calc(-1em); \
+             line-height: calc(1 - 2); border-spacing: calc(-1px) 2px; margin-left: calc(-5px) }",
+        ));
+        let p = style_tree(&root, &[(Origin::Author, &css)], &Device::default());
+        let px = |n| Some(Value::Length(n, Unit::Px));
+        // Math functions are valid even if out of range, and are clamped once computed
+        assert_eq!(px(0.0), p.value("width"));
+        assert_eq!(px(0.0), p.value("font-size"));
+        assert_eq!(Some(Value::Number(0.0)), p.value("line-height"));
+        assert_eq!(
+            Some(Value::List(vec![
+                Value::Length(0.0, Unit::Px),
+                Value::Length(2.0, Unit::Px)
+            ])),
+            p.value("border-spacing")
+        );
+        assert_eq!(px(-5.0), p.value("margin-left"));
+        // Percentages aren't known until layout
+        assert!(matches!(p.value("padding-left"), Some(Value::Calc(_))));
+    }
+
+    #[test]
+    fn test_font_weight_range() {
+        let root = html::parse(String::from("<p></p>"));
+        let weight = |css: &str| {
+            let css = css::parse(format!("p {{ {} }}", css));
+            style_tree(&root, &[(Origin::Author, &css)], &Device::default()).value("font-weight")
+        };
+        // Out of range weights are invalid, so the earlier declaration wins
+        for invalid in ["1001", "0", "-5"] {
+            assert_eq!(
+                Some(Value::Number(700.0)),
+                weight(&format!("font-weight: 700; font-weight: {}", invalid)),
+                "{}",
+                invalid
+            );
+        }
+        assert_eq!(Some(Value::Number(1.0)), weight("font-weight: 1"));
+        // Math functions are clamped instead
+        assert_eq!(
+            Some(Value::Number(1000.0)),
+            weight("font-weight: calc(2000)")
+        );
+    }
+
+    #[test]
+    fn test_computed_values() {
+        let root = html::parse(String::from(
+            r#"<div class="a"><p class="b">Hello</p><p class="c"></p></div>"#,
+        ));
+        let css = css::parse(String::from(
+            "
+            div { color: #111111; font-size: 20px; margin-left: 2em; width: 50%; }
+            p { border-left-style: solid; border-top-width: 4px; border-right-width: thick; }
+            .b { font-size: 1.5em; margin-top: calc(1em + 1rem); border-right-style: dotted; }
+            .c { color: #222222; margin-left: inherit; width: inherit; font-size: larger; }
+            ",
+        ));
+        let div = style_tree(&root, &[(Origin::Author, &css)], &Device::default());
+        let px = |n| Some(Value::Length(n, Unit::Px));
+        assert_eq!(gray(0x11), div.value("color"));
+        assert_eq!(px(40.0), div.value("margin-left"));
+        assert_eq!(Some(Value::Length(50.0, Unit::Percent)), div.value("width"));
+        assert_eq!(
+            Some(Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 0
+            })),
+            div.value("background-color")
+        );
+
+        let (b, c) = (&div.children[0], &div.children[1]);
+        // Inherited properties are inherited, others take their initial values
+        assert_eq!(gray(0x11), b.value("color"));
+        assert_eq!(px(0.0), b.value("margin-left"));
+        assert_eq!(Some(Value::Keyword("auto".to_string())), b.value("width"));
+        // Relative lengths are resolved, with `rem` relative to the root `div`
+        assert_eq!(px(30.0), b.value("font-size"));
+        assert_eq!(px(50.0), b.value("margin-top"));
+        // Border widths depend on the border style, and border colors default to `color`
+        assert_eq!(px(3.0), b.value("border-left-width"));
+        assert_eq!(px(0.0), b.value("border-top-width"));
+        assert_eq!(px(5.0), b.value("border-right-width"));
+        assert_eq!(gray(0x11), b.value("border-left-color"));
+        assert_eq!(gray(0x22), c.value("border-left-color"));
+        // `inherit` works for properties that aren't inherited by default
+        assert_eq!(px(40.0), c.value("margin-left"));
+        assert_eq!(Some(Value::Length(50.0, Unit::Percent)), c.value("width"));
+        assert_eq!(px(24.0), c.value("font-size"));
+
+        // Text nodes inherit from their parent
+        assert_eq!(gray(0x11), b.children[0].value("color"));
+        assert_eq!(px(30.0), b.children[0].value("font-size"));
+    }
+
+    #[test]
+    fn test_css_wide_keywords() {
+        let ua = "p { color: #111111; } #x { color: #222222; }";
+        let user = "p { color: #333333; }";
+        let sheets = |author: &'static str| {
+            [
+                (Origin::UserAgent, ua),
+                (Origin::User, user),
+                (Origin::Author, author),
+            ]
+        };
+        assert_eq!(gray(0x33), color_of(&sheets("p { color: revert; }")));
+        assert_eq!(
+            gray(0x22),
+            color_of(&[
+                (Origin::UserAgent, ua),
+                (Origin::Author, "p { color: revert; }")
+            ])
+        );
+        // Reverting the user-agent origin leaves the property unset, which inherits `color`
+        assert_eq!(
+            Some(Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255
+            })),
+            color_of(&[(Origin::UserAgent, "p { color: revert; }")])
+        );
+        assert_eq!(
+            gray(0x33),
+            color_of(&sheets("p { color: #444444; color: revert; }"))
+        );
+        assert_eq!(
+            Some(Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255
+            })),
+            color_of(&sheets("p { color: initial; }"))
+        );
+        assert_eq!(
+            color_of(&sheets("p { color: unset; }")),
+            color_of(&sheets("p { color: INHERIT; }"))
+        );
+    }
+
+    /// Style a single `<p class="name" id="x">` element and return its specified `color`
+    fn color_of(stylesheets: &[(Origin, &str)]) -> Option<Value> {
+        inline_color_of("", stylesheets)
+    }
+
+    fn gray(level: u8) -> Option<Value> {
+        Some(Value::ColorValue(Color {
+            r: level,
+            g: level,
+            b: level,
+            a: 255,
+        }))
+    }
+
+    #[test]
+    fn test_cascade_source_order() {
+        // With equal specificity, the later rule wins, whichever way the rules are written.
+        let css = "p { color: #111111; } p { color: #222222; }";
+        assert_eq!(gray(0x22), color_of(&[(Origin::Author, css)]));
+        let css = "p { color: #222222; color: #111111; }";
+        assert_eq!(gray(0x11), color_of(&[(Origin::Author, css)]));
+        assert_eq!(
+            gray(0x22),
+            color_of(&[
+                (Origin::Author, "p { color: #111111; }"),
+                (Origin::Author, "p { color: #222222; }"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_rule_index() {
+        // The rule matches through `#x`, so it has that selector's specificity
+        let css = "p.name, #x { color: #111111; } .name { color: #222222; }";
+        assert_eq!(gray(0x11), color_of(&[(Origin::Author, css)]));
+        let css = "* { color: #333333; } .other.name, div { color: #222222; }";
+        assert_eq!(gray(0x33), color_of(&[(Origin::Author, css)]));
+        let css = "#y, .other { color: #222222; } p { color: #111111; }";
+        assert_eq!(gray(0x11), color_of(&[(Origin::Author, css)]));
+    }
+
+    #[test]
+    fn test_style_sharing() {
+        let root = html::parse(String::from(
+            r#"<div><ul><li class="a">1</li><li class="a">2</li><li class="a" id="x">3</li></ul>
+            <ul><li class="a">4</li><li class="a" style="color: #222222">5</li></ul></div>"#,
+        ));
+        let css = css::parse(String::from(
+            ".a { color: #111111; } #x { margin-left: 1px; }",
+        ));
+        let (div, stats) =
+            style_tree_with_stats(&root, &[(Origin::Author, &css)], &Device::default(), false);
+        let (first, second) = (&div.children[0], &div.children[1]);
+        let (li1, li2, li3) = (&first.children[0], &first.children[1], &first.children[2]);
+        // Siblings and cousins with the same tag and attributes share a style
+        assert!(Arc::ptr_eq(&first.style, &second.style));
+        assert!(Arc::ptr_eq(&li1.style, &li2.style));
+        assert!(Arc::ptr_eq(&li1.style, &second.children[0].style));
+        assert!(Arc::ptr_eq(&li1.children[0].style, &li2.children[0].style));
+        // Elements with an id or other attributes don't
+        assert!(!Arc::ptr_eq(&li1.style, &li3.style));
+        assert_eq!(gray(0x11), li3.value("color"));
+        assert_eq!(gray(0x22), second.children[1].value("color"));
+        assert_eq!(StyleSharingStats { hits: 5, misses: 8 }, stats);
+    }
+
+    /// Generate a tree of `depth` levels of elements with `width` children each, with a mix of
+    /// classes, ids, inline styles and text
+    fn generate_dom(depth: usize, width: usize, seed: &mut usize) -> Node {
+        *seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+        let mut attributes = AttrMap::new();
+        attributes.insert(
+            "class".to_string(),
+            format!("c{} d{}", *seed % 7, *seed % 3),
+        );
+        if seed.is_multiple_of(11) {
+            attributes.insert("id".to_string(), format!("i{}", *seed % 5));
+        }
+        if seed.is_multiple_of(13) {
+            attributes.insert(
+                "style".to_string(),
+                "margin-left: 1em; --v: 2px".to_string(),
+            );
+        }
+        let children = if depth == 0 {
+            vec![text(format!("t{}", *seed))]
+        } else {
+            (0..width)
+                .map(|_| generate_dom(depth - 1, width, seed))
+                .collect()
+        };
+        let tag = ["div", "p", "span", "li"][*seed % 4];
+        elem(tag.to_string(), attributes, children)
+    }
+
+    #[test]
+    fn test_parallel_style_tree() {
+        let root = generate_dom(5, 6, &mut 1);
+        let css = css::parse(String::from(
+            "
+            .c1 { color: #111111; font-size: 1.2em; --w: 10%; }
+            .c2.d1 { width: var(--w, 50%); border-left-style: solid; }
+            #i3 { color: inherit; margin-left: calc(1em + var(--v, 3px)); }
+            p { padding-top: 1rem; } span.d2 { display: block; }
+            li { background-color: currentcolor; color: #333333; }
+            .c3 > .d1 span, div .c4 { color: #444444; margin-top: 1em; }
+            ",
+        ));
+        let ua = user_agent_stylesheet();
+        let stylesheets = [(Origin::UserAgent, &ua), (Origin::Author, &css)];
+        let device = Device::default();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()
+            .unwrap();
+        assert_eq!(
+            style_tree(&root, &stylesheets, &device),
+            pool.install(|| style_tree_parallel(&root, &stylesheets, &device))
+        );
+    }
+
+    #[test]
+    fn test_combinators() {
+        let root = html::parse(String::from(
+            r#"<div class="a"><ul><li class="b"><p>1</p></li></ul><p>2</p></div>"#,
+        ));
+        let css = css::parse(String::from(
+            "
+            .a p { color: #111111; }
+            .a > p { color: #222222; }
+            ul .b > p { margin-left: 1px; }
+            div > li p, .b > .a p { margin-left: 2px; }
+            ul > p { color: #333333; }
+            ",
+        ));
+        let div = style_tree(&root, &[(Origin::Author, &css)], &Device::default());
+        let p1 = &div.children[0].children[0].children[0];
+        let p2 = &div.children[1];
+        assert_eq!(gray(0x11), p1.value("color"));
+        assert_eq!(Some(Value::Length(1.0, Unit::Px)), p1.value("margin-left"));
+        assert_eq!(gray(0x22), p2.value("color"));
+        assert_eq!(Some(Value::Length(0.0, Unit::Px)), p2.value("margin-left"));
+
+        // Cousins with different ancestors don't share styles
+        let root = html::parse(String::from(
+            "<div><ul><li><p></p></li></ul><ol><li><p></p></li></ol></div>",
+        ));
+        let css = css::parse(String::from("ul p { color: #111111; }"));
+        let div = style_tree(&root, &[(Origin::Author, &css)], &Device::default());
+        let p_in_ul = &div.children[0].children[0].children[0];
+        let p_in_ol = &div.children[1].children[0].children[0];
+        assert_eq!(gray(0x11), p_in_ul.value("color"));
+        assert_eq!(gray(0x00), p_in_ol.value("color"));
+    }
+
+    /// Check that the ancestor filter never rejects a selector that matches, for every element
+    /// of `node` and its descendants. Return how many non-matching selectors it rejected.
+    fn check_ancestor_filter(
+        node: &Node,
+        ancestors: Option<&Ancestor>,
+        filter: &AncestorFilter,
+        selectors: &[css::Selector],
+    ) -> usize {
+        let elem = match node.node_type {
+            NodeType::Element(ref elem) => elem,
+            NodeType::Text(_) => return 0,
+        };
+        let mut rejected = 0;
+        for selector in selectors {
+            let might_match = ancestor_hashes(selector)
+                .iter()
+                .all(|&hash| filter.might_contain(hash));
+            if matches(elem, ancestors, selector) {
+                assert!(might_match, "{:?} rejected", selector);
+            } else if !might_match {
+                rejected += 1;
+            }
+        }
+        let mut child_filter = *filter;
+        child_filter.insert_element(elem);
+        let ancestor = Ancestor {
+            elem,
+            parent: ancestors,
+        };
+        rejected
+            + node
+                .children
+                .iter()
+                .map(|child| {
+                    check_ancestor_filter(child, Some(&ancestor), &child_filter, selectors)
+                })
+                .sum::<usize>()
+    }
+
+    #[test]
+    fn test_ancestor_filter_matches_plain_matching() {
+        let root = generate_dom(4, 5, &mut 7);
+        let stylesheet = css::parse(String::from(
+            "
+            div p, .c1 .c2, .c3 > .d1, #i2 span, li > li > *, div#i4 .c5.d2 p,
+            .c6 .nothing, ul li, * > * > .c4, div p span li { }
+            ",
+        ));
+        let selectors = &stylesheet.rules[0].selectors;
+        let rejected = check_ancestor_filter(&root, None, &AncestorFilter::default(), selectors);
+        assert!(rejected > 0);
+    }
+
+    #[test]
+    fn test_cascade_important() {
+        let css = "p { color: #111111 !important; } #x { color: #222222; }";
+        assert_eq!(gray(0x11), color_of(&[(Origin::Author, css)]));
+        let css = "p { color: #111111 !important; } #x { color: #222222 ! IMPORTANT; }";
+        assert_eq!(gray(0x22), color_of(&[(Origin::Author, css)]));
+    }
+
+    #[test]
+    fn test_cascade_origins() {
+        // Normal declarations: author beats user beats user agent, regardless of specificity.
+        assert_eq!(
+            gray(0x33),
+            color_of(&[
+                (Origin::Author, "p { color: #333333; }"),
+                (Origin::User, "#x { color: #222222; }"),
+                (Origin::UserAgent, "#x.name { color: #111111; }"),
+            ])
+        );
+        // Important declarations: the order of the origins is reversed.
+        assert_eq!(
+            gray(0x11),
+            color_of(&[
+                (Origin::UserAgent, "p { color: #111111 !important; }"),
+                (Origin::User, "p { color: #222222 !important; }"),
+                (Origin::Author, "#x { color: #333333 !important; }"),
+            ])
+        );
+        assert_eq!(
+            gray(0x22),
+            color_of(&[
+                (Origin::User, "p { color: #222222 !important; }"),
+                (Origin::Author, "#x { color: #333333 !important; }"),
+            ])
+        );
+    }
+
+    /// Style a `<p class="name" id="x">` element with a `style` attribute, and return its
+    /// specified `color`
+    fn inline_color_of(style: &str, stylesheets: &[(Origin, &str)]) -> Option<Value> {
+        let root = html::parse(format!(r#"<p class="name" id="x" style="{}"></p>"#, style));
+        let stylesheets: Vec<_> = stylesheets
+            .iter()
+            .map(|&(origin, source)| (origin, css::parse(String::from(source))))
+            .collect();
+        let stylesheets: Vec<_> = stylesheets
+            .iter()
+            .map(|(origin, stylesheet)| (*origin, stylesheet))
+            .collect();
+        style_tree(&root, &stylesheets, &Device::default()).value("color")
+    }
+
+    #[test]
+    fn test_inline_styles() {
+        let css = "#x.name { color: #111111; }";
+        assert_eq!(
+            gray(0x22),
+            inline_color_of("color: #222222", &[(Origin::Author, css)])
+        );
+        assert_eq!(
+            gray(0x33),
+            inline_color_of("color: #222222; color: #333333;", &[(Origin::Author, css)])
+        );
+        // `!important` in a stylesheet beats normal inline declarations
+        let css = "p { color: #111111 !important; }";
+        assert_eq!(
+            gray(0x11),
+            inline_color_of("color: #222222", &[(Origin::Author, css)])
+        );
+        assert_eq!(
+            gray(0x22),
+            inline_color_of("color: #222222 !important", &[(Origin::Author, css)])
+        );
+        // Inline styles belong to the author origin
+        assert_eq!(
+            gray(0x11),
+            inline_color_of(
+                "color: #222222 !important",
+                &[(Origin::User, "p { color: #111111 !important; }")]
+            )
+        );
+        assert_eq!(
+            Some(Value::ColorValue(Color {
+                r: 0,
+                g: 0,
+                b: 0,
+                a: 255
+            })),
+            inline_color_of("", &[])
+        );
+    }
+
+    #[test]
+    fn test_user_agent_stylesheet() {
+        let root = html::parse(String::from(
+            "<html><head><title>x</title></head><body><h1>a</h1><span>b</span></body></html>",
+        ));
+        let ua = user_agent_stylesheet();
+        let html = style_tree(&root, &[(Origin::UserAgent, &ua)], &Device::default());
+        let (head, body) = (&html.children[0], &html.children[1]);
+        assert!(html.display() == Display::BLOCK);
+        assert!(head.display() == Display::None);
+        assert!(body.display() == Display::BLOCK);
+        assert_eq!(
+            Some(Value::Length(8.0, Unit::Px)),
+            body.value("margin-left")
+        );
+        let (h1, span) = (&body.children[0], &body.children[1]);
+        assert!(h1.display() == Display::BLOCK);
+        assert_eq!(Some(Value::Length(32.0, Unit::Px)), h1.value("font-size"));
+        assert!(span.display() == Display::INLINE);
+
+        // Author styles override the user-agent stylesheet
+        let author = css::parse(String::from("h1 { display: inline; }"));
+        let html = style_tree(
+            &root,
+            &[(Origin::UserAgent, &ua), (Origin::Author, &author)],
+            &Device::default(),
+        );
+        assert!(html.children[1].children[0].display() == Display::INLINE);
+    }
+
+    #[test]
+    fn test_media_rules() {
+        let css = "
+            p { color: #111111; }
+            @media screen and (min-width: 600px) { p { color: #222222; } }
+            @media print { p { color: #333333; } }
+        ";
+        assert_eq!(gray(0x22), color_of(&[(Origin::Author, css)]));
+        let css = "
+            p { color: #111111; }
+            @media (max-width: 600px) { p { color: #222222; } }
+        ";
+        assert_eq!(gray(0x11), color_of(&[(Origin::Author, css)]));
+    }
+
+    /// Style `<div><p class="name" id="x"></p></div>` and return the specified values of the
+    /// `div` and the `p`
+    fn parent_and_child(css: &str) -> (PropertyMap, PropertyMap) {
+        let root = html::parse(String::from(r#"<div><p class="name" id="x"></p></div>"#));
+        let css = css::parse(String::from(css));
+        let mut div = style_tree(&root, &[(Origin::Author, &css)], &Device::default());
+        let p = div.children.remove(0);
+        (div.specified_values, p.specified_values)
+    }
+
+    #[test]
+    fn test_custom_properties() {
+        let css = "
+            div { --brand: #222222; --Spaced :  1px  solid ; }
+            p { color: var(--brand); }
+        ";
+        let (div, p) = parent_and_child(css);
+        assert_eq!(
+            Some(Value::Unparsed(UnparsedValue {
+                css: "1px  solid".to_string(),
+                shorthand: None,
+            })),
+            div.get("--Spaced").cloned()
+        );
+        assert_eq!(None, div.get("color").cloned());
+        // Custom properties are inherited
+        assert_eq!(div.get("--brand").cloned(), p.get("--brand").cloned());
+        assert_eq!(gray(0x22), p.get("color").cloned());
+
+        let (_, p) = parent_and_child("div { --a: #111111; } p { --a: #333333; color: var(--a); }");
+        assert_eq!(gray(0x33), p.get("color").cloned());
+    }
+
+    #[test]
+    fn test_var_fallbacks() {
+        let (_, p) = parent_and_child("p { color: var(--missing, #111111); }");
+        assert_eq!(gray(0x11), p.get("color").cloned());
+        let (_, p) = parent_and_child(
+            "p { --a: var(--b, var(--c, #222222)); --c: #333333; color: var( --a ); }",
+        );
+        assert_eq!(gray(0x33), p.get("color").cloned());
+        // Without a fallback the declaration is invalid at computed-value time
+        let (_, p) = parent_and_child("p { color: #111111; color: var(--missing); }");
+        assert_eq!(None, p.get("color").cloned());
+        // So is a substituted value that the property doesn't accept, or that doesn't parse
+        let (_, p) = parent_and_child("p { --w: 10deg; width: 1px; width: var(--w) }");
+        assert_eq!(None, p.get("width").cloned());
+        let (_, p) = parent_and_child("p { --w: foo(1); color: #111111; color: var(--w) }");
+        assert_eq!(None, p.get("color").cloned());
+    }
+
+    #[test]
+    fn test_var_cycles() {
+        let css = "
+            p {
+                --a: var(--b, #111111);
+                --b: var(--a, #111111);
+                --c: var(--a, #222222);
+                --self: var(--self);
+                color: var(--c);
+            }
+        ";
+        let (_, p) = parent_and_child(css);
+        assert_eq!(None, p.get("--a").cloned());
+        assert_eq!(None, p.get("--b").cloned());
+        assert_eq!(None, p.get("--self").cloned());
+        assert_eq!(gray(0x22), p.get("color").cloned());
+    }
+
+    #[test]
+    fn test_var_in_shorthands() {
+        let css = "
+            div { --space: 1px 2px; }
+            p { margin: var(--space) 3px; margin-left: 4px; border: var(--width) solid; }
+        ";
+        let (_, p) = parent_and_child(css);
+        let px = |n| Some(Value::Length(n, Unit::Px));
+        assert_eq!(px(1.0), p.get("margin-top").cloned());
+        assert_eq!(px(2.0), p.get("margin-right").cloned());
+        assert_eq!(px(3.0), p.get("margin-bottom").cloned());
+        assert_eq!(px(4.0), p.get("margin-left").cloned());
+        assert_eq!(None, p.get("border-top-style").cloned());
+    }
+
+    #[test]
+    fn test_explain() {
+        let root = html::parse(String::from(
+            r#"<div><p class="a" style="margin-top: 1px"></p><p></p></div>"#,
+        ));
+        let user_agent = css::parse(String::from("p { color: #111111; margin-top: 2px; }"));
+        let author = css::parse(String::from(
+            "div > .a { color: #222222; }\n\
+             p { color: #333333; color: revert; margin-top: 3px; }",
+        ));
+        let stylesheets = [(Origin::UserAgent, &user_agent), (Origin::Author, &author)];
+        let selectors = css::parse_selector_list(String::from("div .a"));
+        let explanations = explain(&root, &selectors, &stylesheets, &Device::default());
+        assert_eq!(1, explanations.len());
+        assert_eq!(
+            Some("a"),
+            explanations[0]
+                .element
+                .attributes
+                .get("class")
+                .map(|s| &s[..])
+        );
+
+        let properties = &explanations[0].properties;
+        let names: Vec<_> = properties.iter().map(|(name, _)| &name[..]).collect();
+        assert_eq!(vec!["color", "margin-top"], names);
+
+        // `div > .a` wins: `p` is less specific, and its other declaration reverts
+        let color: Vec<_> = properties[0]
+            .1
+            .iter()
+            .map(|entry| {
+                (
+                    entry.value.clone(),
+                    entry.won,
+                    entry.specificity,
+                    entry.origin,
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                (gray(0x22).unwrap(), true, (0, 1, 1), Origin::Author),
+                (
+                    Value::Keyword("revert".to_string()),
+                    false,
+                    (0, 0, 1),
+                    Origin::Author
+                ),
+                (gray(0x33).unwrap(), false, (0, 0, 1), Origin::Author),
+                (gray(0x11).unwrap(), false, (0, 0, 1), Origin::UserAgent),
+            ],
+            color
+        );
+        let location = properties[0].1[0].location.unwrap();
+        assert_eq!((1, 1), (location.line, location.column));
+        let location = properties[0].1[2].location.unwrap();
+        assert_eq!((2, 1), (location.line, location.column));
+
+        // The `style` attribute beats the rules
+        let margin_top = &properties[1].1;
+        assert_eq!(3, margin_top.len());
+        assert!(margin_top[0].won && margin_top[0].selector.is_none());
+        assert!(margin_top[0].location.is_none());
+        assert_eq!(Value::Length(1.0, Unit::Px), margin_top[0].value);
+        assert!(!margin_top[1].won && !margin_top[2].won);
     }
 }